@@ -0,0 +1,108 @@
+//! Benchmarks for the library code behind the main `show`, `verify` and `modify` paths.
+//!
+//! Input file is taken from the `ELF_EDITOR_BENCH_INPUT` environment variable.  When not set, the
+//! benchmark binary itself is used, as a reasonably large ELF file with debug info.
+
+use std::{env, fs::File, hint::black_box, io, path::PathBuf};
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use goblin::{container::Ctx, elf::Elf};
+use memmap2::Mmap;
+
+use elf_editor::{
+    document::ElfDocument,
+    inspect::file_regions,
+    structure::{find_structure_violations, UnknownBytes},
+    transformer::{
+        combinators::{map_bytes, only_section_named, KeepAsIs},
+        transform_elf_sections, LayoutOptions,
+    },
+};
+
+fn input_bytes() -> Mmap {
+    let input_path = env::var_os("ELF_EDITOR_BENCH_INPUT")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| env::current_exe().expect("Benchmark binary path is known"));
+    let input_file = File::open(&input_path).expect("Benchmark input can be opened");
+    // SAFETY: Benchmark input is not modified while the benchmark runs.
+    unsafe { Mmap::map(&input_file) }.expect("Benchmark input can be memory mapped")
+}
+
+fn parse(input_bytes: &[u8]) -> (Elf<'_>, Ctx) {
+    let elf = Elf::parse(input_bytes).expect("Benchmark input is an ELF");
+    let ctx = Ctx {
+        container: elf
+            .header
+            .container()
+            .expect("Input ELF header has a valid size"),
+        le: elf
+            .header
+            .endianness()
+            .expect("Input ELF header has a valid endianness"),
+    };
+    (elf, ctx)
+}
+
+fn inspect(c: &mut Criterion) {
+    let input_bytes = input_bytes();
+
+    c.bench_function("inspect/file_regions", |b| {
+        b.iter(|| {
+            let (elf, ctx) = parse(black_box(&input_bytes));
+            file_regions(&elf.header, &elf.section_headers, ctx)
+        })
+    });
+}
+
+fn verify(c: &mut Criterion) {
+    let input_bytes = input_bytes();
+
+    c.bench_function("verify", |b| {
+        b.iter(|| {
+            let (elf, ctx) = parse(black_box(&input_bytes));
+            find_structure_violations(&input_bytes, &elf, ctx, UnknownBytes::Preserve)
+        })
+    });
+}
+
+fn modify(c: &mut Criterion) {
+    let input_bytes = input_bytes();
+    let (elf, ctx) = parse(&input_bytes);
+    let options = LayoutOptions {
+        unknown_bytes: UnknownBytes::Preserve,
+        ..LayoutOptions::default()
+    };
+
+    c.bench_function("modify/copy", |b| {
+        b.iter(|| {
+            transform_elf_sections(&input_bytes, &elf, ctx, io::sink(), options, KeepAsIs)
+                .expect("Benchmark input can be copied")
+        })
+    });
+
+    c.bench_function("modify/document", |b| {
+        b.iter(|| {
+            ElfDocument::new(&input_bytes, &elf, ctx)
+                .write(io::sink(), options)
+                .expect("Benchmark input can be copied")
+        })
+    });
+
+    if only_section_named(&elf, ".comment", KeepAsIs).is_some() {
+        c.bench_function("modify/grow_comment", |b| {
+            b.iter(|| {
+                let transformer = only_section_named(
+                    &elf,
+                    ".comment",
+                    map_bytes(|bytes: &[u8]| [bytes, b"elf-editor\0"].concat()),
+                )
+                .expect("Input has a .comment section");
+                transform_elf_sections(&input_bytes, &elf, ctx, io::sink(), options, transformer)
+                    .expect("Benchmark input .comment section can grow")
+            })
+        });
+    }
+}
+
+criterion_group!(benches, inspect, verify, modify);
+criterion_main!(benches);
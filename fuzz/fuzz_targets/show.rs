@@ -0,0 +1,49 @@
+//! Inspection helpers used by the `show` command.
+
+#![no_main]
+
+use goblin::{
+    container::Ctx,
+    elf::{
+        section_header::{SHT_DYNSYM, SHT_SYMTAB},
+        Elf,
+    },
+};
+use libfuzzer_sys::fuzz_target;
+
+use elf_editor::{
+    image::LoadImage,
+    inspect::{
+        file_regions, find_current_entrypoint, section_symbols, symbol_version, symbol_versions,
+    },
+};
+
+fuzz_target!(|bytes: &[u8]| {
+    let Ok(elf) = Elf::parse(bytes) else {
+        return;
+    };
+    let (Ok(container), Ok(le)) = (elf.header.container(), elf.header.endianness()) else {
+        return;
+    };
+    let ctx = Ctx { container, le };
+
+    let _ = format!("{elf:#?}");
+    let _ = elf.dynstrtab.to_vec();
+    let _ = elf.shdr_strtab.to_vec();
+    let _ = file_regions(&elf.header, &elf.section_headers, ctx);
+    let _ = find_current_entrypoint(&elf);
+    let _ = LoadImage::new(bytes, &elf);
+
+    let versions = symbol_versions(bytes, &elf);
+    for header in &elf.section_headers {
+        if header.sh_type != SHT_SYMTAB && header.sh_type != SHT_DYNSYM {
+            continue;
+        }
+        let Ok(symbols) = section_symbols(bytes, &elf, ctx, header) else {
+            continue;
+        };
+        for symbol in symbols {
+            let _ = symbol_version(&elf, &versions, symbol.index);
+        }
+    }
+});
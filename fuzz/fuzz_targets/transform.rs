@@ -0,0 +1,41 @@
+//! No-op transformation of the inputs that pass the structure verification, with every layout
+//! strategy.  Layout problems must be reported as errors, not panics.
+
+#![no_main]
+
+use goblin::{container::Ctx, elf::Elf};
+use libfuzzer_sys::fuzz_target;
+
+use elf_editor::{
+    structure::{verify_elf_structure, UnknownBytes},
+    transformer::{combinators::KeepAsIs, transform_elf_sections, LayoutOptions, LayoutStrategy},
+};
+
+fuzz_target!(|bytes: &[u8]| {
+    let Ok(elf) = Elf::parse(bytes) else {
+        return;
+    };
+    let (Ok(container), Ok(le)) = (elf.header.container(), elf.header.endianness()) else {
+        return;
+    };
+    let ctx = Ctx { container, le };
+
+    for unknown_bytes in [UnknownBytes::Reject, UnknownBytes::Preserve] {
+        if verify_elf_structure(bytes, &elf, ctx, unknown_bytes).is_err() {
+            continue;
+        }
+
+        for strategy in [
+            LayoutStrategy::Pack,
+            LayoutStrategy::PreserveGaps,
+            LayoutStrategy::AppendToEnd,
+        ] {
+            let options = LayoutOptions {
+                strategy,
+                unknown_bytes,
+            };
+            let mut output = vec![];
+            let _ = transform_elf_sections(bytes, &elf, ctx, &mut output, options, KeepAsIs);
+        }
+    }
+});
@@ -0,0 +1,22 @@
+//! Structure verification, that is run on every input before it is modified.
+
+#![no_main]
+
+use goblin::{container::Ctx, elf::Elf};
+use libfuzzer_sys::fuzz_target;
+
+use elf_editor::structure::{find_structure_violations, UnknownBytes};
+
+fuzz_target!(|bytes: &[u8]| {
+    let Ok(elf) = Elf::parse(bytes) else {
+        return;
+    };
+    let (Ok(container), Ok(le)) = (elf.header.container(), elf.header.endianness()) else {
+        return;
+    };
+    let ctx = Ctx { container, le };
+
+    for unknown_bytes in [UnknownBytes::Reject, UnknownBytes::Preserve] {
+        let _ = find_structure_violations(bytes, &elf, ctx, unknown_bytes);
+    }
+});
@@ -1,18 +1,28 @@
 use std::path::PathBuf;
 
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 
-pub mod show;
+use elf_editor::structure;
+
+pub mod create;
+pub mod diff;
+pub mod extract;
 pub mod modify;
+pub mod show;
+pub mod verify;
 
 /// Editor for ELF files.
 #[derive(Parser, Debug)]
 #[command(version, about)]
 pub struct Args {
     #[arg(long, value_name = "INPUT")]
-    /// Input ELF file to process.
+    /// Input file to process.  An ELF file for all the commands, except for `create`.
     pub input: PathBuf,
 
+    #[arg(long, value_enum, default_value_t)]
+    /// Output format of the `show` and `diff` commands.
+    pub format: OutputFormat,
+
     #[command(subcommand)]
     pub command: Command,
 }
@@ -26,4 +36,52 @@ pub enum Command {
 
     /// Modify the input file
     Modify(modify::ModifyArgs),
+
+    /// Check the input file structure, reporting all the problems found.
+    ///
+    /// Exits with a non-zero code if the structure is not supported.
+    Verify(verify::VerifyArgs),
+
+    #[command(subcommand)]
+    /// Write parts of the input file into separate files.
+    Extract(extract::ExtractArgs),
+
+    /// Create a new ELF file from the input file, that is not an ELF file.
+    Create(create::CreateArgs),
+
+    /// Compare the structure of the input file with another ELF file.
+    ///
+    /// Exits with a non-zero code if any differences are found.
+    Diff(diff::DiffArgs),
+}
+
+/// How to present the command results.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum OutputFormat {
+    /// Human readable text.
+    #[default]
+    Text,
+    /// A single JSON object.
+    Json,
+}
+
+/// How to treat bytes that are not covered by any known structure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum UnknownBytes {
+    /// Only zero bytes are allowed outside of the known structures.  They are considered padding
+    /// and are not preserved when the file is modified.
+    #[default]
+    Reject,
+    /// Ranges that contain non-zero bytes are kept as opaque blobs, attached to the preceding
+    /// structure, and are copied into the modified file unchanged.
+    Preserve,
+}
+
+impl From<UnknownBytes> for structure::UnknownBytes {
+    fn from(value: UnknownBytes) -> Self {
+        match value {
+            UnknownBytes::Reject => Self::Reject,
+            UnknownBytes::Preserve => Self::Preserve,
+        }
+    }
 }
@@ -0,0 +1,25 @@
+use std::path::PathBuf;
+
+use clap::{Args, Subcommand};
+
+pub mod from_binary;
+
+#[derive(Args, Debug)]
+#[command(name = "create")]
+pub struct CreateArgs {
+    #[arg(long, value_name = "OUTPUT")]
+    /// Output ELF file to generate.
+    pub output: PathBuf,
+
+    #[command(subcommand)]
+    pub command: CreateCommand,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum CreateCommand {
+    /// Wrap the input file content into a loadable ELF file.
+    ///
+    /// Input is not expected to be an ELF file.  It is placed into a single section, loaded by a
+    /// PT_LOAD segment at the `--base` address.
+    FromBinary(from_binary::FromBinaryArgs),
+}
@@ -0,0 +1,80 @@
+use clap::{Args, ValueEnum};
+
+#[derive(Args, Debug)]
+pub struct FromBinaryArgs {
+    #[arg(long, value_enum)]
+    /// Target architecture.  Selects the ELF class and the byte order.
+    pub machine: Machine,
+
+    #[arg(long, value_parser = parse_u64, default_value = "0x1000")]
+    /// Address the input content is loaded at.  Must be a multiple of 8.
+    pub base: u64,
+
+    #[arg(long, value_enum, default_value_t)]
+    /// Section to place the input content into.
+    pub section: ContentSection,
+
+    #[arg(long, value_parser = parse_u64, default_value = "0")]
+    /// Offset of the entry point from the start of the input content.
+    pub entry_offset: u64,
+
+    #[arg(long, value_name = "NAME")]
+    /// Add a global symbol with this name into .dynsym, pointing to the entry point.
+    pub entry_symbol: Option<String>,
+
+    #[arg(long = "symbol", value_name = "NAME=OFFSET", value_parser = parse_symbol)]
+    /// Add a global symbol into .dynsym, at the specified offset from the start of the
+    /// input content.  Can be repeated.
+    ///
+    /// Symbols are functions in `.text`, and objects in `.rodata`.
+    pub symbols: Vec<(String, u64)>,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Machine {
+    /// eBPF and Solana sBPF, ELF64, little-endian.
+    Bpf,
+    /// ELF64, little-endian.
+    X86_64,
+    /// ELF64, little-endian.
+    Aarch64,
+    /// ELF64, little-endian.
+    Riscv64,
+    /// ELF32, little-endian.
+    I386,
+    /// ELF32, little-endian.
+    Arm,
+    /// ELF32, big-endian.
+    Powerpc,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ContentSection {
+    /// Executable `.text` section.
+    #[default]
+    Text,
+    /// Read only `.rodata` section.
+    Rodata,
+}
+
+/// Parses a decimal, or a `0x` prefixed hexadecimal number.
+fn parse_u64(value: &str) -> Result<u64, String> {
+    let res = match value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+    {
+        Some(hex) => u64::from_str_radix(&hex.replace('_', ""), 16),
+        None => value.replace('_', "").parse(),
+    };
+    res.map_err(|err| format!("Expected a decimal or a 0x prefixed hexadecimal number: {err}"))
+}
+
+fn parse_symbol(value: &str) -> Result<(String, u64), String> {
+    let (name, offset) = value
+        .split_once('=')
+        .ok_or_else(|| "Expected NAME=OFFSET".to_owned())?;
+    if name.is_empty() {
+        return Err("Symbol name can not be empty".to_owned());
+    }
+    Ok((name.to_owned(), parse_u64(offset)?))
+}
@@ -0,0 +1,12 @@
+use std::path::PathBuf;
+
+use clap::Args;
+
+#[derive(Args, Debug)]
+#[command(name = "diff")]
+pub struct DiffArgs {
+    #[arg(value_name = "OTHER")]
+    /// ELF file to compare the input with.  Differences are reported as changes from the input
+    /// file to this file.
+    pub other: PathBuf,
+}
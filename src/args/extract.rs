@@ -0,0 +1,86 @@
+use std::path::PathBuf;
+
+use clap::{Args, Subcommand, ValueEnum};
+
+use elf_editor::image;
+
+#[derive(Subcommand, Debug)]
+#[command(name = "extract")]
+pub enum ExtractArgs {
+    /// Write the file content of a section into a file.
+    Section(SectionArgs),
+
+    /// Write the file content of a program section into a file.
+    Segment(SegmentArgs),
+
+    /// Write the memory image of all the PT_LOAD program sections into a file.
+    ///
+    /// Program sections are placed at their physical addresses.  Only the bytes present in the
+    /// input file are included.
+    Image(ImageArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct SectionArgs {
+    /// Name of the section to extract.
+    pub name: String,
+
+    #[arg(short, long, value_name = "OUTPUT")]
+    /// Output file to write.
+    pub output: PathBuf,
+}
+
+#[derive(Args, Debug)]
+pub struct SegmentArgs {
+    /// Index of the program header, as shown by `show program-sections`.
+    pub index: usize,
+
+    #[arg(short, long, value_name = "OUTPUT")]
+    /// Output file to write.
+    pub output: PathBuf,
+}
+
+#[derive(Args, Debug)]
+pub struct ImageArgs {
+    #[arg(long, value_enum, default_value_t)]
+    /// Output file format.
+    pub format: ImageFormat,
+
+    #[arg(short, long, value_name = "OUTPUT")]
+    /// Output file to write.
+    pub output: PathBuf,
+}
+
+/// Output format of the `image` command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum ImageFormat {
+    /// Raw memory content, starting at the lowest loaded address.  Gaps between the segments are
+    /// filled with zeros.  Similar to `objcopy -O binary`, except that whole segments are included,
+    /// not only the sections inside them.
+    #[default]
+    Binary,
+    /// Intel HEX text records.  Addresses are limited to 32 bits.
+    Ihex,
+    /// Motorola S-record text records.  Addresses are limited to 32 bits.
+    Srec,
+}
+
+impl From<ImageFormat> for image::ImageFormat {
+    fn from(value: ImageFormat) -> Self {
+        match value {
+            ImageFormat::Binary => Self::Binary,
+            ImageFormat::Ihex => Self::Ihex,
+            ImageFormat::Srec => Self::Srec,
+        }
+    }
+}
+
+impl ExtractArgs {
+    pub fn output(&self) -> &PathBuf {
+        match self {
+            Self::Section(args) => &args.output,
+            Self::Segment(args) => &args.output,
+            Self::Image(args) => &args.output,
+        }
+    }
+}
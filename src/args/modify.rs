@@ -1,6 +1,10 @@
 use std::path::PathBuf;
 
-use clap::{Args, Subcommand};
+use clap::{Args, Subcommand, ValueEnum};
+
+use elf_editor::transformer;
+
+use super::UnknownBytes;
 
 pub mod dyn_sym;
 
@@ -11,6 +15,19 @@ pub struct ModifyArgs {
     /// Output ELF file to generate.
     pub output: PathBuf,
 
+    #[arg(long, value_enum, default_value_t)]
+    /// How to place sections and header tables in the output file.
+    ///
+    /// `pack` and `preserve-gaps` keep the section addresses, so they can only resize the last
+    /// section of a `PT_LOAD` program section.  Edits that resize other loaded sections, like
+    /// `dyn-sym add` and `dyn-sym remove` do with `.dynsym` in most files, fail with these
+    /// layouts, and need `append-to-end`.
+    pub layout: LayoutStrategy,
+
+    #[arg(long, value_enum, default_value_t)]
+    /// How to treat bytes that are not covered by any known structure.
+    pub unknown_bytes: UnknownBytes,
+
     #[command(subcommand)]
     pub command: ModifyCommand,
 }
@@ -21,3 +38,31 @@ pub enum ModifyCommand {
     /// Modify the .dynsym section, holding the loader dynamic symbols.
     DynSym(dyn_sym::DynSymArgs),
 }
+
+/// How the structures are placed in the output file, after some of the sections change size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum LayoutStrategy {
+    /// Each structure is placed right after the previous one, aligned according to its alignment
+    /// requirements.  Any padding between the structures is removed.
+    #[default]
+    Pack,
+    /// Structures stay at their original offsets, unless a preceding structure moved or grew past
+    /// them.  A structure is moved by the same amount as the structure before it, or further, if
+    /// that one grew into it.  So a gap after a structure that grew shrinks, or disappears.  Output
+    /// differs minimally from the input.
+    PreserveGaps,
+    /// Sections that grow are moved to the end of the file, into a new `PT_LOAD` program section
+    /// with a fresh virtual address range.  All the other structures keep their offsets and
+    /// addresses.  Dynamic section entries that point to the moved sections are updated.
+    AppendToEnd,
+}
+
+impl From<LayoutStrategy> for transformer::LayoutStrategy {
+    fn from(value: LayoutStrategy) -> Self {
+        match value {
+            LayoutStrategy::Pack => Self::Pack,
+            LayoutStrategy::PreserveGaps => Self::PreserveGaps,
+            LayoutStrategy::AppendToEnd => Self::AppendToEnd,
+        }
+    }
+}
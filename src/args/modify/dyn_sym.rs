@@ -10,5 +10,8 @@ pub enum DynSymArgs {
     Add(add::AddArgs),
 
     /// Remove an entry from the .dynsym table.
+    ///
+    /// Symbols that relocations refer to, and local symbols, can not be removed.  The symbol name
+    /// stays in the .dynstr table.
     Remove(remove::RemoveArgs),
 }
@@ -1,4 +1,8 @@
-use clap::Subcommand;
+use clap::{Args, Subcommand, ValueEnum};
+use goblin::elf::{
+    section_header::{self, *},
+    sym,
+};
 
 #[derive(Subcommand, Debug)]
 #[command(name = "show")]
@@ -9,14 +13,30 @@ pub enum ShowArgs {
     /// Overview of the file layout.
     Layout,
 
-    /// Show the program sections.
+    /// Show the program sections, and the sections each of them holds.
+    ///
+    /// Program sections that hold only a part of some section, or do not hold any sections, are
+    /// marked.
     ProgramSections,
 
     /// Show the file segments.
     FileSegments,
 
-    /// Show the .dynsym table and the .dynstr string table content.
-    DynSym,
+    /// Show the section header table, similar to `readelf -S`.
+    ///
+    /// Includes the indices of the program sections that hold each section.
+    Sections(SectionsArgs),
+
+    /// Show the .dynsym symbol table, similar to `readelf --dyn-syms`.
+    ///
+    /// Versioned symbols have the version appended to their names: `@@` marks the default version
+    /// of a defined symbol, and `@` any other version.
+    DynSym(SymbolsArgs),
+
+    /// Show the .symtab symbol table, similar to `readelf --syms`.
+    ///
+    /// The static symbol table is not used by the loader, and is usually removed by `strip`.
+    Symtab(SymtabArgs),
 
     /// Show the .shstrtab string table content.
     ShStrTab,
@@ -30,3 +50,134 @@ pub enum ShowArgs {
     /// Used by the Solana VM loader.
     Entrypoint,
 }
+
+#[derive(Args, Debug)]
+pub struct SectionsArgs {
+    #[arg(long, value_name = "GLOB")]
+    /// Only show sections with names matching this pattern.  `*` matches any number of characters,
+    /// and `?` matches a single character.
+    pub name: Option<String>,
+
+    #[arg(long = "type", value_name = "TYPE", value_parser = parse_section_type)]
+    /// Only show sections of this type.  For example, `PROGBITS` or `SHT_NOBITS`.  Case
+    /// insensitive.
+    pub section_type: Option<u32>,
+}
+
+#[derive(Args, Debug)]
+pub struct SymbolsArgs {
+    #[arg(long, value_name = "GLOB")]
+    /// Only show symbols with names matching this pattern.  `*` matches any number of characters,
+    /// and `?` matches a single character.  Versions are not part of the matched name.
+    pub name: Option<String>,
+
+    #[arg(long = "type", value_name = "TYPE", value_parser = parse_symbol_type)]
+    /// Only show symbols of this type.  For example, `FUNC` or `STT_OBJECT`.  Case insensitive.
+    pub symbol_type: Option<u8>,
+
+    #[arg(long, conflicts_with = "undefined")]
+    /// Only show symbols defined in this file.
+    pub defined: bool,
+
+    #[arg(long)]
+    /// Only show undefined symbols, that are expected to be provided by other files.
+    pub undefined: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct SymtabArgs {
+    #[command(flatten)]
+    pub symbols: SymbolsArgs,
+
+    #[arg(long, value_name = "ORDER")]
+    /// Sort the symbols.  Symbols are shown in the symbol table order by default.
+    pub sort: Option<SymbolOrder>,
+
+    #[arg(long)]
+    /// Demangle Rust, legacy and v0, and C++ symbol names.  `--name` then matches the demangled
+    /// names.
+    pub demangle: bool,
+}
+
+/// Order of the symbols in the symbol table views.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolOrder {
+    /// By `st_value`, lowest first.
+    Address,
+    /// By `st_size`, largest first.
+    Size,
+}
+
+/// Section types `--type` accepts, the ones `show sections` has names for.  Range markers, like
+/// `SHT_LOOS`, are not included.
+const SECTION_TYPES: [u32; 28] = [
+    SHT_NULL,
+    SHT_PROGBITS,
+    SHT_SYMTAB,
+    SHT_STRTAB,
+    SHT_RELA,
+    SHT_HASH,
+    SHT_DYNAMIC,
+    SHT_NOTE,
+    SHT_NOBITS,
+    SHT_REL,
+    SHT_SHLIB,
+    SHT_DYNSYM,
+    SHT_INIT_ARRAY,
+    SHT_FINI_ARRAY,
+    SHT_PREINIT_ARRAY,
+    SHT_GROUP,
+    SHT_SYMTAB_SHNDX,
+    SHT_GNU_ATTRIBUTES,
+    SHT_GNU_HASH,
+    SHT_GNU_LIBLIST,
+    SHT_CHECKSUM,
+    SHT_SUNW_MOVE,
+    SHT_SUNW_COMDAT,
+    SHT_SUNW_SYMINFO,
+    SHT_GNU_VERDEF,
+    SHT_GNU_VERNEED,
+    SHT_GNU_VERSYM,
+    SHT_X86_64_UNWIND,
+];
+
+/// Parses a section type name, with or without the `SHT_` prefix, into the `sh_type` value.
+fn parse_section_type(value: &str) -> Result<u32, String> {
+    let value = value.to_ascii_uppercase();
+    let name = value.strip_prefix("SHT_").unwrap_or(&value);
+    SECTION_TYPES
+        .into_iter()
+        .find(|&sh_type| section_header::sht_to_str(sh_type).strip_prefix("SHT_") == Some(name))
+        .ok_or_else(|| {
+            let names: Vec<_> = SECTION_TYPES
+                .iter()
+                .map(|&sh_type| section_header::sht_to_str(sh_type).trim_start_matches("SHT_"))
+                .collect();
+            format!(
+                "Unknown section type.  Expected one of: {}",
+                names.join(", ")
+            )
+        })
+}
+
+/// Parses a symbol type name, with or without the `STT_` prefix, into the `st_type` value.
+fn parse_symbol_type(value: &str) -> Result<u8, String> {
+    // `st_type` is the low 4 bits of `st_info`.
+    const SYMBOL_TYPES: std::ops::Range<u8> = 0..0x10;
+
+    let value = value.to_ascii_uppercase();
+    let name = value.strip_prefix("STT_").unwrap_or(&value);
+    SYMBOL_TYPES
+        .into_iter()
+        .find(|&st_type| sym::type_to_str(st_type) == name)
+        .ok_or_else(|| {
+            let names: Vec<_> = SYMBOL_TYPES
+                .map(sym::type_to_str)
+                .filter(|name| *name != "UNKNOWN_STT")
+                .collect();
+            format!(
+                "Unknown symbol type.  Expected one of: {}",
+                names.join(", ")
+            )
+        })
+}
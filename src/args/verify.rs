@@ -0,0 +1,11 @@
+use clap::Args;
+
+use super::UnknownBytes;
+
+#[derive(Args, Debug)]
+#[command(name = "verify")]
+pub struct VerifyArgs {
+    #[arg(long, value_enum, default_value_t)]
+    /// How to treat bytes that are not covered by any known structure.
+    pub unknown_bytes: UnknownBytes,
+}
@@ -0,0 +1,1051 @@
+//! Construction of ELF files from scratch.
+//!
+//! [`ElfBuilder`] takes sections and segments in the order they should appear in the file, and
+//! computes all the offsets, addresses and sizes.  Allocated sections are placed at
+//! `base_address + file offset`, so `PT_LOAD` segments are always congruent with their offsets.
+//! A section can be given a fixed address, with [`ElfBuilder::set_address()`], in which case it is
+//! moved in the file to stay congruent, and the following sections are placed after it in memory.
+//! A `PT_LOAD` with `p_flags` different from the preceding one starts on a new page in memory, so
+//! that no page is mapped with two sets of permissions.
+//!
+//! ```
+//! use elf_editor::builder::{ElfBuilder, Segment, Symbol};
+//! use goblin::{
+//!     container::{Container, Ctx},
+//!     elf::{header::{EM_BPF, ET_DYN}, program_header::{PF_R, PF_X, PT_LOAD}, section_header::*, sym::*},
+//! };
+//! use scroll::Endian;
+//!
+//! let mut builder = ElfBuilder::new(Ctx::new(Container::Big, Endian::Little), ET_DYN, EM_BPF);
+//! let text = builder.add_section(".text", SHT_PROGBITS, u64::from(SHF_ALLOC | SHF_EXECINSTR), 8, vec![0; 16]);
+//! let dynstr = builder.add_strings(".dynstr", u64::from(SHF_ALLOC));
+//! builder.add_symbols(".dynsym", SHT_DYNSYM, u64::from(SHF_ALLOC), dynstr, vec![Symbol {
+//!     name: "entrypoint".to_owned(),
+//!     st_info: (STB_GLOBAL << 4) | STT_FUNC,
+//!     st_other: 0,
+//!     section: text,
+//!     offset: 0,
+//!     st_size: 16,
+//! }]);
+//! builder.add_segment(Segment::sections(PT_LOAD, PF_R | PF_X, 0x1000, text, dynstr + 1));
+//! let bytes = builder.build().expect("ELF is valid");
+//! ```
+
+use std::{collections::HashMap, io, ops::RangeInclusive};
+
+use goblin::{
+    container::Ctx,
+    elf::{
+        dynamic::{Dyn, DT_NULL},
+        program_header::PT_LOAD,
+        section_header::{
+            SHF_ALLOC, SHF_WRITE, SHN_LORESERVE, SHT_DYNAMIC, SHT_HASH, SHT_NOBITS, SHT_NULL,
+            SHT_STRTAB,
+        },
+        sym::STB_LOCAL,
+        Header, ProgramHeader, SectionHeader, Sym,
+    },
+};
+use scroll::{ctx::SizeWith as _, IOwrite as _};
+
+use crate::{
+    document::{encode_entries, hash::sysv_hash_table, StringTable},
+    transformer::{add_padding, align_offset, header_table_alignment, iowrite_from_scroll},
+};
+
+/// Memory size of `SHT_NOBITS` sections is rounded up to this value, so that the following
+/// sections keep their addresses congruent with their file offsets.
+const PAGE_SIZE: u64 = 0x1000;
+
+/// Assembles an ELF file, from sections and segments.
+pub struct ElfBuilder {
+    ctx: Ctx,
+    e_type: u16,
+    e_machine: u16,
+    e_flags: u32,
+    /// Section index and offset of the entry point.
+    entry: Option<(usize, u64)>,
+    base_address: u64,
+    /// Section 0 is the `SHT_NULL` section.
+    sections: Vec<BuilderSection>,
+    segments: Vec<Segment>,
+}
+
+struct BuilderSection {
+    name: String,
+    header: SectionHeader,
+    /// Address set with [`ElfBuilder::set_address()`].
+    address: Option<u64>,
+    content: Content,
+}
+
+enum Content {
+    Bytes(Vec<u8>),
+    NoBits {
+        size: u64,
+    },
+    Strings(StringTable),
+    Symbols(Vec<Symbol>),
+    Dynamic(Vec<(u64, DynamicValue)>),
+    /// `SHT_HASH` table for the symbol table with the specified index.
+    SysvHash {
+        symbols: usize,
+    },
+}
+
+/// Symbol added with [`ElfBuilder::add_symbols()`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    pub st_info: u8,
+    pub st_other: u8,
+    /// Index of the section that holds the symbol, as returned by [`ElfBuilder::add_section()`], or
+    /// a reserved index, like `SHN_UNDEF` or `SHN_ABS`.
+    pub section: usize,
+    /// Offset of the symbol from the start of the `section`.  Becomes the symbol address if the
+    /// section is allocated.  Used as `st_value` as is for the reserved section indices.
+    pub offset: u64,
+    pub st_size: u64,
+}
+
+/// Value of a dynamic entry added with [`ElfBuilder::add_dynamic()`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DynamicValue {
+    Value(u64),
+    /// Address of the section with the specified index.
+    SectionAddress(usize),
+    /// Size of the section with the specified index.
+    SectionSize(usize),
+    /// Offset of a string, that is added to the string table linked to the dynamic section.
+    String(String),
+}
+
+/// Program header added with [`ElfBuilder::add_segment()`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    pub p_type: u32,
+    pub p_flags: u32,
+    pub p_align: u64,
+    pub covers: SegmentCovers,
+}
+
+/// Part of the file a [`Segment`] covers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SegmentCovers {
+    /// Program headers table, for `PT_PHDR`.
+    ProgramHeaders,
+    /// Range of sections, by their indices.  If `with_headers` is set, the segment starts at the
+    /// start of the file, covering the ELF header and the program headers table.
+    Sections {
+        sections: RangeInclusive<usize>,
+        with_headers: bool,
+    },
+}
+
+impl Segment {
+    /// Segment that covers sections from `first` to `last`, inclusive.
+    pub fn sections(p_type: u32, p_flags: u32, p_align: u64, first: usize, last: usize) -> Self {
+        Self {
+            p_type,
+            p_flags,
+            p_align,
+            covers: SegmentCovers::Sections {
+                sections: first..=last,
+                with_headers: false,
+            },
+        }
+    }
+}
+
+/// Position of a section in the output file.
+#[derive(Clone, Copy, Default)]
+struct Placement {
+    offset: u64,
+    addr: u64,
+    file_size: u64,
+    memory_size: u64,
+}
+
+impl ElfBuilder {
+    /// `ctx` selects ELF32 or ELF64 and the byte order.  `e_type` and `e_machine` are copied into
+    /// the ELF header as is.
+    pub fn new(ctx: Ctx, e_type: u16, e_machine: u16) -> Self {
+        Self {
+            ctx,
+            e_type,
+            e_machine,
+            e_flags: 0,
+            entry: None,
+            base_address: 0,
+            sections: vec![BuilderSection {
+                name: String::new(),
+                header: SectionHeader::default(),
+                address: None,
+                content: Content::Bytes(vec![]),
+            }],
+            segments: vec![],
+        }
+    }
+
+    pub fn flags(&mut self, e_flags: u32) -> &mut Self {
+        self.e_flags = e_flags;
+        self
+    }
+
+    /// Address of the first byte of the file.  Should be a multiple of the `PT_LOAD` alignment.
+    pub fn base_address(&mut self, base_address: u64) -> &mut Self {
+        self.base_address = base_address;
+        self
+    }
+
+    /// Entry point, at the specified `offset` inside the `section`.
+    pub fn entry(&mut self, section: usize, offset: u64) -> &mut Self {
+        self.entry = Some((section, offset));
+        self
+    }
+
+    /// Adds a section with the specified content.  Returns the section index.
+    pub fn add_section(
+        &mut self,
+        name: &str,
+        sh_type: u32,
+        sh_flags: u64,
+        sh_addralign: u64,
+        content: Vec<u8>,
+    ) -> usize {
+        self.push_section(
+            name,
+            sh_type,
+            sh_flags,
+            sh_addralign,
+            Content::Bytes(content),
+        )
+    }
+
+    /// Adds an `SHT_NOBITS` section, that occupies no space in the file.  Returns the section
+    /// index.
+    ///
+    /// Memory size of the section is rounded up to a page, so that sections that follow it stay
+    /// congruent.
+    pub fn add_nobits(&mut self, name: &str, sh_flags: u64, sh_addralign: u64, size: u64) -> usize {
+        self.push_section(
+            name,
+            SHT_NOBITS,
+            sh_flags,
+            sh_addralign,
+            Content::NoBits { size },
+        )
+    }
+
+    /// Adds an empty `SHT_STRTAB` section.  Symbol names and string dynamic values are added into
+    /// it by [`ElfBuilder::add_symbols()`] and [`ElfBuilder::add_dynamic()`].  Returns the section
+    /// index.
+    pub fn add_strings(&mut self, name: &str, sh_flags: u64) -> usize {
+        self.push_section(
+            name,
+            SHT_STRTAB,
+            sh_flags,
+            1,
+            Content::Strings(StringTable::default()),
+        )
+    }
+
+    /// Adds a symbol table, `SHT_SYMTAB` or `SHT_DYNSYM`, with names in the `strings` table.  The
+    /// null symbol is added automatically.  Local symbols must go first.  Returns the section
+    /// index.
+    pub fn add_symbols(
+        &mut self,
+        name: &str,
+        sh_type: u32,
+        sh_flags: u64,
+        strings: usize,
+        symbols: Vec<Symbol>,
+    ) -> usize {
+        let container = self.ctx.container;
+        let index = self.push_section(
+            name,
+            sh_type,
+            sh_flags,
+            header_table_alignment(self.ctx),
+            Content::Symbols(symbols),
+        );
+
+        let header = &mut self.sections[index].header;
+        header.sh_link = strings as u32;
+        header.sh_entsize = Sym::size(container) as u64;
+        index
+    }
+
+    /// Adds an `SHT_DYNAMIC` section, with the `DT_NULL` terminator added automatically.  String
+    /// values are added into the `strings` table.  Returns the section index.
+    pub fn add_dynamic(&mut self, strings: usize, entries: Vec<(u64, DynamicValue)>) -> usize {
+        let index = self.push_section(
+            ".dynamic",
+            SHT_DYNAMIC,
+            u64::from(SHF_ALLOC | SHF_WRITE),
+            header_table_alignment(self.ctx),
+            Content::Dynamic(entries),
+        );
+
+        let header = &mut self.sections[index].header;
+        header.sh_link = strings as u32;
+        header.sh_entsize = Dyn::size_with(&self.ctx) as u64;
+        index
+    }
+
+    /// Adds an `SHT_HASH` section for the `symbols` table, as returned by
+    /// [`ElfBuilder::add_symbols()`].  Returns the section index.
+    pub fn add_hash(&mut self, symbols: usize) -> usize {
+        let index = self.push_section(
+            ".hash",
+            SHT_HASH,
+            u64::from(SHF_ALLOC),
+            4,
+            Content::SysvHash { symbols },
+        );
+
+        let header = &mut self.sections[index].header;
+        header.sh_link = symbols as u32;
+        header.sh_entsize = 4;
+        index
+    }
+
+    /// Sets `sh_link`, `sh_info` and `sh_entsize` of a section.
+    pub fn set_links(&mut self, section: usize, sh_link: u32, sh_info: u32, sh_entsize: u64) {
+        let header = &mut self.sections[section].header;
+        header.sh_link = sh_link;
+        header.sh_info = sh_info;
+        header.sh_entsize = sh_entsize;
+    }
+
+    /// Places an allocated section at the specified address.  Sections that follow it are placed
+    /// after it in memory.  Address should be a multiple of the section alignment.
+    pub fn set_address(&mut self, section: usize, address: u64) -> &mut Self {
+        self.sections[section].address = Some(address);
+        self
+    }
+
+    pub fn add_segment(&mut self, segment: Segment) -> &mut Self {
+        self.segments.push(segment);
+        self
+    }
+
+    fn push_section(
+        &mut self,
+        name: &str,
+        sh_type: u32,
+        sh_flags: u64,
+        sh_addralign: u64,
+        content: Content,
+    ) -> usize {
+        self.sections.push(BuilderSection {
+            name: name.to_owned(),
+            header: SectionHeader {
+                sh_type,
+                sh_flags,
+                sh_addralign,
+                ..SectionHeader::default()
+            },
+            address: None,
+            content,
+        });
+        self.sections.len() - 1
+    }
+
+    /// `p_flags` of the `PT_LOAD` segment that starts with the section at `index`, if any.
+    fn load_flags_starting_at(&self, index: usize) -> Option<u32> {
+        self.segments
+            .iter()
+            .find_map(|segment| match &segment.covers {
+                SegmentCovers::Sections {
+                    sections,
+                    with_headers: false,
+                } if segment.p_type == PT_LOAD && *sections.start() == index => {
+                    Some(segment.p_flags)
+                }
+                _ => None,
+            })
+    }
+
+    /// Names of all the symbols in the `symbols` table, including the null symbol.
+    fn symbol_names(&self, symbols: usize) -> Result<Vec<&str>, String> {
+        match self.sections.get(symbols).map(|section| &section.content) {
+            Some(Content::Symbols(symbols)) => Ok(std::iter::once("")
+                .chain(symbols.iter().map(|symbol| symbol.name.as_str()))
+                .collect()),
+            _ => Err(format!("Section {symbols} is not a symbol table.")),
+        }
+    }
+
+    /// Produces the ELF file bytes.
+    pub fn build(&self) -> Result<Vec<u8>, String> {
+        let mut res = vec![];
+        self.write(&mut res)?;
+        Ok(res)
+    }
+
+    /// Writes the ELF file into the `output`.
+    ///
+    /// A `.shstrtab` section is added after all the other sections.  It is followed by the section
+    /// headers table.
+    pub fn write<Output>(&self, mut output: Output) -> Result<(), String>
+    where
+        Output: io::Write,
+    {
+        let ctx = self.ctx;
+
+        // Strings are added to the tables while the content is encoded, so all the tables are
+        // copied first.
+        let mut strings = self
+            .sections
+            .iter()
+            .enumerate()
+            .filter_map(|(index, section)| match &section.content {
+                Content::Strings(table) => Some((index, table.clone())),
+                _ => None,
+            })
+            .collect::<HashMap<_, _>>();
+        let mut string_offset = |section: &BuilderSection, value: &str| {
+            let table = strings
+                .get_mut(&(section.header.sh_link as usize))
+                .ok_or_else(|| {
+                    format!(
+                        "{} section sh_link does not point to a string table.",
+                        section.name
+                    )
+                })?;
+            Ok::<_, String>(table.add(value))
+        };
+
+        // Names first, so that sizes of all the string tables are known before the layout.
+        let mut symbol_names = HashMap::new();
+        let mut dynamic_strings = HashMap::new();
+        for (index, section) in self.sections.iter().enumerate() {
+            match &section.content {
+                Content::Symbols(symbols) => {
+                    let offsets = symbols
+                        .iter()
+                        .map(|symbol| string_offset(section, &symbol.name))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    symbol_names.insert(index, offsets);
+                }
+                Content::Dynamic(entries) => {
+                    for (entry, (_, value)) in entries.iter().enumerate() {
+                        if let DynamicValue::String(value) = value {
+                            dynamic_strings.insert((index, entry), string_offset(section, value)?);
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        let mut shstrtab = StringTable::default();
+        let sh_names = self
+            .sections
+            .iter()
+            .map(|section| match section.header.sh_type {
+                SHT_NULL => 0,
+                _ => shstrtab.add(&section.name),
+            })
+            .collect::<Vec<_>>();
+        let shstrtab_name = shstrtab.add(".shstrtab");
+
+        // Content sizes.
+        let sizes = self
+            .sections
+            .iter()
+            .enumerate()
+            .map(|(index, section)| match &section.content {
+                Content::Bytes(bytes) => Ok(bytes.len() as u64),
+                Content::NoBits { size } => Ok(*size),
+                Content::Strings(_) => Ok(strings[&index].len() as u64),
+                Content::Symbols(symbols) => {
+                    Ok(((symbols.len() + 1) * Sym::size(ctx.container)) as u64)
+                }
+                Content::Dynamic(entries) => {
+                    Ok(((entries.len() + 1) * Dyn::size_with(&ctx)) as u64)
+                }
+                Content::SysvHash { symbols } => {
+                    // Same number of buckets and chain entries.
+                    let names = self.symbol_names(*symbols)?;
+                    Ok((2 + 2 * names.len() as u64) * 4)
+                }
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        // Layout.
+        let table_alignment = header_table_alignment(ctx);
+        let phnum = self.segments.len();
+        let ph_size = ProgramHeader::size_with(&ctx) as u64;
+        let mut offset = Header::size_with(&ctx) as u64;
+        let phoff = if phnum == 0 {
+            0
+        } else {
+            let phoff = align_offset(offset, table_alignment);
+            offset = phoff + phnum as u64 * ph_size;
+            phoff
+        };
+
+        // Address of the file offset 0, for the allocated sections.  Wraps around, when a section
+        // is placed at an address that is below its file offset.
+        let mut address_bias = self.base_address;
+        // Flags of the `PT_LOAD` that holds the last placed section, and the end of the memory
+        // used so far.
+        let mut load_flags = self
+            .segments
+            .iter()
+            .find_map(|segment| match segment.covers {
+                SegmentCovers::Sections {
+                    with_headers: true, ..
+                } if segment.p_type == PT_LOAD => Some(segment.p_flags),
+                _ => None,
+            });
+        let mut memory_end = load_flags.map(|_| self.base_address.wrapping_add(offset));
+        let mut placements = vec![Placement::default()];
+        for (index, (section, size)) in self.sections.iter().zip(&sizes).enumerate().skip(1) {
+            let mut offset_start = align_offset(offset, section.header.sh_addralign);
+            if let Some(address) = section.address {
+                offset_start += address.wrapping_sub(offset_start) % PAGE_SIZE;
+                address_bias = address.wrapping_sub(offset_start);
+            }
+            if let Some(p_flags) = self.load_flags_starting_at(index) {
+                // Loaders map whole pages, with the permissions of the `PT_LOAD`.  A page shared by
+                // two `PT_LOAD`s with different permissions would get the permissions of the one
+                // mapped last.
+                let new_permissions = load_flags.is_some_and(|load_flags| load_flags != p_flags);
+                if let (true, None, Some(memory_end)) =
+                    (new_permissions, section.address, memory_end)
+                {
+                    let addr = offset_start.wrapping_add(address_bias);
+                    let page_start = addr - addr % PAGE_SIZE;
+                    if memory_end > page_start {
+                        address_bias = address_bias
+                            .wrapping_add(align_offset(memory_end, PAGE_SIZE) - page_start);
+                    }
+                }
+                load_flags = Some(p_flags);
+            }
+
+            let is_alloc = section.header.sh_flags & u64::from(SHF_ALLOC) != 0;
+            let addr = if is_alloc {
+                offset_start.wrapping_add(address_bias)
+            } else {
+                0
+            };
+
+            let is_nobits = section.header.sh_type == SHT_NOBITS;
+            let memory_size = if is_alloc { *size } else { 0 };
+            placements.push(Placement {
+                offset: offset_start,
+                addr,
+                file_size: if is_nobits { 0 } else { *size },
+                memory_size,
+            });
+            if is_alloc {
+                let end = addr.wrapping_add(memory_size);
+                memory_end = Some(memory_end.map_or(end, |memory_end| memory_end.max(end)));
+            }
+
+            if is_nobits {
+                if is_alloc {
+                    address_bias = address_bias.wrapping_add(align_offset(*size, PAGE_SIZE));
+                }
+            } else {
+                offset = offset_start + size;
+            }
+        }
+
+        let shstrtab_offset = offset;
+        offset += shstrtab.len() as u64;
+        let shoff = align_offset(offset, table_alignment);
+        let shstrndx = self.sections.len();
+
+        let address_of = |index: usize| -> Result<u64, String> {
+            placements
+                .get(index)
+                .map(|placement| placement.addr)
+                .ok_or_else(|| format!("Section index {index} is out of range."))
+        };
+
+        // Section headers.
+        let mut section_headers = self
+            .sections
+            .iter()
+            .zip(&placements)
+            .zip(sizes.iter().zip(&sh_names))
+            .map(|((section, placement), (size, sh_name))| {
+                if section.header.sh_type == SHT_NULL {
+                    return SectionHeader::default();
+                }
+
+                let sh_info = match &section.content {
+                    Content::Symbols(symbols) => {
+                        1 + symbols
+                            .iter()
+                            .take_while(|symbol| symbol.st_info >> 4 == STB_LOCAL)
+                            .count() as u32
+                    }
+                    _ => section.header.sh_info,
+                };
+
+                SectionHeader {
+                    sh_name: *sh_name,
+                    sh_addr: placement.addr,
+                    sh_offset: placement.offset,
+                    sh_size: *size,
+                    sh_info,
+                    ..section.header.clone()
+                }
+            })
+            .collect::<Vec<_>>();
+        section_headers.push(SectionHeader {
+            sh_name: shstrtab_name,
+            sh_type: SHT_STRTAB,
+            sh_offset: shstrtab_offset,
+            sh_size: shstrtab.len() as u64,
+            sh_addralign: 1,
+            ..SectionHeader::default()
+        });
+
+        // Program headers.
+        let program_headers = self
+            .segments
+            .iter()
+            .map(|segment| {
+                let (p_offset, p_vaddr, file_end, memory_end) = match &segment.covers {
+                    SegmentCovers::ProgramHeaders => {
+                        let end = phoff + phnum as u64 * ph_size;
+                        let vaddr = self.base_address + phoff;
+                        (phoff, vaddr, end, vaddr + (end - phoff))
+                    }
+                    SegmentCovers::Sections {
+                        sections,
+                        with_headers,
+                    } => {
+                        let covered = placements.get(sections.clone()).ok_or_else(|| {
+                            format!("Segment sections {sections:?} are out of range.")
+                        })?;
+                        let first = covered
+                            .first()
+                            .ok_or_else(|| "Segment covers no sections.".to_owned())?;
+                        let (start, vaddr) = if *with_headers {
+                            (0, self.base_address)
+                        } else {
+                            (first.offset, first.addr)
+                        };
+                        let file_end = covered
+                            .iter()
+                            .map(|placement| placement.offset + placement.file_size)
+                            .fold(start, u64::max);
+                        let memory_end = covered
+                            .iter()
+                            .map(|placement| placement.addr + placement.memory_size)
+                            .fold(vaddr + (file_end - start), u64::max);
+                        (start, vaddr, file_end, memory_end)
+                    }
+                };
+
+                Ok(ProgramHeader {
+                    p_type: segment.p_type,
+                    p_flags: segment.p_flags,
+                    p_offset,
+                    p_vaddr,
+                    p_paddr: p_vaddr,
+                    p_filesz: file_end - p_offset,
+                    p_memsz: memory_end - p_vaddr,
+                    p_align: segment.p_align,
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        // Section content.
+        let contents = self
+            .sections
+            .iter()
+            .enumerate()
+            .map(|(index, section)| match &section.content {
+                Content::Bytes(bytes) => Ok(bytes.clone()),
+                Content::NoBits { .. } => Ok(vec![]),
+                Content::Strings(_) => Ok(strings[&index].bytes().to_vec()),
+                Content::SysvHash { symbols } => {
+                    let names = self.symbol_names(*symbols)?;
+                    let names = names.iter().map(|name| name.as_bytes()).collect::<Vec<_>>();
+                    sysv_hash_table(&names, names.len() as u32, ctx)
+                        .ok_or_else(|| format!("{} has too many symbols.", section.name))
+                }
+                Content::Symbols(symbols) => {
+                    let symbols = std::iter::once(Ok(Sym::default()))
+                        .chain(symbols.iter().zip(&symbol_names[&index]).map(
+                            |(symbol, st_name)| {
+                                let is_section_index =
+                                    symbol.section != 0 && symbol.section < SHN_LORESERVE as usize;
+                                let st_value = if is_section_index {
+                                    address_of(symbol.section)? + symbol.offset
+                                } else {
+                                    symbol.offset
+                                };
+                                Ok(Sym {
+                                    st_name: *st_name,
+                                    st_info: symbol.st_info,
+                                    st_other: symbol.st_other,
+                                    st_shndx: symbol.section,
+                                    st_value,
+                                    st_size: symbol.st_size,
+                                })
+                            },
+                        ))
+                        .collect::<Result<Vec<_>, String>>()?;
+                    Ok(encode_entries(&symbols, ctx))
+                }
+                Content::Dynamic(entries) => {
+                    let entries = entries
+                        .iter()
+                        .enumerate()
+                        .map(|(entry, (d_tag, value))| {
+                            let d_val = match value {
+                                DynamicValue::Value(value) => *value,
+                                DynamicValue::SectionAddress(section) => address_of(*section)?,
+                                DynamicValue::SectionSize(section) => {
+                                    *sizes.get(*section).ok_or_else(|| {
+                                        format!("Section index {section} is out of range.")
+                                    })?
+                                }
+                                DynamicValue::String(_) => dynamic_strings[&(index, entry)] as u64,
+                            };
+                            Ok(Dyn {
+                                d_tag: *d_tag,
+                                d_val,
+                            })
+                        })
+                        .chain(std::iter::once(Ok(Dyn {
+                            d_tag: DT_NULL,
+                            d_val: 0,
+                        })))
+                        .collect::<Result<Vec<_>, String>>()?;
+                    Ok(encode_entries(&entries, ctx))
+                }
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        let e_entry = match self.entry {
+            Some((section, offset)) => address_of(section)? + offset,
+            None => 0,
+        };
+
+        let header = {
+            let mut res = Header::new(ctx);
+            res.e_type = self.e_type;
+            res.e_machine = self.e_machine;
+            res.e_flags = self.e_flags;
+            res.e_entry = e_entry;
+            res.e_phoff = phoff;
+            res.e_phnum = phnum
+                .try_into()
+                .map_err(|_| format!("Too many segments: {phnum}"))?;
+            res.e_shoff = shoff;
+            res.e_shnum = section_headers
+                .len()
+                .try_into()
+                .map_err(|_| format!("Too many sections: {}", section_headers.len()))?;
+            res.e_shstrndx = shstrndx
+                .try_into()
+                .map_err(|_| format!("Too many sections: {shstrndx}"))?;
+            if phnum == 0 {
+                res.e_phentsize = 0;
+            }
+            res
+        };
+
+        // Serialization buffer.
+        let mut buf = [0u8; 256];
+        let mut written_up_to = 0;
+
+        let write_error = |err: io::Error| format!("Failed to write the output.\nError: {err}");
+
+        output.iowrite_with(header, ctx).map_err(write_error)?;
+        written_up_to += Header::size_with(&ctx) as u64;
+
+        add_padding(&mut output, &mut buf, phoff, &mut written_up_to);
+        for header in program_headers {
+            iowrite_from_scroll(&mut buf, &mut output, header, ctx)
+                .expect("`ProgramHeader` values serialize correctly");
+            written_up_to += ph_size;
+        }
+
+        for ((section, placement), content) in
+            self.sections.iter().zip(&placements).zip(&contents).skip(1)
+        {
+            if section.header.sh_type == SHT_NOBITS {
+                continue;
+            }
+            add_padding(&mut output, &mut buf, placement.offset, &mut written_up_to);
+            output.write_all(content).map_err(write_error)?;
+            written_up_to += content.len() as u64;
+        }
+
+        add_padding(&mut output, &mut buf, shstrtab_offset, &mut written_up_to);
+        output.write_all(shstrtab.bytes()).map_err(write_error)?;
+        written_up_to += shstrtab.len() as u64;
+
+        add_padding(&mut output, &mut buf, shoff, &mut written_up_to);
+        for header in section_headers {
+            iowrite_from_scroll(&mut buf, &mut output, header, ctx)
+                .expect("`SectionHeader` values serialize correctly");
+        }
+
+        output.flush().map_err(write_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use goblin::{
+        container::{Container, Ctx},
+        elf::{
+            dynamic::{DT_HASH, DT_NEEDED, DT_STRSZ, DT_STRTAB, DT_SYMTAB},
+            header::{EM_BPF, EM_PPC, ET_DYN},
+            program_header::{PF_R, PF_W, PF_X, PT_DYNAMIC, PT_LOAD, PT_PHDR},
+            section_header::{SHF_ALLOC, SHF_EXECINSTR, SHF_WRITE, SHT_DYNSYM, SHT_PROGBITS},
+            sym::{STB_GLOBAL, STT_FUNC, STT_OBJECT},
+            Elf, Sym, Symtab,
+        },
+    };
+    use pretty_assertions::assert_eq;
+    use scroll::Endian;
+
+    use crate::structure::{verify_elf_structure, UnknownBytes};
+
+    use super::{DynamicValue, ElfBuilder, Segment, SegmentCovers, Symbol};
+
+    // Shared object with code, data, and the dynamic symbols.
+    fn shared_object(ctx: Ctx, e_machine: u16) -> ElfBuilder {
+        let mut builder = ElfBuilder::new(ctx, ET_DYN, e_machine);
+        builder.base_address(0x10000);
+
+        let dynstr = builder.add_strings(".dynstr", u64::from(SHF_ALLOC));
+        let text = builder.add_section(
+            ".text",
+            SHT_PROGBITS,
+            u64::from(SHF_ALLOC | SHF_EXECINSTR),
+            8,
+            vec![0x95; 16],
+        );
+        let data = builder.add_section(
+            ".data",
+            SHT_PROGBITS,
+            u64::from(SHF_ALLOC | SHF_WRITE),
+            8,
+            vec![1, 2, 3, 4],
+        );
+        let dynsym = builder.add_symbols(
+            ".dynsym",
+            SHT_DYNSYM,
+            u64::from(SHF_ALLOC),
+            dynstr,
+            vec![
+                Symbol {
+                    name: "entrypoint".to_owned(),
+                    st_info: (STB_GLOBAL << 4) | STT_FUNC,
+                    st_other: 0,
+                    section: text,
+                    offset: 8,
+                    st_size: 8,
+                },
+                Symbol {
+                    name: "value".to_owned(),
+                    st_info: (STB_GLOBAL << 4) | STT_OBJECT,
+                    st_other: 0,
+                    section: data,
+                    offset: 0,
+                    st_size: 4,
+                },
+            ],
+        );
+        let hash = builder.add_hash(dynsym);
+        let dynamic = builder.add_dynamic(
+            dynstr,
+            vec![
+                (DT_NEEDED, DynamicValue::String("libc.so".to_owned())),
+                (DT_HASH, DynamicValue::SectionAddress(hash)),
+                (DT_SYMTAB, DynamicValue::SectionAddress(dynsym)),
+                (DT_STRTAB, DynamicValue::SectionAddress(dynstr)),
+                (DT_STRSZ, DynamicValue::SectionSize(dynstr)),
+            ],
+        );
+
+        builder
+            .entry(text, 8)
+            .add_segment(Segment {
+                p_type: PT_PHDR,
+                p_flags: PF_R,
+                p_align: 8,
+                covers: SegmentCovers::ProgramHeaders,
+            })
+            .add_segment(Segment {
+                p_type: PT_LOAD,
+                p_flags: PF_R | PF_X,
+                p_align: 0x1000,
+                covers: SegmentCovers::Sections {
+                    sections: dynstr..=text,
+                    with_headers: true,
+                },
+            })
+            .add_segment(Segment::sections(
+                PT_LOAD,
+                PF_R | PF_W,
+                0x1000,
+                data,
+                dynamic,
+            ))
+            .add_segment(Segment::sections(
+                PT_DYNAMIC,
+                PF_R | PF_W,
+                8,
+                dynamic,
+                dynamic,
+            ));
+        builder
+    }
+
+    // Pages of the `PT_LOAD` segments, as mapped by a loader, must not overlap.
+    fn assert_load_pages_disjoint(elf: &Elf) {
+        let mut pages: Vec<_> = elf
+            .program_headers
+            .iter()
+            .filter(|header| header.p_type == PT_LOAD)
+            .map(|header| {
+                let start = header.p_vaddr & !0xfff;
+                let end = (header.p_vaddr + header.p_memsz + 0xfff) & !0xfff;
+                start..end
+            })
+            .collect();
+        pages.sort_by_key(|pages| pages.start);
+        for pair in pages.windows(2) {
+            assert!(
+                pair[0].end <= pair[1].start,
+                "PT_LOAD pages {:#x?} and {:#x?} overlap",
+                pair[0],
+                pair[1]
+            );
+        }
+    }
+
+    fn check_shared_object(ctx: Ctx, e_machine: u16) {
+        let bytes = shared_object(ctx, e_machine).build().expect("ELF is built");
+
+        let elf = Elf::parse(&bytes).expect("Built ELF is valid");
+        verify_elf_structure(&bytes, &elf, ctx, UnknownBytes::Reject)
+            .expect("Built ELF structure is supported");
+
+        assert_eq!(elf.header.e_machine, e_machine);
+        assert_eq!(elf.is_64, ctx.container == Container::Big);
+        assert_eq!(elf.little_endian, ctx.le == Endian::Little);
+        assert_eq!(elf.libraries, vec!["libc.so"]);
+
+        let text = &elf.section_headers[2];
+        // Without a hash table goblin does not know the `.dynsym` size, so it is read via the
+        // section header.
+        let dynsym = &elf.section_headers[4];
+        let symbols = Symtab::parse(
+            &bytes,
+            dynsym.sh_offset as usize,
+            dynsym.sh_size as usize / Sym::size(ctx.container),
+            ctx,
+        )
+        .expect("Symbols are valid");
+        assert_eq!(symbols.len(), 3);
+        assert_eq!(elf.dynsyms.len(), 3, "Symbols are visible via DT_HASH");
+        let entrypoint = symbols
+            .iter()
+            .find(|symbol| elf.dynstrtab.get_at(symbol.st_name) == Some("entrypoint"))
+            .expect("entrypoint is found");
+        assert_eq!(entrypoint.st_value, text.sh_addr + 8);
+        assert_eq!(elf.header.e_entry, text.sh_addr + 8);
+
+        let load = &elf.program_headers[1];
+        assert_eq!((load.p_offset, load.p_vaddr), (0, 0x10000));
+        assert_load_pages_disjoint(&elf);
+        assert_eq!(
+            elf.shdr_strtab.get_at(elf.section_headers[4].sh_name),
+            Some(".dynsym")
+        );
+    }
+
+    #[test]
+    fn builds_elf64_little_endian() {
+        check_shared_object(Ctx::new(Container::Big, Endian::Little), EM_BPF);
+    }
+
+    #[test]
+    fn builds_elf32_big_endian() {
+        check_shared_object(Ctx::new(Container::Little, Endian::Big), EM_PPC);
+    }
+
+    #[test]
+    fn section_at_fixed_address() {
+        let ctx = Ctx::new(Container::Big, Endian::Little);
+        let mut builder = ElfBuilder::new(ctx, ET_DYN, EM_BPF);
+        let text = builder.add_section(
+            ".text",
+            SHT_PROGBITS,
+            u64::from(SHF_ALLOC | SHF_EXECINSTR),
+            8,
+            vec![0x95; 16],
+        );
+        let data = builder.add_section(
+            ".data",
+            SHT_PROGBITS,
+            u64::from(SHF_ALLOC | SHF_WRITE),
+            8,
+            vec![1, 2, 3, 4],
+        );
+        builder
+            .set_address(text, 0x1_0000_0008)
+            .add_segment(Segment::sections(PT_LOAD, PF_R | PF_X, 0x1000, text, data));
+        let bytes = builder.build().expect("ELF is built");
+
+        let elf = Elf::parse(&bytes).expect("Built ELF is valid");
+        verify_elf_structure(&bytes, &elf, ctx, UnknownBytes::Reject)
+            .expect("Built ELF structure is supported");
+
+        let text = &elf.section_headers[text];
+        let data = &elf.section_headers[data];
+        assert_eq!((text.sh_offset, text.sh_addr), (0x1008, 0x1_0000_0008));
+        assert_eq!((data.sh_offset, data.sh_addr), (0x1018, 0x1_0000_0018));
+        assert_eq!(elf.program_headers[0].p_vaddr, 0x1_0000_0008);
+    }
+
+    #[test]
+    fn load_with_other_flags_starts_on_new_page() {
+        let ctx = Ctx::new(Container::Big, Endian::Little);
+        let mut builder = ElfBuilder::new(ctx, ET_DYN, EM_BPF);
+        let text = builder.add_section(
+            ".text",
+            SHT_PROGBITS,
+            u64::from(SHF_ALLOC | SHF_EXECINSTR),
+            8,
+            vec![0x95; 16],
+        );
+        let data = builder.add_section(
+            ".data",
+            SHT_PROGBITS,
+            u64::from(SHF_ALLOC | SHF_WRITE),
+            8,
+            vec![1, 2, 3, 4],
+        );
+        builder
+            .set_address(text, 0x1_0000_0000)
+            .add_segment(Segment::sections(PT_LOAD, PF_R | PF_X, 0x1000, text, text))
+            .add_segment(Segment::sections(PT_LOAD, PF_R | PF_W, 0x1000, data, data));
+        let bytes = builder.build().expect("ELF is built");
+
+        let elf = Elf::parse(&bytes).expect("Built ELF is valid");
+        verify_elf_structure(&bytes, &elf, ctx, UnknownBytes::Reject)
+            .expect("Built ELF structure is supported");
+
+        let data = &elf.section_headers[data];
+        assert_eq!((data.sh_offset, data.sh_addr), (0x1010, 0x1_0000_1010));
+        assert_load_pages_disjoint(&elf);
+    }
+}
@@ -0,0 +1,443 @@
+//! Structural comparison of two ELF files.
+//!
+//! Program headers are matched by their types, sections and symbols by their names.  When several
+//! items share a type or a name, they are matched in the order they appear in the files.
+
+use std::{collections::HashMap, hash::Hash};
+
+use goblin::{
+    container::Ctx,
+    elf::{
+        section_header::{SHT_DYNSYM, SHT_NOBITS, SHT_SYMTAB},
+        Elf, Header, ProgramHeader, SectionHeader, Sym,
+    },
+};
+use serde::Serialize;
+
+use crate::inspect::{section_bytes, section_symbols, NamedSymbol};
+
+/// All the differences found between the "old" and the "new" files.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ElfDiff {
+    /// ELF header fields that differ.
+    pub header: Vec<FieldChange>,
+    pub program_headers: Vec<ProgramHeaderDiff>,
+    pub sections: Vec<SectionDiff>,
+    pub symbols: Vec<SymbolDiff>,
+}
+
+/// A numeric field that has a different value in the two files.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FieldChange {
+    /// Field name, as used in the ELF specification.  For example, `sh_size`.
+    pub field: &'static str,
+    pub old: u64,
+    pub new: u64,
+    /// `new - old`.
+    pub delta: i128,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Change {
+    /// Only present in the new file.
+    Added,
+    /// Only present in the old file.
+    Removed,
+    /// Present in both files, but some of the fields differ.
+    Changed { fields: Vec<FieldChange> },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ProgramHeaderDiff {
+    /// Index in the old file, or in the new file, for an added program header.
+    pub index: usize,
+    pub p_type: u32,
+    pub change: Change,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SectionDiff {
+    pub name: String,
+    pub change: Change,
+    /// Summary of the file content differences.  Only set when the section occupies space in the
+    /// file in both the old and the new files, and the content differs.
+    pub content: Option<ContentChange>,
+}
+
+/// Byte level differences of a section content.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ContentChange {
+    /// Number of bytes that are different, or present in only one of the files.
+    pub changed_bytes: u64,
+    /// Number of continuous runs of changed bytes.
+    pub changed_ranges: u64,
+    /// Offset of the first changed byte, from the start of the section.
+    pub first_change: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SymbolDiff {
+    /// Name of the symbol table section type: `.symtab` or `.dynsym`.
+    pub table: &'static str,
+    pub name: String,
+    pub change: Change,
+}
+
+impl ElfDiff {
+    pub fn is_empty(&self) -> bool {
+        self.header.is_empty()
+            && self.program_headers.is_empty()
+            && self.sections.is_empty()
+            && self.symbols.is_empty()
+    }
+}
+
+/// Compares the structure of the `old` and the `new` files.
+pub fn compare_elfs(
+    old_bytes: &[u8],
+    old_elf: &Elf,
+    old_ctx: Ctx,
+    new_bytes: &[u8],
+    new_elf: &Elf,
+    new_ctx: Ctx,
+) -> ElfDiff {
+    ElfDiff {
+        header: compare_headers(&old_elf.header, &new_elf.header),
+        program_headers: compare_program_headers(
+            &old_elf.program_headers,
+            &new_elf.program_headers,
+        ),
+        sections: compare_sections(old_bytes, old_elf, new_bytes, new_elf),
+        symbols: compare_symbols(old_bytes, old_elf, old_ctx, new_bytes, new_elf, new_ctx),
+    }
+}
+
+/// Collects a [`FieldChange`] for every pair of values that differ.
+fn field_changes(fields: &[(&'static str, u64, u64)]) -> Vec<FieldChange> {
+    fields
+        .iter()
+        .filter(|(_, old, new)| old != new)
+        .map(|&(field, old, new)| FieldChange {
+            field,
+            old,
+            new,
+            delta: i128::from(new) - i128::from(old),
+        })
+        .collect()
+}
+
+fn compare_headers(old: &Header, new: &Header) -> Vec<FieldChange> {
+    field_changes(&[
+        ("ei_class", old.e_ident[4].into(), new.e_ident[4].into()),
+        ("ei_data", old.e_ident[5].into(), new.e_ident[5].into()),
+        ("ei_osabi", old.e_ident[7].into(), new.e_ident[7].into()),
+        ("e_type", old.e_type.into(), new.e_type.into()),
+        ("e_machine", old.e_machine.into(), new.e_machine.into()),
+        ("e_version", old.e_version.into(), new.e_version.into()),
+        ("e_entry", old.e_entry, new.e_entry),
+        ("e_phoff", old.e_phoff, new.e_phoff),
+        ("e_shoff", old.e_shoff, new.e_shoff),
+        ("e_flags", old.e_flags.into(), new.e_flags.into()),
+        ("e_ehsize", old.e_ehsize.into(), new.e_ehsize.into()),
+        (
+            "e_phentsize",
+            old.e_phentsize.into(),
+            new.e_phentsize.into(),
+        ),
+        ("e_phnum", old.e_phnum.into(), new.e_phnum.into()),
+        (
+            "e_shentsize",
+            old.e_shentsize.into(),
+            new.e_shentsize.into(),
+        ),
+        ("e_shnum", old.e_shnum.into(), new.e_shnum.into()),
+        ("e_shstrndx", old.e_shstrndx.into(), new.e_shstrndx.into()),
+    ])
+}
+
+fn compare_program_headers(old: &[ProgramHeader], new: &[ProgramHeader]) -> Vec<ProgramHeaderDiff> {
+    let indexed = |headers: &[ProgramHeader]| {
+        headers
+            .iter()
+            .enumerate()
+            .map(|(index, header)| (header.p_type, (index, header.clone())))
+            .collect::<Vec<_>>()
+    };
+    let old = indexed(old);
+    let new = indexed(new);
+
+    let mut res = vec![];
+    for (&p_type, old, new) in match_by_key(&old, &new) {
+        let (index, change) = match (old, new) {
+            (Some((index, old)), Some((_, new))) => {
+                let fields = field_changes(&[
+                    ("p_flags", old.p_flags.into(), new.p_flags.into()),
+                    ("p_offset", old.p_offset, new.p_offset),
+                    ("p_vaddr", old.p_vaddr, new.p_vaddr),
+                    ("p_paddr", old.p_paddr, new.p_paddr),
+                    ("p_filesz", old.p_filesz, new.p_filesz),
+                    ("p_memsz", old.p_memsz, new.p_memsz),
+                    ("p_align", old.p_align, new.p_align),
+                ]);
+                if fields.is_empty() {
+                    continue;
+                }
+                (*index, Change::Changed { fields })
+            }
+            (Some((index, _)), None) => (*index, Change::Removed),
+            (None, Some((index, _))) => (*index, Change::Added),
+            (None, None) => unreachable!("`match_by_key` returns at least one side"),
+        };
+
+        res.push(ProgramHeaderDiff {
+            index,
+            p_type,
+            change,
+        });
+    }
+
+    res
+}
+
+/// Pairs items with the same key, in the order they appear in `old` and `new`.  Items without a
+/// pair are returned with `None` on the other side.  Old items come first, followed by the items
+/// that are only present in `new`.
+fn match_by_key<'item, Key, Item>(
+    old: &'item [(Key, Item)],
+    new: &'item [(Key, Item)],
+) -> Vec<(&'item Key, Option<&'item Item>, Option<&'item Item>)>
+where
+    Key: Eq + Hash,
+{
+    let mut new_by_key = HashMap::<&Key, Vec<usize>>::new();
+    for (index, (key, _)) in new.iter().enumerate().rev() {
+        new_by_key.entry(key).or_default().push(index);
+    }
+
+    let mut matched = vec![false; new.len()];
+    let mut res = vec![];
+    for (key, old_item) in old {
+        let new_index = new_by_key.get_mut(key).and_then(Vec::pop);
+        if let Some(new_index) = new_index {
+            matched[new_index] = true;
+        }
+        res.push((key, Some(old_item), new_index.map(|index| &new[index].1)));
+    }
+
+    for ((key, new_item), _) in new.iter().zip(matched).filter(|(_, matched)| !matched) {
+        res.push((key, None, Some(new_item)));
+    }
+
+    res
+}
+
+fn named_sections<'elf>(elf: &'elf Elf) -> Vec<(String, &'elf SectionHeader)> {
+    elf.section_headers
+        .iter()
+        .map(|header| {
+            let name = elf.shdr_strtab.get_at(header.sh_name).unwrap_or("");
+            (name.to_owned(), header)
+        })
+        .collect()
+}
+
+fn compare_sections(
+    old_bytes: &[u8],
+    old_elf: &Elf,
+    new_bytes: &[u8],
+    new_elf: &Elf,
+) -> Vec<SectionDiff> {
+    let old_sections = named_sections(old_elf);
+    let new_sections = named_sections(new_elf);
+
+    let mut res = vec![];
+    for (name, old, new) in match_by_key(&old_sections, &new_sections) {
+        let (change, content) = match (old, new) {
+            (Some(old), Some(new)) => {
+                let fields = field_changes(&[
+                    ("sh_type", old.sh_type.into(), new.sh_type.into()),
+                    ("sh_flags", old.sh_flags, new.sh_flags),
+                    ("sh_addr", old.sh_addr, new.sh_addr),
+                    ("sh_offset", old.sh_offset, new.sh_offset),
+                    ("sh_size", old.sh_size, new.sh_size),
+                    ("sh_link", old.sh_link.into(), new.sh_link.into()),
+                    ("sh_info", old.sh_info.into(), new.sh_info.into()),
+                    ("sh_addralign", old.sh_addralign, new.sh_addralign),
+                    ("sh_entsize", old.sh_entsize, new.sh_entsize),
+                ]);
+                let content = if old.sh_type == SHT_NOBITS || new.sh_type == SHT_NOBITS {
+                    None
+                } else {
+                    compare_content(section_bytes(old_bytes, old), section_bytes(new_bytes, new))
+                };
+                if fields.is_empty() && content.is_none() {
+                    continue;
+                }
+                (Change::Changed { fields }, content)
+            }
+            (Some(_), None) => (Change::Removed, None),
+            (None, Some(_)) => (Change::Added, None),
+            (None, None) => unreachable!("`match_by_key` returns at least one side"),
+        };
+
+        res.push(SectionDiff {
+            name: name.clone(),
+            change,
+            content,
+        });
+    }
+
+    res
+}
+
+/// Returns `None` if the content is identical.
+fn compare_content(old: &[u8], new: &[u8]) -> Option<ContentChange> {
+    let mut changed_bytes = 0;
+    let mut changed_ranges = 0;
+    let mut first_change = None;
+    let mut in_range = false;
+
+    for (offset, (old, new)) in old.iter().zip(new).enumerate() {
+        if old == new {
+            in_range = false;
+            continue;
+        }
+
+        changed_bytes += 1;
+        first_change.get_or_insert(offset as u64);
+        if !in_range {
+            changed_ranges += 1;
+            in_range = true;
+        }
+    }
+
+    let common = old.len().min(new.len());
+    let tail = old.len().max(new.len()) - common;
+    if tail != 0 {
+        changed_bytes += tail as u64;
+        first_change.get_or_insert(common as u64);
+        if !in_range {
+            changed_ranges += 1;
+        }
+    }
+
+    Some(ContentChange {
+        changed_bytes,
+        changed_ranges,
+        first_change: first_change?,
+    })
+}
+
+/// Named symbols from all the `.symtab` and `.dynsym` sections, keyed by the table and the symbol
+/// names.  Tables are located via the section headers, so that `.dynsym` is found even when there is no
+/// hash table.  Tables that can not be parsed are skipped.
+fn named_symbols(bytes: &[u8], elf: &Elf, ctx: Ctx) -> Vec<((&'static str, String), Sym)> {
+    let mut res = vec![];
+
+    for header in &elf.section_headers {
+        let table = match header.sh_type {
+            SHT_SYMTAB => ".symtab",
+            SHT_DYNSYM => ".dynsym",
+            _ => continue,
+        };
+
+        let Ok(symbols) = section_symbols(bytes, elf, ctx, header) else {
+            continue;
+        };
+
+        for NamedSymbol { name, symbol, .. } in symbols {
+            match name {
+                Some("") | None => (),
+                Some(name) => res.push(((table, name.to_owned()), symbol)),
+            }
+        }
+    }
+
+    res
+}
+
+fn compare_symbols(
+    old_bytes: &[u8],
+    old_elf: &Elf,
+    old_ctx: Ctx,
+    new_bytes: &[u8],
+    new_elf: &Elf,
+    new_ctx: Ctx,
+) -> Vec<SymbolDiff> {
+    let old_symbols = named_symbols(old_bytes, old_elf, old_ctx);
+    let new_symbols = named_symbols(new_bytes, new_elf, new_ctx);
+
+    let mut res = vec![];
+    for ((table, name), old, new) in match_by_key(&old_symbols, &new_symbols) {
+        let change = match (old, new) {
+            (Some(old), Some(new)) => {
+                let fields = field_changes(&[
+                    ("st_value", old.st_value, new.st_value),
+                    ("st_size", old.st_size, new.st_size),
+                    ("st_bind", old.st_bind().into(), new.st_bind().into()),
+                ]);
+                if fields.is_empty() {
+                    continue;
+                }
+                Change::Changed { fields }
+            }
+            (Some(_), None) => Change::Removed,
+            (None, Some(_)) => Change::Added,
+            (None, None) => unreachable!("`match_by_key` returns at least one side"),
+        };
+
+        res.push(SymbolDiff {
+            table,
+            name: name.clone(),
+            change,
+        });
+    }
+
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::{compare_content, match_by_key, ContentChange};
+
+    #[test]
+    fn content_summary() {
+        assert_eq!(compare_content(&[1, 2, 3], &[1, 2, 3]), None);
+        assert_eq!(
+            compare_content(&[1, 2, 3, 4, 5], &[1, 0, 0, 4, 0, 6]),
+            Some(ContentChange {
+                changed_bytes: 4,
+                changed_ranges: 2,
+                first_change: 1,
+            }),
+        );
+        assert_eq!(
+            compare_content(&[1, 2], &[1, 2, 3]),
+            Some(ContentChange {
+                changed_bytes: 1,
+                changed_ranges: 1,
+                first_change: 2,
+            }),
+        );
+    }
+
+    #[test]
+    fn duplicate_keys_match_in_order() {
+        let old = [("a", 1), ("b", 2), ("a", 3)];
+        let new = [("a", 10), ("c", 20), ("a", 30), ("a", 40)];
+
+        assert_eq!(
+            match_by_key(&old, &new),
+            vec![
+                (&"a", Some(&1), Some(&10)),
+                (&"b", Some(&2), None),
+                (&"a", Some(&3), Some(&30)),
+                (&"c", None, Some(&20)),
+                (&"a", None, Some(&40)),
+            ],
+        );
+    }
+}
@@ -0,0 +1,83 @@
+use std::{fs::File, io::BufWriter, path::Path};
+
+use goblin::{container::Ctx, elf::Elf};
+use memmap2::Mmap;
+
+use elf_editor::structure::{verify_elf_structure, UnknownBytes};
+
+use crate::{
+    args::create::{CreateArgs, CreateCommand},
+    modify::remove_output,
+};
+
+mod from_binary;
+
+/// Returns `true` if the output file was produced and passed the self-check.  Otherwise, the
+/// output file is removed.
+pub fn run(
+    input_bytes: &[u8],
+    CreateArgs {
+        output: output_path,
+        command,
+    }: CreateArgs,
+) -> bool {
+    let builder = match command {
+        CreateCommand::FromBinary(args) => from_binary::run(input_bytes, args),
+    };
+
+    let Some(builder) = builder else {
+        return false;
+    };
+
+    let output = match File::create(&output_path) {
+        Ok(output) => BufWriter::new(output),
+        Err(err) => {
+            println!(
+                "Failed to open the output file: {}\n\
+                 Error: {}",
+                output_path.to_string_lossy(),
+                err,
+            );
+            return false;
+        }
+    };
+
+    if let Err(err) = builder.write(output) {
+        println!("Failed to build the output file:\n{err}");
+        remove_output(&output_path);
+        return false;
+    }
+
+    if let Err(err) = check_output_file(&output_path) {
+        println!("Output file failed the self-check:\n{err}");
+        remove_output(&output_path);
+        return false;
+    }
+
+    true
+}
+
+/// Parses the output file back and checks that its structure is supported by the rest of the
+/// tool.
+fn check_output_file(output_path: &Path) -> Result<(), String> {
+    let output_file = File::open(output_path)
+        .map_err(|err| format!("Failed to open the output file for reading.\nError: {err}"))?;
+    // SAFETY: Output file has just been written by us, and is not expected to change.
+    let output_bytes = unsafe { Mmap::map(&output_file) }
+        .map_err(|err| format!("Failed to read the output file.\nError: {err}"))?;
+
+    let output_elf = Elf::parse(&output_bytes)
+        .map_err(|err| format!("Output is not a valid ELF.\nError: {err}"))?;
+    let ctx = Ctx {
+        container: output_elf
+            .header
+            .container()
+            .map_err(|err| format!("Output ELF class is invalid.\nError: {err}"))?,
+        le: output_elf
+            .header
+            .endianness()
+            .map_err(|err| format!("Output ELF endianness is invalid.\nError: {err}"))?,
+    };
+
+    verify_elf_structure(&output_bytes, &output_elf, ctx, UnknownBytes::Reject)
+}
@@ -0,0 +1,168 @@
+use goblin::{
+    container::{Container, Ctx},
+    elf::{
+        dynamic::{DT_HASH, DT_STRSZ, DT_STRTAB, DT_SYMENT, DT_SYMTAB},
+        header::{
+            EM_386, EM_AARCH64, EM_ARM, EM_BPF, EM_PPC, EM_RISCV, EM_X86_64, ET_DYN, ET_EXEC,
+        },
+        program_header::{PF_R, PF_W, PF_X, PT_DYNAMIC, PT_LOAD},
+        section_header::{SHF_ALLOC, SHF_EXECINSTR, SHT_DYNSYM, SHT_PROGBITS},
+        sym::{STB_GLOBAL, STT_FUNC, STT_OBJECT},
+        Sym,
+    },
+};
+use scroll::Endian;
+
+use elf_editor::builder::{DynamicValue, ElfBuilder, Segment, Symbol};
+
+use crate::args::create::from_binary::{ContentSection, FromBinaryArgs, Machine};
+
+/// Alignment of the section holding the input content.  Instructions of all the supported machines
+/// fit.
+const CONTENT_ALIGNMENT: u64 = 8;
+
+const LOAD_ALIGNMENT: u64 = 0x1000;
+
+/// Builds an ELF file with the `input_bytes` as its only loaded content.  Symbols, if any, are
+/// placed into `.dynsym`, with a `.hash` lookup table, that are referenced from `.dynamic`, in a
+/// separate `PT_LOAD` segment.
+pub fn run(
+    input_bytes: &[u8],
+    FromBinaryArgs {
+        machine,
+        base,
+        section,
+        entry_offset,
+        entry_symbol,
+        symbols,
+    }: FromBinaryArgs,
+) -> Option<ElfBuilder> {
+    let content_size = input_bytes.len() as u64;
+
+    if base % CONTENT_ALIGNMENT != 0 {
+        println!("Base address must be a multiple of {CONTENT_ALIGNMENT}: {base:#x}");
+        return None;
+    }
+    if base.checked_add(content_size).is_none() {
+        println!(
+            "Input content does not fit into the address space at the base address.\n\
+             Base address: {base:#x}\n\
+             Input size: {content_size:#x}",
+        );
+        return None;
+    }
+
+    let symbols = entry_symbol
+        .map(|name| (name, entry_offset))
+        .into_iter()
+        .chain(symbols)
+        .collect::<Vec<_>>();
+    for (name, offset) in std::iter::once(("<entry point>", &entry_offset))
+        .chain(symbols.iter().map(|(name, offset)| (name.as_str(), offset)))
+    {
+        if *offset > content_size {
+            println!(
+                "Offset of {name} is outside of the input content.\n\
+                 Offset: {offset:#x}\n\
+                 Input size: {content_size:#x}",
+            );
+            return None;
+        }
+    }
+
+    let (ctx, e_machine) = machine_target(machine);
+    let e_type = if symbols.is_empty() { ET_EXEC } else { ET_DYN };
+    let mut builder = ElfBuilder::new(ctx, e_type, e_machine);
+
+    let (name, sh_flags, p_flags, st_type) = match section {
+        ContentSection::Text => (".text", SHF_ALLOC | SHF_EXECINSTR, PF_R | PF_X, STT_FUNC),
+        ContentSection::Rodata => (".rodata", SHF_ALLOC, PF_R, STT_OBJECT),
+    };
+    let content = builder.add_section(
+        name,
+        SHT_PROGBITS,
+        u64::from(sh_flags),
+        CONTENT_ALIGNMENT,
+        input_bytes.to_vec(),
+    );
+    builder
+        .set_address(content, base)
+        .entry(content, entry_offset)
+        .add_segment(Segment::sections(
+            PT_LOAD,
+            p_flags,
+            LOAD_ALIGNMENT,
+            content,
+            content,
+        ));
+
+    if symbols.is_empty() {
+        return Some(builder);
+    }
+
+    let dynstr = builder.add_strings(".dynstr", u64::from(SHF_ALLOC));
+    let dynsym = builder.add_symbols(
+        ".dynsym",
+        SHT_DYNSYM,
+        u64::from(SHF_ALLOC),
+        dynstr,
+        symbols
+            .into_iter()
+            .map(|(name, offset)| Symbol {
+                name,
+                st_info: (STB_GLOBAL << 4) | st_type,
+                st_other: 0,
+                section: content,
+                offset,
+                st_size: 0,
+            })
+            .collect(),
+    );
+    let hash = builder.add_hash(dynsym);
+    let dynamic = builder.add_dynamic(
+        dynstr,
+        vec![
+            (DT_HASH, DynamicValue::SectionAddress(hash)),
+            (DT_SYMTAB, DynamicValue::SectionAddress(dynsym)),
+            (
+                DT_SYMENT,
+                DynamicValue::Value(Sym::size(ctx.container) as u64),
+            ),
+            (DT_STRTAB, DynamicValue::SectionAddress(dynstr)),
+            (DT_STRSZ, DynamicValue::SectionSize(dynstr)),
+        ],
+    );
+    builder
+        .add_segment(Segment::sections(
+            PT_LOAD,
+            PF_R | PF_W,
+            LOAD_ALIGNMENT,
+            dynstr,
+            dynamic,
+        ))
+        .add_segment(Segment::sections(
+            PT_DYNAMIC,
+            PF_R | PF_W,
+            ctx.size() as u64,
+            dynamic,
+            dynamic,
+        ));
+
+    Some(builder)
+}
+
+fn machine_target(machine: Machine) -> (Ctx, u16) {
+    let elf64_le = Ctx::new(Container::Big, Endian::Little);
+    let elf32_le = Ctx::new(Container::Little, Endian::Little);
+    let elf32_be = Ctx::new(Container::Little, Endian::Big);
+
+    match machine {
+        Machine::Bpf => (elf64_le, EM_BPF),
+        Machine::X86_64 => (elf64_le, EM_X86_64),
+        Machine::Aarch64 => (elf64_le, EM_AARCH64),
+        Machine::Riscv64 => (elf64_le, EM_RISCV),
+        Machine::I386 => (elf32_le, EM_386),
+        Machine::Arm => (elf32_le, EM_ARM),
+        Machine::Powerpc => (elf32_be, EM_PPC),
+    }
+}
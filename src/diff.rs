@@ -0,0 +1,155 @@
+//! Reports structural differences between the input ELF and another ELF file.
+
+use goblin::{
+    container::Ctx,
+    elf::{header, program_header, section_header, sym, Elf},
+};
+
+use elf_editor::compare::{compare_elfs, Change, ElfDiff, FieldChange};
+
+use crate::args::OutputFormat;
+
+/// Returns `true` if no differences were found.
+pub fn run(
+    input_bytes: &[u8],
+    elf: &Elf,
+    ctx: Ctx,
+    other_bytes: &[u8],
+    format: OutputFormat,
+) -> bool {
+    let other_elf = match Elf::parse(other_bytes) {
+        Ok(elf) => elf,
+        Err(err) => {
+            println!("Other file is not a valid ELF file.\nError: {err}");
+            return false;
+        }
+    };
+    let (Ok(container), Ok(le)) = (other_elf.header.container(), other_elf.header.endianness())
+    else {
+        println!("Other ELF header has an invalid class or endianness.");
+        return false;
+    };
+    let other_ctx = Ctx { container, le };
+
+    let diff = compare_elfs(input_bytes, elf, ctx, other_bytes, &other_elf, other_ctx);
+
+    match format {
+        OutputFormat::Text => print_diff(&diff),
+        OutputFormat::Json => println!(
+            "{}",
+            serde_json::to_string_pretty(&diff).expect("Diff serializes into JSON")
+        ),
+    }
+
+    diff.is_empty()
+}
+
+fn print_diff(diff: &ElfDiff) {
+    if diff.is_empty() {
+        println!("No differences found");
+        return;
+    }
+
+    if !diff.header.is_empty() {
+        println!("ELF header:");
+        for field in &diff.header {
+            print_field(field);
+        }
+    }
+
+    if !diff.program_headers.is_empty() {
+        println!("Program sections:");
+        for program_header in &diff.program_headers {
+            println!(
+                "  #{} {}: {}",
+                program_header.index,
+                program_header::pt_to_str(program_header.p_type),
+                change_name(&program_header.change),
+            );
+            print_change_fields(&program_header.change);
+        }
+    }
+
+    if !diff.sections.is_empty() {
+        println!("Sections:");
+        for section in &diff.sections {
+            let name = if section.name.is_empty() {
+                "---"
+            } else {
+                &section.name
+            };
+            println!("  {name}: {}", change_name(&section.change));
+            print_change_fields(&section.change);
+            if let Some(content) = &section.content {
+                println!(
+                    "    content: 0x{:x} byte(s) differ in {} range(s), first at offset 0x{:x}",
+                    content.changed_bytes, content.changed_ranges, content.first_change,
+                );
+            }
+        }
+    }
+
+    if !diff.symbols.is_empty() {
+        println!("Symbols:");
+        for symbol in &diff.symbols {
+            println!(
+                "  {} {}: {}",
+                symbol.table,
+                symbol.name,
+                change_name(&symbol.change)
+            );
+            print_change_fields(&symbol.change);
+        }
+    }
+}
+
+fn change_name(change: &Change) -> &'static str {
+    match change {
+        Change::Added => "added",
+        Change::Removed => "removed",
+        Change::Changed { .. } => "changed",
+    }
+}
+
+fn print_change_fields(change: &Change) {
+    if let Change::Changed { fields } = change {
+        for field in fields {
+            print_field(field);
+        }
+    }
+}
+
+fn print_field(change: &FieldChange) {
+    let FieldChange {
+        field,
+        old,
+        new,
+        delta,
+    } = *change;
+
+    // Enumerations are shown by name, as a delta is meaningless for them.
+    let names = match field {
+        "e_type" => Some((header::et_to_str(old as u16), header::et_to_str(new as u16))),
+        "e_machine" => Some((
+            header::machine_to_str(old as u16),
+            header::machine_to_str(new as u16),
+        )),
+        "sh_type" => Some((
+            section_header::sht_to_str(old as u32),
+            section_header::sht_to_str(new as u32),
+        )),
+        "st_bind" => Some((sym::bind_to_str(old as u8), sym::bind_to_str(new as u8))),
+        _ => None,
+    };
+
+    match names {
+        Some((old, new)) => println!("    {field}: {old} -> {new}"),
+        None if delta < 0 => {
+            println!(
+                "    {field}: 0x{old:x} -> 0x{new:x} (-0x{:x})",
+                delta.unsigned_abs()
+            )
+        }
+        None => println!("    {field}: 0x{old:x} -> 0x{new:x} (+0x{delta:x})"),
+    }
+}
@@ -0,0 +1,604 @@
+//! Editable in-memory model of an ELF file.
+//!
+//! [`ElfDocument`] holds a typed, mutable copy of every section of the input.  Edits are applied
+//! to the model, and [`ElfDocument::write()`] serializes it back, via the layout engine in
+//! [`crate::transformer`].  Only sections that differ from the input are rewritten, everything
+//! else is copied as is.
+
+use std::{borrow::Cow, io};
+
+use goblin::{
+    container::Ctx,
+    elf::{
+        dynamic::Dyn,
+        reloc::Reloc,
+        section_header::{
+            SHT_DYNAMIC, SHT_DYNSYM, SHT_GNU_HASH, SHT_GNU_VERSYM, SHT_HASH, SHT_NOBITS, SHT_NULL,
+            SHT_REL, SHT_RELA, SHT_STRTAB, SHT_SYMTAB,
+        },
+        Elf, SectionHeader, Sym,
+    },
+};
+use scroll::{
+    ctx::{SizeWith, TryFromCtx, TryIntoCtx},
+    Pread as _, Pwrite as _,
+};
+
+use crate::transformer::{transform_elf_sections, LayoutOptions, SectionPlan, SectionTransformer};
+
+pub(crate) mod hash;
+
+/// Editable model of an ELF file.  Sections are in the section header table order, and are
+/// addressed by their section header table index.
+pub struct ElfDocument<'input> {
+    input_bytes: &'input [u8],
+    elf: &'input Elf<'input>,
+    ctx: Ctx,
+    sections: Vec<Section<'input>>,
+}
+
+/// A single section of an [`ElfDocument`].
+pub struct Section<'input> {
+    /// Section name, from the section headers string table.
+    pub name: String,
+    /// Section header from the input file.  Offset and size of the section in the output are
+    /// computed by the layout engine.
+    header: SectionHeader,
+    /// Section content.
+    pub data: SectionData<'input>,
+}
+
+/// Typed content of a section.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SectionData<'input> {
+    /// `SHT_NOBITS` section, that occupies no space in the file.
+    NoBits { size: u64 },
+    /// `SHT_STRTAB` section.
+    Strings(StringTable),
+    /// `SHT_SYMTAB` or `SHT_DYNSYM` section.
+    Symbols(Vec<Sym>),
+    /// `SHT_DYNAMIC` section.
+    Dynamic(Vec<Dyn>),
+    /// `SHT_RELA` or `SHT_REL` section.
+    Relocations { is_rela: bool, entries: Vec<Reloc> },
+    /// Any other section, or a section that could not be decoded.  Unless changed, refers to the
+    /// input bytes, so that large sections, such as debug info, are not copied.
+    Raw(Cow<'input, [u8]>),
+}
+
+/// Content of an `SHT_STRTAB` section.  Strings are referenced by their byte offsets, so existing
+/// strings are never moved, and new strings are appended at the end.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StringTable {
+    bytes: Vec<u8>,
+}
+
+impl<'input> ElfDocument<'input> {
+    /// Decodes all the sections of the `elf`, parsed from the `input_bytes`.
+    pub fn new(input_bytes: &'input [u8], elf: &'input Elf<'input>, ctx: Ctx) -> Self {
+        let sections = elf
+            .section_headers
+            .iter()
+            .map(|header| Section {
+                name: elf
+                    .shdr_strtab
+                    .get_at(header.sh_name)
+                    .unwrap_or_default()
+                    .to_owned(),
+                header: header.clone(),
+                data: SectionData::decode(input_bytes, header, ctx),
+            })
+            .collect();
+
+        Self {
+            input_bytes,
+            elf,
+            ctx,
+            sections,
+        }
+    }
+
+    pub fn section_mut(&mut self, index: usize) -> &mut Section<'input> {
+        &mut self.sections[index]
+    }
+
+    /// Index of the first section with the specified name.
+    pub fn section_index(&self, name: &str) -> Option<usize> {
+        self.sections
+            .iter()
+            .position(|section| section.header.sh_type != SHT_NULL && section.name == name)
+    }
+
+    /// Entries of the first `SHT_DYNAMIC` section, if any.
+    pub fn dynamic_mut(&mut self) -> Option<&mut Vec<Dyn>> {
+        self.sections
+            .iter_mut()
+            .find_map(|section| match &mut section.data {
+                SectionData::Dynamic(entries) => Some(entries),
+                _ => None,
+            })
+    }
+
+    /// Updates the `SHT_GNU_HASH`, `SHT_HASH` and `SHT_GNU_VERSYM` sections, to match the symbol
+    /// tables they describe.  Should be called after symbols are added or removed.  New symbols are
+    /// not versioned.
+    ///
+    /// Symbols hashed by an `SHT_GNU_HASH` table are reordered, if needed, so that symbols in the
+    /// same bucket are adjacent.  Relocations that refer to the moved symbols are updated.
+    ///
+    /// Returns indices of all the updated sections.
+    pub fn update_symbol_lookup_tables(&mut self) -> Result<Vec<usize>, String> {
+        let (reordered, mut updated) = self.group_symbols_by_gnu_hash_bucket()?;
+
+        for index in 0..self.sections.len() {
+            let header = &self.sections[index].header;
+            if ![SHT_GNU_HASH, SHT_HASH, SHT_GNU_VERSYM].contains(&header.sh_type) {
+                continue;
+            }
+
+            let sh_type = header.sh_type;
+            let name = &self.sections[index].name;
+            let symbols_index = header.sh_link as usize;
+            let (symbols, names) = self.symbol_names(name, symbols_index)?;
+            let SectionData::Raw(bytes) = &self.sections[index].data else {
+                unreachable!("Lookup table sections are not decoded");
+            };
+
+            let symbol_count = match sh_type {
+                SHT_GNU_HASH => hash::gnu_hash_symbol_count(bytes, self.ctx),
+                SHT_HASH => hash::sysv_hash_symbol_count(bytes, self.ctx),
+                _ => Some(hash::versym_symbol_count(bytes)),
+            };
+            if symbol_count == Some(symbols.len()) && !reordered.contains(&symbols_index) {
+                continue;
+            }
+
+            let new_bytes = match sh_type {
+                SHT_GNU_HASH => hash::rebuild_gnu_hash(bytes, &names, self.ctx),
+                SHT_HASH => hash::rebuild_sysv_hash(bytes, &names, self.ctx),
+                _ => Some(hash::resize_versym(bytes, symbols.len(), self.ctx)),
+            }
+            .ok_or_else(|| format!("Failed to parse the {name} section."))?;
+
+            self.sections[index].data = SectionData::Raw(Cow::Owned(new_bytes));
+            updated.push(index);
+        }
+
+        updated.sort_unstable();
+        updated.dedup();
+        Ok(updated)
+    }
+
+    /// Index of the first symbol called `name` in the decoded symbol table at `symbols_index`.
+    pub fn symbol_index(&self, symbols_index: usize, name: &str) -> Option<usize> {
+        let section_name = &self.sections.get(symbols_index)?.name;
+        let (_, names) = self.symbol_names(section_name, symbols_index).ok()?;
+        names
+            .iter()
+            .position(|symbol_name| *symbol_name == name.as_bytes())
+    }
+
+    /// Removes the symbol at `symbol_index` from the decoded symbol table at `symbols_index`.
+    /// Relocations that refer to the following symbols, as well as the `SHT_GNU_HASH`, `SHT_HASH`
+    /// and `SHT_GNU_VERSYM` sections of the symbol table are updated.  The symbol name stays in the
+    /// string table.
+    ///
+    /// Local symbols, and symbols that relocations refer to, can not be removed.
+    ///
+    /// Returns indices of all the updated sections.
+    pub fn remove_symbol(
+        &mut self,
+        symbols_index: usize,
+        symbol_index: usize,
+    ) -> Result<Vec<usize>, String> {
+        let Some(Section {
+            name: symbols_name,
+            header: symbols_header,
+            data: SectionData::Symbols(symbols),
+        }) = self.sections.get(symbols_index)
+        else {
+            return Err("Section is not a decoded symbol table.".to_owned());
+        };
+
+        if symbol_index >= symbols.len() {
+            return Err(format!(
+                "{symbols_name} section does not have a symbol with index {symbol_index}."
+            ));
+        }
+        // Section header `sh_info` holds the index of the first non-local symbol.
+        if symbol_index < symbols_header.sh_info as usize {
+            return Err(format!(
+                "Symbol {symbol_index} in the {symbols_name} section is local, and can not be \
+                 removed."
+            ));
+        }
+
+        // Nothing is changed until all the linked sections are updated successfully.
+        let mut changes = vec![];
+        for index in 0..self.sections.len() {
+            let linked = &self.sections[index];
+            if linked.header.sh_link as usize != symbols_index {
+                continue;
+            }
+
+            let new_data = match (&linked.data, linked.header.sh_type) {
+                (SectionData::Relocations { is_rela, entries }, _) => {
+                    let mut entries = entries.clone();
+                    for entry in &mut entries {
+                        if entry.r_sym == symbol_index {
+                            return Err(format!(
+                                "{} section refers to the removed symbol.",
+                                linked.name,
+                            ));
+                        }
+                        if entry.r_sym > symbol_index {
+                            entry.r_sym -= 1;
+                        }
+                    }
+                    SectionData::Relocations {
+                        is_rela: *is_rela,
+                        entries,
+                    }
+                }
+                (SectionData::Raw(bytes), SHT_GNU_VERSYM) => {
+                    SectionData::Raw(Cow::Owned(hash::remove_versym(bytes, symbol_index)))
+                }
+                (SectionData::Raw(bytes), SHT_GNU_HASH | SHT_HASH) => {
+                    let (_, mut names) = self.symbol_names(&linked.name, symbols_index)?;
+                    names.remove(symbol_index);
+                    let new_bytes = if linked.header.sh_type == SHT_GNU_HASH {
+                        hash::rebuild_gnu_hash_without(bytes, symbol_index, &names, self.ctx)
+                    } else {
+                        hash::rebuild_sysv_hash(bytes, &names, self.ctx)
+                    }
+                    .ok_or_else(|| format!("Failed to parse the {} section.", linked.name))?;
+                    SectionData::Raw(Cow::Owned(new_bytes))
+                }
+                (_, SHT_REL | SHT_RELA) => {
+                    return Err(format!(
+                        "{} section could not be decoded, so its symbol indices can not be \
+                         updated.",
+                        linked.name,
+                    ));
+                }
+                _ => continue,
+            };
+
+            changes.push((index, new_data));
+        }
+
+        let SectionData::Symbols(symbols) = &mut self.sections[symbols_index].data else {
+            unreachable!("Checked above");
+        };
+        symbols.remove(symbol_index);
+
+        let mut updated = vec![symbols_index];
+        for (index, data) in changes {
+            self.sections[index].data = data;
+            updated.push(index);
+        }
+        updated.sort_unstable();
+        Ok(updated)
+    }
+
+    /// Reorders symbols hashed by the `SHT_GNU_HASH` tables, so that symbols in the same bucket are
+    /// adjacent.  Relocations and `SHT_GNU_VERSYM` entries of the moved symbols are updated.
+    ///
+    /// Returns indices of the reordered symbol tables, and indices of all the updated sections.
+    fn group_symbols_by_gnu_hash_bucket(&mut self) -> Result<(Vec<usize>, Vec<usize>), String> {
+        let mut reordered = vec![];
+        let mut updated = vec![];
+
+        for index in 0..self.sections.len() {
+            let header = &self.sections[index].header;
+            if header.sh_type != SHT_GNU_HASH {
+                continue;
+            }
+
+            let name = &self.sections[index].name;
+            let symbols_index = header.sh_link as usize;
+            let (_, names) = self.symbol_names(name, symbols_index)?;
+            let SectionData::Raw(bytes) = &self.sections[index].data else {
+                unreachable!("Lookup table sections are not decoded");
+            };
+            let order = hash::gnu_hash_symbol_order(bytes, &names, self.ctx)
+                .ok_or_else(|| format!("Failed to parse the {name} section."))?;
+            if order.iter().enumerate().all(|(new, &old)| new == old) {
+                continue;
+            }
+
+            let mut new_indices = vec![0; order.len()];
+            for (new, &old) in order.iter().enumerate() {
+                new_indices[old] = new;
+            }
+
+            for (linked_index, linked) in self.sections.iter_mut().enumerate() {
+                if linked.header.sh_link as usize != symbols_index {
+                    continue;
+                }
+
+                match &mut linked.data {
+                    SectionData::Relocations { entries, .. } => {
+                        for entry in entries {
+                            entry.r_sym = *new_indices.get(entry.r_sym).ok_or_else(|| {
+                                format!(
+                                    "{} section refers to a symbol that does not exist.\n\
+                                     Symbol index: {}",
+                                    linked.name, entry.r_sym,
+                                )
+                            })?;
+                        }
+                    }
+                    SectionData::Raw(bytes) if linked.header.sh_type == SHT_GNU_VERSYM => {
+                        let bytes = hash::resize_versym(bytes, order.len(), self.ctx);
+                        linked.data =
+                            SectionData::Raw(Cow::Owned(hash::reorder_versym(&bytes, &order)));
+                    }
+                    _ if [SHT_REL, SHT_RELA].contains(&linked.header.sh_type) => {
+                        return Err(format!(
+                            "{} section could not be decoded, so its symbol indices can not be \
+                             updated.",
+                            linked.name,
+                        ));
+                    }
+                    _ => continue,
+                }
+                updated.push(linked_index);
+            }
+
+            let SectionData::Symbols(symbols) = &mut self.sections[symbols_index].data else {
+                unreachable!("Checked by `symbol_names()`");
+            };
+            *symbols = order.iter().map(|&old| symbols[old]).collect();
+            reordered.push(symbols_index);
+            updated.push(symbols_index);
+        }
+
+        Ok((reordered, updated))
+    }
+
+    /// Symbols of the decoded symbol table at `symbols_index`, and their names.  `table_name` is
+    /// the name of the section that refers to the symbol table, for the error messages.
+    fn symbol_names(
+        &self,
+        table_name: &str,
+        symbols_index: usize,
+    ) -> Result<(&[Sym], Vec<&[u8]>), String> {
+        let Some(Section {
+            header: symbols_header,
+            data: SectionData::Symbols(symbols),
+            ..
+        }) = self.sections.get(symbols_index)
+        else {
+            return Err(format!(
+                "{table_name} section is not linked to a decoded symbol table."
+            ));
+        };
+        let Some(SectionData::Strings(strings)) = self
+            .sections
+            .get(symbols_header.sh_link as usize)
+            .map(|section| &section.data)
+        else {
+            return Err(format!(
+                "{table_name} section symbol table is not linked to a string table."
+            ));
+        };
+
+        let names = symbols
+            .iter()
+            .map(|symbol| strings.get(symbol.st_name).unwrap_or_default().as_bytes())
+            .collect();
+        Ok((symbols, names))
+    }
+
+    /// Produces a new ELF file, with all the changes applied to the document, in the `output`.
+    pub fn write<Output>(&self, output: Output, options: LayoutOptions) -> Result<(), String>
+    where
+        Output: io::Write,
+    {
+        transform_elf_sections(self.input_bytes, self.elf, self.ctx, output, options, self)
+    }
+}
+
+impl SectionTransformer for &ElfDocument<'_> {
+    type Plan = Vec<u8>;
+
+    fn plan(
+        &self,
+        input_bytes: &[u8],
+        section_index: usize,
+        section_header: &SectionHeader,
+        ctx: Ctx,
+    ) -> Option<SectionPlan<Self::Plan>> {
+        let section = &self.sections[section_index];
+
+        if let SectionData::NoBits { size } = section.data {
+            return (size != section_header.sh_size).then_some(SectionPlan {
+                new_size: size,
+                plan: vec![],
+            });
+        }
+
+        let bytes = section.data.encode(ctx);
+        let start = section_header.sh_offset as usize;
+        let end = start + section_header.sh_size as usize;
+        if input_bytes.get(start..end) == Some(&*bytes) {
+            return None;
+        }
+
+        Some(SectionPlan {
+            new_size: bytes.len() as u64,
+            plan: bytes.into_owned(),
+        })
+    }
+
+    fn write(
+        &self,
+        _input_bytes: &[u8],
+        _section_index: usize,
+        _section_header: &SectionHeader,
+        _ctx: Ctx,
+        plan: Self::Plan,
+        output: &mut dyn io::Write,
+    ) {
+        output
+            .write_all(&plan)
+            .expect("Output can consume all the section data");
+    }
+}
+
+impl<'input> SectionData<'input> {
+    /// Decodes section content based on the section type.  Sections that do not decode cleanly
+    /// are kept as [`SectionData::Raw`], so that they are reproduced exactly.
+    fn decode(input_bytes: &'input [u8], header: &SectionHeader, ctx: Ctx) -> Self {
+        if header.sh_type == SHT_NOBITS {
+            return Self::NoBits {
+                size: header.sh_size,
+            };
+        }
+
+        let start = header.sh_offset as usize;
+        let end = start.saturating_add(header.sh_size as usize);
+        let Some(bytes) = input_bytes.get(start..end) else {
+            return Self::Raw(Cow::Borrowed(&[]));
+        };
+
+        let decoded = match header.sh_type {
+            SHT_STRTAB => Some(Self::Strings(StringTable {
+                bytes: bytes.to_vec(),
+            })),
+            SHT_SYMTAB | SHT_DYNSYM => decode_entries(bytes, ctx).map(Self::Symbols),
+            SHT_DYNAMIC => decode_entries(bytes, ctx).map(Self::Dynamic),
+            SHT_RELA | SHT_REL => {
+                let is_rela = header.sh_type == SHT_RELA;
+                decode_entries(bytes, (is_rela, ctx))
+                    .map(|entries| Self::Relocations { is_rela, entries })
+            }
+            _ => None,
+        };
+
+        // Round trip must be exact, otherwise we would change sections that were not edited.
+        match decoded {
+            Some(decoded) if *decoded.encode(ctx) == *bytes => decoded,
+            _ => Self::Raw(Cow::Borrowed(bytes)),
+        }
+    }
+
+    /// Serialized section content.  Empty for [`SectionData::NoBits`].
+    fn encode(&self, ctx: Ctx) -> Cow<'_, [u8]> {
+        match self {
+            Self::NoBits { .. } => Cow::Borrowed(&[]),
+            Self::Strings(StringTable { bytes }) => Cow::Borrowed(bytes),
+            Self::Raw(bytes) => Cow::Borrowed(bytes),
+            Self::Symbols(entries) => Cow::Owned(encode_entries(entries, ctx)),
+            Self::Dynamic(entries) => Cow::Owned(encode_entries(entries, ctx)),
+            Self::Relocations { is_rela, entries } => {
+                Cow::Owned(encode_entries(entries, (*is_rela, ctx)))
+            }
+        }
+    }
+
+    pub fn as_strings_mut(&mut self) -> Option<&mut StringTable> {
+        match self {
+            Self::Strings(table) => Some(table),
+            _ => None,
+        }
+    }
+
+    pub fn as_symbols_mut(&mut self) -> Option<&mut Vec<Sym>> {
+        match self {
+            Self::Symbols(entries) => Some(entries),
+            _ => None,
+        }
+    }
+}
+
+impl Default for StringTable {
+    /// Table that holds only the empty string, at offset 0.
+    fn default() -> Self {
+        Self { bytes: vec![0] }
+    }
+}
+
+impl StringTable {
+    /// Number of bytes in the table.
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// String that starts at the specified offset.
+    pub fn get(&self, offset: usize) -> Option<&str> {
+        let tail = self.bytes.get(offset..)?;
+        let end = tail.iter().position(|&b| b == 0)?;
+        std::str::from_utf8(&tail[..end]).ok()
+    }
+
+    /// Appends a new string to the table, returning its offset.
+    pub fn add(&mut self, value: &str) -> usize {
+        let offset = self.bytes.len();
+        self.bytes.extend_from_slice(value.as_bytes());
+        self.bytes.push(0);
+        offset
+    }
+}
+
+/// Decodes a table of fixed size entries.  Returns `None` if the `bytes` size is not a multiple of
+/// the entry size, or if any entry fails to decode.
+fn decode_entries<'bytes, T, EntryCtx>(bytes: &'bytes [u8], ctx: EntryCtx) -> Option<Vec<T>>
+where
+    T: SizeWith<EntryCtx> + TryFromCtx<'bytes, EntryCtx, Error = goblin::error::Error>,
+    EntryCtx: Copy,
+{
+    let entry_size = T::size_with(&ctx);
+    if entry_size == 0 || !bytes.len().is_multiple_of(entry_size) {
+        return None;
+    }
+
+    (0..bytes.len() / entry_size)
+        .map(|i| bytes.pread_with(i * entry_size, ctx).ok())
+        .collect()
+}
+
+pub(crate) fn encode_entries<T, EntryCtx>(entries: &[T], ctx: EntryCtx) -> Vec<u8>
+where
+    T: SizeWith<EntryCtx> + TryIntoCtx<EntryCtx, Error = goblin::error::Error> + Clone,
+    EntryCtx: Copy,
+{
+    let entry_size = T::size_with(&ctx);
+    let mut res = vec![0; entries.len() * entry_size];
+    for (i, entry) in entries.iter().enumerate() {
+        res.pwrite_with(entry.clone(), i * entry_size, ctx)
+            .expect("Entry fits into the preallocated buffer");
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::StringTable;
+
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn string_table_add_appends() {
+        let mut table = StringTable {
+            bytes: b"\0first\0".to_vec(),
+        };
+
+        assert_eq!(table.add("second"), 7);
+
+        assert_eq!(table.len(), 14);
+        assert_eq!(table.get(1), Some("first"));
+        assert_eq!(table.get(7), Some("second"));
+        assert_eq!(table.get(9), Some("cond"));
+        assert_eq!(table.get(14), None);
+    }
+}
@@ -0,0 +1,346 @@
+//! Symbol lookup tables, that are derived from the symbol tables: `SHT_GNU_HASH`, `SHT_HASH` and
+//! `SHT_GNU_VERSYM`.
+
+use goblin::container::{Container, Ctx};
+use scroll::{Pread as _, Pwrite as _};
+
+/// Version index of symbols that are not versioned.
+const VER_NDX_GLOBAL: u16 = 1;
+
+/// Hash function used by `SHT_GNU_HASH` sections.
+pub fn gnu_hash(name: &[u8]) -> u32 {
+    name.iter().fold(5381u32, |hash, &c| {
+        hash.wrapping_mul(33).wrapping_add(u32::from(c))
+    })
+}
+
+/// Hash function used by `SHT_HASH` sections.
+pub fn sysv_hash(name: &[u8]) -> u32 {
+    name.iter().fold(0u32, |hash, &c| {
+        let hash = (hash << 4).wrapping_add(u32::from(c));
+        let high = hash & 0xf000_0000;
+        (hash ^ (high >> 24)) & !high
+    })
+}
+
+/// Number of symbols covered by the `SHT_GNU_HASH` table in `bytes`.
+pub fn gnu_hash_symbol_count(bytes: &[u8], ctx: Ctx) -> Option<usize> {
+    let header = GnuHashHeader::read(bytes, ctx)?;
+    let chain_bytes = bytes.len().checked_sub(header.chain_offset(ctx))?;
+    Some(header.symoffset as usize + chain_bytes / 4)
+}
+
+/// Order the `SHT_GNU_HASH` table in `bytes` requires for symbols with the specified `names`.
+/// Symbols from `symoffset` on are hashed, and hashed symbols in the same bucket must be adjacent.
+/// Returns the current symbol indices, in the required order.  Symbols that are already in a
+/// suitable order keep their positions.
+pub fn gnu_hash_symbol_order(bytes: &[u8], names: &[&[u8]], ctx: Ctx) -> Option<Vec<usize>> {
+    let GnuHashHeader {
+        nbuckets,
+        symoffset,
+        ..
+    } = GnuHashHeader::read(bytes, ctx)?;
+
+    if nbuckets == 0 {
+        return None;
+    }
+
+    let symoffset = symoffset as usize;
+    let mut order = (0..names.len()).collect::<Vec<_>>();
+    let hashes = names
+        .get(symoffset..)?
+        .iter()
+        .map(|name| gnu_hash(name))
+        .collect::<Vec<_>>();
+    if !buckets_are_adjacent(&hashes, nbuckets) {
+        // Sort is stable, so symbols keep their relative order within a bucket.
+        order[symoffset..].sort_by_key(|&index| hashes[index - symoffset] % nbuckets);
+    }
+
+    Some(order)
+}
+
+/// Builds a new `SHT_GNU_HASH` table for symbols with the specified `names`, keeping the
+/// parameters of the existing table in `bytes`.
+///
+/// Symbols must be in the [`gnu_hash_symbol_order()`], otherwise `None` is returned.
+pub fn rebuild_gnu_hash(bytes: &[u8], names: &[&[u8]], ctx: Ctx) -> Option<Vec<u8>> {
+    let GnuHashHeader {
+        nbuckets,
+        symoffset,
+        bloom_size,
+        bloom_shift,
+    } = GnuHashHeader::read(bytes, ctx)?;
+
+    if bloom_size == 0 {
+        return None;
+    }
+
+    let hashes = names
+        .get(symoffset as usize..)?
+        .iter()
+        .map(|name| gnu_hash(name))
+        .collect::<Vec<_>>();
+
+    if nbuckets == 0 || !buckets_are_adjacent(&hashes, nbuckets) {
+        return None;
+    }
+
+    let word_bits = match ctx.container {
+        Container::Little => 32,
+        Container::Big => 64,
+    };
+    let mut bloom = vec![0u64; bloom_size as usize];
+    for hash in &hashes {
+        let word = &mut bloom[(hash / word_bits % bloom_size) as usize];
+        *word |= 1 << (hash % word_bits);
+        *word |= 1 << ((hash >> bloom_shift) % word_bits);
+    }
+
+    let mut buckets = vec![0u32; nbuckets as usize];
+    let mut chain = vec![0u32; hashes.len()];
+    for (i, hash) in hashes.iter().enumerate() {
+        let bucket = &mut buckets[(hash % nbuckets) as usize];
+        if *bucket == 0 {
+            *bucket = symoffset + i as u32;
+        }
+
+        let is_last = hashes
+            .get(i + 1)
+            .is_none_or(|next| next % nbuckets != hash % nbuckets);
+        chain[i] = (hash & !1) | u32::from(is_last);
+    }
+
+    let header = GnuHashHeader {
+        nbuckets,
+        symoffset,
+        bloom_size,
+        bloom_shift,
+    };
+    let mut res = vec![0; header.chain_offset(ctx) + chain.len() * 4];
+    let mut offset = 0;
+    for value in [nbuckets, symoffset, bloom_size, bloom_shift] {
+        res.gwrite_with(value, &mut offset, ctx.le).ok()?;
+    }
+    for word in bloom {
+        match ctx.container {
+            Container::Little => res.gwrite_with(word as u32, &mut offset, ctx.le).ok()?,
+            Container::Big => res.gwrite_with(word, &mut offset, ctx.le).ok()?,
+        };
+    }
+    for value in buckets.into_iter().chain(chain) {
+        res.gwrite_with(value, &mut offset, ctx.le).ok()?;
+    }
+
+    Some(res)
+}
+
+/// Builds a new `SHT_GNU_HASH` table, after the symbol at index `removed` is removed from the
+/// symbols the table in `bytes` describes.  `names` are the names of the remaining symbols.
+/// `symoffset` is lowered, if the removed symbol is not hashed.
+pub fn rebuild_gnu_hash_without(
+    bytes: &[u8],
+    removed: usize,
+    names: &[&[u8]],
+    ctx: Ctx,
+) -> Option<Vec<u8>> {
+    let GnuHashHeader { symoffset, .. } = GnuHashHeader::read(bytes, ctx)?;
+
+    let mut bytes = bytes.to_vec();
+    if removed < symoffset as usize {
+        bytes.pwrite_with(symoffset - 1, 4, ctx.le).ok()?;
+    }
+
+    rebuild_gnu_hash(&bytes, names, ctx)
+}
+
+/// Number of symbols covered by the `SHT_HASH` table in `bytes`.
+pub fn sysv_hash_symbol_count(bytes: &[u8], ctx: Ctx) -> Option<usize> {
+    bytes
+        .pread_with::<u32>(4, ctx.le)
+        .ok()
+        .map(|nchain| nchain as usize)
+}
+
+/// Builds a new `SHT_HASH` table for symbols with the specified `names`, keeping the number of
+/// buckets of the existing table in `bytes`.
+pub fn rebuild_sysv_hash(bytes: &[u8], names: &[&[u8]], ctx: Ctx) -> Option<Vec<u8>> {
+    let nbucket = bytes.pread_with::<u32>(0, ctx.le).ok()?;
+    sysv_hash_table(names, nbucket, ctx)
+}
+
+/// Builds an `SHT_HASH` table with `nbucket` buckets, for symbols with the specified `names`.
+pub fn sysv_hash_table(names: &[&[u8]], nbucket: u32, ctx: Ctx) -> Option<Vec<u8>> {
+    let nbucket = nbucket.max(1);
+    let nchain = u32::try_from(names.len()).ok()?;
+
+    let mut buckets = vec![0u32; nbucket as usize];
+    let mut chains = vec![0u32; names.len()];
+    // Inserting at the bucket head in the reverse order keeps every chain in the symbol order.
+    for (i, name) in names.iter().enumerate().skip(1).rev() {
+        let bucket = &mut buckets[(sysv_hash(name) % nbucket) as usize];
+        chains[i] = *bucket;
+        *bucket = i as u32;
+    }
+
+    let mut res = vec![0; (2 + buckets.len() + chains.len()) * 4];
+    let mut offset = 0;
+    for value in [nbucket, nchain].into_iter().chain(buckets).chain(chains) {
+        res.gwrite_with(value, &mut offset, ctx.le).ok()?;
+    }
+
+    Some(res)
+}
+
+/// Number of symbols covered by the `SHT_GNU_VERSYM` table in `bytes`.
+pub fn versym_symbol_count(bytes: &[u8]) -> usize {
+    bytes.len() / 2
+}
+
+/// Reorders the entries of an `SHT_GNU_VERSYM` table, to follow the symbols that were moved into
+/// the specified `order`.  `order` holds the old symbol indices, and the table must cover them all.
+pub fn reorder_versym(bytes: &[u8], order: &[usize]) -> Vec<u8> {
+    order
+        .iter()
+        .flat_map(|&index| [bytes[index * 2], bytes[index * 2 + 1]])
+        .collect()
+}
+
+/// Removes the entry of the symbol at index `removed` from an `SHT_GNU_VERSYM` table.  Tables that
+/// do not cover the symbol are not changed.
+pub fn remove_versym(bytes: &[u8], removed: usize) -> Vec<u8> {
+    let mut res = bytes.to_vec();
+    if res.len() >= (removed + 1) * 2 {
+        res.drain(removed * 2..(removed + 1) * 2);
+    }
+    res
+}
+
+/// Resizes an `SHT_GNU_VERSYM` table to cover `symbol_count` symbols.  New symbols are not
+/// versioned.
+pub fn resize_versym(bytes: &[u8], symbol_count: usize, ctx: Ctx) -> Vec<u8> {
+    let mut res = vec![0; symbol_count * 2];
+    let kept = bytes.len().min(res.len());
+    res[..kept].copy_from_slice(&bytes[..kept]);
+
+    let mut offset = kept;
+    while offset < res.len() {
+        res.gwrite_with(VER_NDX_GLOBAL, &mut offset, ctx.le)
+            .expect("Entry fits into the preallocated buffer");
+    }
+
+    res
+}
+
+struct GnuHashHeader {
+    nbuckets: u32,
+    symoffset: u32,
+    bloom_size: u32,
+    bloom_shift: u32,
+}
+
+impl GnuHashHeader {
+    fn read(bytes: &[u8], ctx: Ctx) -> Option<Self> {
+        let field = |index: usize| bytes.pread_with::<u32>(index * 4, ctx.le).ok();
+        Some(Self {
+            nbuckets: field(0)?,
+            symoffset: field(1)?,
+            bloom_size: field(2)?,
+            bloom_shift: field(3)?,
+        })
+    }
+
+    /// Offset of the chain array, from the start of the table.
+    fn chain_offset(&self, ctx: Ctx) -> usize {
+        let word_size = match ctx.container {
+            Container::Little => 4,
+            Container::Big => 8,
+        };
+        16 + self.bloom_size as usize * word_size + self.nbuckets as usize * 4
+    }
+}
+
+/// Checks that symbols with the specified `hashes` that fall into the same bucket are adjacent.
+fn buckets_are_adjacent(hashes: &[u32], nbuckets: u32) -> bool {
+    let mut seen = vec![false; nbuckets as usize];
+    let mut previous = None;
+    for hash in hashes {
+        let bucket = hash % nbuckets;
+        if previous != Some(bucket) {
+            if seen[bucket as usize] {
+                return false;
+            }
+            seen[bucket as usize] = true;
+            previous = Some(bucket);
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use goblin::container::{Container, Ctx};
+    use pretty_assertions::assert_eq;
+    use scroll::{Endian, Pread as _};
+
+    use super::{
+        gnu_hash, gnu_hash_symbol_count, gnu_hash_symbol_order, rebuild_gnu_hash,
+        rebuild_gnu_hash_without, sysv_hash,
+    };
+
+    #[test]
+    fn hash_functions() {
+        assert_eq!(gnu_hash(b""), 0x0000_1505);
+        assert_eq!(gnu_hash(b"printf"), 0x156b_2bb8);
+        assert_eq!(sysv_hash(b"printf"), 0x0779_05a6);
+    }
+
+    #[test]
+    fn gnu_hash_groups_symbols_by_bucket() {
+        let ctx = Ctx::new(Container::Big, Endian::Little);
+        let names: [&[u8]; 4] = [b"", b"a", b"b", b"c"];
+
+        // 2 buckets, `symoffset` is 1, single bloom word, bloom shift is 6.  Hash table content is
+        // ignored.
+        let mut old = vec![];
+        for value in [2u32, 1, 1, 6] {
+            old.extend_from_slice(&value.to_le_bytes());
+        }
+        old.resize(16 + 8 + 2 * 4 + 2 * 4, 0);
+
+        // "a" and "c" land in the same bucket, while "b" is between them.
+        assert_eq!(gnu_hash(b"a") % 2, gnu_hash(b"c") % 2);
+        assert_ne!(gnu_hash(b"a") % 2, gnu_hash(b"b") % 2);
+
+        assert_eq!(rebuild_gnu_hash(&old, &names, ctx), None);
+
+        let order = gnu_hash_symbol_order(&old, &names, ctx).expect("Table is valid");
+        assert_eq!(order, vec![0, 1, 3, 2]);
+
+        let names = order.iter().map(|&index| names[index]).collect::<Vec<_>>();
+        let new = rebuild_gnu_hash(&old, &names, ctx).expect("Table is rebuilt");
+        assert_eq!(new.pread_with::<u32>(0, ctx.le).unwrap(), 2);
+        assert_eq!(gnu_hash_symbol_count(&new, ctx), Some(4));
+    }
+
+    #[test]
+    fn gnu_hash_without_unhashed_symbol() {
+        let ctx = Ctx::new(Container::Big, Endian::Little);
+
+        // 1 bucket, `symoffset` is 2, single bloom word, bloom shift is 6, and a single hashed
+        // symbol.
+        let mut old = vec![];
+        for value in [1u32, 2, 1, 6] {
+            old.extend_from_slice(&value.to_le_bytes());
+        }
+        old.resize(16 + 8 + 4 + 4, 0);
+
+        // Unhashed symbol 1 is removed.
+        let names: [&[u8]; 2] = [b"", b"a"];
+        let new = rebuild_gnu_hash_without(&old, 1, &names, ctx).expect("Table is rebuilt");
+        assert_eq!(new.pread_with::<u32>(4, ctx.le).unwrap(), 1);
+        assert_eq!(gnu_hash_symbol_count(&new, ctx), Some(2));
+        // The only bucket starts at the hashed symbol.
+        assert_eq!(new.pread_with::<u32>(16 + 8, ctx.le).unwrap(), 1);
+    }
+}
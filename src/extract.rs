@@ -0,0 +1,72 @@
+//! Writes raw content of the input ELF parts into separate files.
+
+use std::{
+    fs::File,
+    io::{BufWriter, Write as _},
+    path::Path,
+};
+
+use goblin::elf::Elf;
+
+use elf_editor::image::{section_content, segment_content, LoadImage};
+
+use crate::{
+    args::extract::{ExtractArgs, ImageArgs, SectionArgs, SegmentArgs},
+    modify::remove_output,
+};
+
+/// Returns `true` if the output file was written.
+pub fn run(input_bytes: &[u8], elf: &Elf, args: ExtractArgs) -> bool {
+    let res = match args {
+        ExtractArgs::Section(SectionArgs { name, output }) => {
+            section_content(input_bytes, elf, &name)
+                .and_then(|content| write_output(&output, |file| write_bytes(file, content)))
+        }
+        ExtractArgs::Segment(SegmentArgs { index, output }) => {
+            segment_content(input_bytes, elf, index)
+                .and_then(|content| write_output(&output, |file| write_bytes(file, content)))
+        }
+        ExtractArgs::Image(ImageArgs { format, output }) => LoadImage::new(input_bytes, elf)
+            .and_then(|image| write_output(&output, |file| image.write(format.into(), file))),
+    };
+
+    if let Err(err) = res {
+        println!("{err}");
+        return false;
+    }
+
+    true
+}
+
+/// Creates the output file and fills it using `fill`.  Removes the file if `fill` fails.
+fn write_output<Fill>(output_path: &Path, fill: Fill) -> Result<(), String>
+where
+    Fill: FnOnce(&mut BufWriter<File>) -> Result<(), String>,
+{
+    let mut output = File::create(output_path)
+        .map(BufWriter::new)
+        .map_err(|err| {
+            format!(
+                "Failed to open the output file: {}\n\
+             Error: {}",
+                output_path.to_string_lossy(),
+                err,
+            )
+        })?;
+
+    let res = fill(&mut output).and_then(|()| {
+        output
+            .flush()
+            .map_err(|err| format!("Failed to write the output.\nError: {err}"))
+    });
+    if res.is_err() {
+        remove_output(output_path);
+    }
+    res
+}
+
+fn write_bytes(output: &mut BufWriter<File>, bytes: &[u8]) -> Result<(), String> {
+    output
+        .write_all(bytes)
+        .map_err(|err| format!("Failed to write the output.\nError: {err}"))
+}
@@ -0,0 +1,236 @@
+//! Raw content of sections and segments, and flat memory images of the loadable segments.
+
+use std::io;
+
+use goblin::elf::{program_header::PT_LOAD, section_header::SHT_NOBITS, Elf};
+
+mod ihex;
+mod srec;
+
+/// Output format of a [`LoadImage`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ImageFormat {
+    /// Raw memory content, starting at the lowest loaded address.  Gaps between the segments are
+    /// filled with zeros.  Similar to `objcopy -O binary`, except that whole segments are included,
+    /// not only the sections inside them.
+    #[default]
+    Binary,
+    /// Intel HEX text records.  Addresses are limited to 32 bits.
+    Ihex,
+    /// Motorola S-record text records.  Addresses are limited to 32 bits.
+    Srec,
+}
+
+/// File content of the section with the specified name.
+pub fn section_content<'input>(
+    input_bytes: &'input [u8],
+    elf: &Elf,
+    name: &str,
+) -> Result<&'input [u8], String> {
+    let header = elf
+        .section_headers
+        .iter()
+        .find(|header| elf.shdr_strtab.get_at(header.sh_name) == Some(name))
+        .ok_or_else(|| format!("Input does not have a section named {name}"))?;
+
+    if header.sh_type == SHT_NOBITS {
+        return Err(format!("Section {name} occupies no space in the file"));
+    }
+
+    file_content(input_bytes, header.sh_offset, header.sh_size)
+        .ok_or_else(|| format!("Section {name} is outside of the input file"))
+}
+
+/// File content of the program header with the specified index.
+pub fn segment_content<'input>(
+    input_bytes: &'input [u8],
+    elf: &Elf,
+    index: usize,
+) -> Result<&'input [u8], String> {
+    let header = elf.program_headers.get(index).ok_or_else(|| {
+        format!(
+            "Input has {} program headers, there is no header with index {index}",
+            elf.program_headers.len(),
+        )
+    })?;
+
+    file_content(input_bytes, header.p_offset, header.p_filesz)
+        .ok_or_else(|| format!("Program header {index} is outside of the input file"))
+}
+
+fn file_content(input_bytes: &[u8], offset: u64, size: u64) -> Option<&[u8]> {
+    let start = usize::try_from(offset).ok()?;
+    let end = start.checked_add(usize::try_from(size).ok()?)?;
+    input_bytes.get(start..end)
+}
+
+/// File content of all the `PT_LOAD` segments, placed at their physical addresses.
+///
+/// Only the bytes present in the file are included, so `p_memsz` past `p_filesz` is ignored, the
+/// same way `objcopy -O binary` does it.
+pub struct LoadImage<'input> {
+    /// Chunks ordered by address, not overlapping.
+    pub chunks: Vec<Chunk<'input>>,
+    pub entry: u64,
+}
+
+pub struct Chunk<'input> {
+    pub address: u64,
+    pub bytes: &'input [u8],
+}
+
+impl Chunk<'_> {
+    /// Address right after the last byte of the chunk.
+    fn end(&self) -> u64 {
+        self.address + self.bytes.len() as u64
+    }
+}
+
+impl<'input> LoadImage<'input> {
+    pub fn new(input_bytes: &'input [u8], elf: &Elf) -> Result<Self, String> {
+        let mut chunks = elf
+            .program_headers
+            .iter()
+            .enumerate()
+            .filter(|(_, header)| header.p_type == PT_LOAD && header.p_filesz != 0)
+            .map(|(index, header)| {
+                let bytes = file_content(input_bytes, header.p_offset, header.p_filesz)
+                    .ok_or_else(|| format!("PT_LOAD {index} is outside of the input file"))?;
+                if header.p_paddr.checked_add(header.p_filesz).is_none() {
+                    return Err(format!(
+                        "PT_LOAD {index} extends past the end of the address space"
+                    ));
+                }
+                Ok(Chunk {
+                    address: header.p_paddr,
+                    bytes,
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        chunks.sort_by_key(|chunk| chunk.address);
+
+        if let Some(overlap) = chunks
+            .windows(2)
+            .find(|pair| pair[0].end() > pair[1].address)
+        {
+            return Err(format!(
+                "PT_LOAD segments overlap in physical memory: \
+                 0x{:0>16x} - 0x{:0>16x} and 0x{:0>16x} - 0x{:0>16x}",
+                overlap[0].address,
+                overlap[0].end(),
+                overlap[1].address,
+                overlap[1].end(),
+            ));
+        }
+
+        Ok(Self {
+            chunks,
+            entry: elf.header.e_entry,
+        })
+    }
+
+    /// Lowest loaded address, or 0 for an image without any content.
+    pub fn start(&self) -> u64 {
+        self.chunks.first().map_or(0, |chunk| chunk.address)
+    }
+
+    /// Address right after the highest loaded byte.
+    pub fn end(&self) -> u64 {
+        self.chunks.last().map_or(0, Chunk::end)
+    }
+
+    pub fn write<Output>(&self, format: ImageFormat, output: &mut Output) -> Result<(), String>
+    where
+        Output: io::Write,
+    {
+        let res = match format {
+            ImageFormat::Binary => self.write_binary(output),
+            ImageFormat::Ihex => {
+                self.check_32_bit_addresses("Intel HEX")?;
+                ihex::write(self, output)
+            }
+            ImageFormat::Srec => {
+                self.check_32_bit_addresses("S-record")?;
+                srec::write(self, output)
+            }
+        };
+        res.map_err(|err| format!("Failed to write the output.\nError: {err}"))
+    }
+
+    fn write_binary<Output>(&self, output: &mut Output) -> io::Result<()>
+    where
+        Output: io::Write,
+    {
+        let zeros = [0u8; 4096];
+        let mut written_up_to = self.start();
+        for chunk in &self.chunks {
+            while written_up_to < chunk.address {
+                let size = (chunk.address - written_up_to).min(zeros.len() as u64);
+                output.write_all(&zeros[..size as usize])?;
+                written_up_to += size;
+            }
+            output.write_all(chunk.bytes)?;
+            written_up_to = chunk.end();
+        }
+        Ok(())
+    }
+
+    /// Checks that all the loaded addresses fit into 32 bits, as required by the text formats.
+    /// Entry point is a virtual address, so it is only recorded when it fits.
+    fn check_32_bit_addresses(&self, format: &str) -> Result<(), String> {
+        let max_address = self.end().saturating_sub(1);
+        if max_address > u64::from(u32::MAX) {
+            return Err(format!(
+                "{format} supports only 32 bit addresses, while the image extends to 0x{:0>16x}",
+                max_address,
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::{Chunk, ImageFormat, LoadImage};
+
+    #[test]
+    fn binary_fills_gaps() {
+        let image = LoadImage {
+            chunks: vec![
+                Chunk {
+                    address: 0x1000,
+                    bytes: &[1, 2],
+                },
+                Chunk {
+                    address: 0x1004,
+                    bytes: &[3],
+                },
+            ],
+            entry: 0x1000,
+        };
+
+        let mut output = vec![];
+        image
+            .write(ImageFormat::Binary, &mut output)
+            .expect("Image is written");
+        assert_eq!(output, vec![1, 2, 0, 0, 3]);
+    }
+
+    #[test]
+    fn text_formats_reject_64_bit_addresses() {
+        let image = LoadImage {
+            chunks: vec![Chunk {
+                address: 0x1_0000_0000,
+                bytes: &[1],
+            }],
+            entry: 0,
+        };
+
+        for format in [ImageFormat::Ihex, ImageFormat::Srec] {
+            assert!(image.write(format, &mut vec![]).is_err());
+        }
+    }
+}
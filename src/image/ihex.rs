@@ -0,0 +1,104 @@
+//! Intel HEX encoding, using the 32 bit extended linear addresses.
+
+use std::io;
+
+use super::LoadImage;
+
+const DATA: u8 = 0x00;
+const END_OF_FILE: u8 = 0x01;
+const EXTENDED_LINEAR_ADDRESS: u8 = 0x04;
+const START_LINEAR_ADDRESS: u8 = 0x05;
+
+/// Bytes of data in a single record.  Same as `objcopy` uses.
+const RECORD_DATA_SIZE: usize = 16;
+
+/// Writes the `image` as Intel HEX records.  All the loaded addresses must fit into 32 bits.
+pub fn write<Output>(image: &LoadImage, output: &mut Output) -> io::Result<()>
+where
+    Output: io::Write,
+{
+    let mut upper_address = 0;
+    for chunk in &image.chunks {
+        let mut address = chunk.address as u32;
+        let mut bytes = chunk.bytes;
+        while !bytes.is_empty() {
+            if address >> 16 != upper_address {
+                upper_address = address >> 16;
+                write_record(
+                    output,
+                    0,
+                    EXTENDED_LINEAR_ADDRESS,
+                    &(upper_address as u16).to_be_bytes(),
+                )?;
+            }
+
+            // Records do not cross the 64KiB boundary, as the record address is only 16 bits.
+            let till_boundary = 0x1_0000 - (address & 0xffff) as usize;
+            let size = bytes.len().min(RECORD_DATA_SIZE).min(till_boundary);
+            write_record(output, address as u16, DATA, &bytes[..size])?;
+
+            bytes = &bytes[size..];
+            address = address.wrapping_add(size as u32);
+        }
+    }
+
+    if let Ok(entry) = u32::try_from(image.entry) {
+        if entry != 0 {
+            write_record(output, 0, START_LINEAR_ADDRESS, &entry.to_be_bytes())?;
+        }
+    }
+
+    write_record(output, 0, END_OF_FILE, &[])
+}
+
+fn write_record<Output>(output: &mut Output, address: u16, kind: u8, data: &[u8]) -> io::Result<()>
+where
+    Output: io::Write,
+{
+    let [address_high, address_low] = address.to_be_bytes();
+    let header = [data.len() as u8, address_high, address_low, kind];
+    let sum = header
+        .iter()
+        .chain(data)
+        .fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+
+    write!(output, ":")?;
+    for byte in header.iter().chain(data) {
+        write!(output, "{byte:02X}")?;
+    }
+    writeln!(output, "{:02X}", sum.wrapping_neg())
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::{write, LoadImage};
+    use crate::image::Chunk;
+
+    #[test]
+    fn records() {
+        let bytes = [
+            0x21, 0x46, 0x01, 0x36, 0x01, 0x21, 0x47, 0x01, 0x36, 0x00, 0x7E, 0xFE, 0x09, 0xD2,
+            0x19, 0x01, 0x21,
+        ];
+        let image = LoadImage {
+            chunks: vec![Chunk {
+                address: 0x0001_0100,
+                bytes: &bytes,
+            }],
+            entry: 0x0001_0100,
+        };
+
+        let mut output = vec![];
+        write(&image, &mut output).expect("Records are written");
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            ":020000040001F9\n\
+             :10010000214601360121470136007EFE09D2190140\n\
+             :0101100021CD\n\
+             :0400000500010100F5\n\
+             :00000001FF\n",
+        );
+    }
+}
@@ -0,0 +1,97 @@
+//! Motorola S-record encoding.  Address size is picked by the highest address, the same way
+//! `objcopy` does it.
+
+use std::io;
+
+use super::LoadImage;
+
+/// Bytes of data in a single record.  Same as `objcopy` uses.
+const RECORD_DATA_SIZE: usize = 16;
+
+/// Writes the `image` as S-records.  All the loaded addresses must fit into 32 bits.
+pub fn write<Output>(image: &LoadImage, output: &mut Output) -> io::Result<()>
+where
+    Output: io::Write,
+{
+    let entry = if image.entry <= u64::from(u32::MAX) {
+        image.entry
+    } else {
+        0
+    };
+
+    // Data and termination record types, for the 16, 24 and 32 bit addresses.
+    let max_address = image.end().saturating_sub(1).max(entry);
+    let (address_size, data_kind, termination_kind) = if max_address <= 0xffff {
+        (2, 1, 9)
+    } else if max_address <= 0xff_ffff {
+        (3, 2, 8)
+    } else {
+        (4, 3, 7)
+    };
+
+    // Header record, without a module name.
+    write_record(output, 0, 2, 0, &[])?;
+
+    for chunk in &image.chunks {
+        let mut address = chunk.address;
+        for data in chunk.bytes.chunks(RECORD_DATA_SIZE) {
+            write_record(output, data_kind, address_size, address, data)?;
+            address += data.len() as u64;
+        }
+    }
+
+    write_record(output, termination_kind, address_size, entry, &[])
+}
+
+fn write_record<Output>(
+    output: &mut Output,
+    kind: u8,
+    address_size: usize,
+    address: u64,
+    data: &[u8],
+) -> io::Result<()>
+where
+    Output: io::Write,
+{
+    let address_bytes = &(address as u32).to_be_bytes()[4 - address_size..];
+    let count = (address_size + data.len() + 1) as u8;
+    let sum = address_bytes
+        .iter()
+        .chain(data)
+        .fold(count, |sum, byte| sum.wrapping_add(*byte));
+
+    write!(output, "S{kind}{count:02X}")?;
+    for byte in address_bytes.iter().chain(data) {
+        write!(output, "{byte:02X}")?;
+    }
+    writeln!(output, "{:02X}", !sum)
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::{write, LoadImage};
+    use crate::image::Chunk;
+
+    #[test]
+    fn records() {
+        let bytes = [0x0A, 0x0A, 0x0D, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        let image = LoadImage {
+            chunks: vec![Chunk {
+                address: 0x7AF0,
+                bytes: &bytes,
+            }],
+            entry: 0,
+        };
+
+        let mut output = vec![];
+        write(&image, &mut output).expect("Records are written");
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "S0030000FC\n\
+             S1137AF00A0A0D0000000000000000000000000061\n\
+             S9030000FC\n",
+        );
+    }
+}
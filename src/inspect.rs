@@ -1,6 +1,23 @@
-///! Helpers for inspection of the input ELF.
+//! Helpers for inspection of the input ELF.
 
-use goblin::{elf::Elf, strtab::Strtab};
+use std::{collections::HashMap, ops::Range};
+
+use goblin::{
+    container::Ctx,
+    elf::{
+        program_header::{
+            PT_DYNAMIC, PT_GNU_EH_FRAME, PT_GNU_RELRO, PT_GNU_STACK, PT_LOAD, PT_TLS,
+        },
+        section_header::{
+            SHF_ALLOC, SHF_TLS, SHT_GNU_VERDEF, SHT_GNU_VERNEED, SHT_NOBITS, SHT_NULL,
+        },
+        symver::VER_FLG_BASE,
+        Elf, Header, ProgramHeader, SectionHeader, Sym, Symtab,
+    },
+    strtab::Strtab,
+};
+use scroll::ctx::SizeWith as _;
+use serde::Serialize;
 
 pub fn find_in_strtab(strtab: &Strtab, target: &str) -> Option<usize> {
     for i in 0..strtab.len() {
@@ -16,15 +33,68 @@ pub fn find_in_strtab(strtab: &Strtab, target: &str) -> Option<usize> {
     None
 }
 
+/// Part of the file that holds a known structure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FileRegion {
+    ElfHeader,
+    ProgramHeaders,
+    Section(usize),
+    SectionHeaders,
+}
+
+/// All the known structures that occupy space in the file, with their byte ranges, in the file
+/// offset order.
+///
+/// `SHT_NULL` and `SHT_NOBITS` sections, as well as empty header tables are not included, as they
+/// do not occupy any space in the file.  Ranges are computed with saturating arithmetic, so for a
+/// malformed file they could extend all the way to `u64::MAX`.
+pub fn file_regions(
+    header: &Header,
+    section_headers: &[SectionHeader],
+    ctx: Ctx,
+) -> Vec<(FileRegion, Range<u64>)> {
+    let mut regions = vec![(FileRegion::ElfHeader, 0..Header::size_with(&ctx) as u64)];
+
+    let program_headers_size = u64::from(header.e_phentsize) * u64::from(header.e_phnum);
+    if program_headers_size != 0 {
+        regions.push((
+            FileRegion::ProgramHeaders,
+            header.e_phoff..header.e_phoff.saturating_add(program_headers_size),
+        ));
+    }
+
+    regions.extend(
+        section_headers
+            .iter()
+            .enumerate()
+            .filter(|(_, header)| header.sh_type != SHT_NULL && header.sh_type != SHT_NOBITS)
+            .map(|(index, header)| {
+                (
+                    FileRegion::Section(index),
+                    header.sh_offset..header.sh_offset.saturating_add(header.sh_size),
+                )
+            }),
+    );
+
+    let section_headers_size = u64::from(header.e_shentsize) * u64::from(header.e_shnum);
+    if section_headers_size != 0 {
+        regions.push((
+            FileRegion::SectionHeaders,
+            header.e_shoff..header.e_shoff.saturating_add(section_headers_size),
+        ));
+    }
+
+    regions.sort_by_key(|(_, range)| (range.start, range.end));
+    regions
+}
+
 pub struct SymbolInfo {
     pub offset: u64,
     pub size: u64,
 }
 
 pub fn find_current_entrypoint(elf: &Elf) -> Option<SymbolInfo> {
-    let Some(entrypoint_st_name) = find_in_strtab(&elf.dynstrtab, "entrypoint") else {
-        return None;
-    };
+    let entrypoint_st_name = find_in_strtab(&elf.dynstrtab, "entrypoint")?;
 
     elf.dynsyms
         .iter()
@@ -34,3 +104,463 @@ pub fn find_current_entrypoint(elf: &Elf) -> Option<SymbolInfo> {
             size: symbol.st_size,
         })
 }
+
+/// File content of a section, or an empty slice if the section does not occupy space in the file,
+/// or points outside of it.
+pub fn section_bytes<'input>(bytes: &'input [u8], header: &SectionHeader) -> &'input [u8] {
+    if header.sh_type == SHT_NOBITS {
+        return &[];
+    }
+
+    let range = usize::try_from(header.sh_offset).ok().and_then(|start| {
+        let end = start.checked_add(usize::try_from(header.sh_size).ok()?)?;
+        Some(start..end)
+    });
+    range.and_then(|range| bytes.get(range)).unwrap_or(&[])
+}
+
+/// An entry of a symbol table, with its name.
+#[derive(Debug, Clone)]
+pub struct NamedSymbol<'input> {
+    /// Index of the entry in the symbol table.
+    pub index: usize,
+    /// `None` if the name can not be read from the string table.
+    pub name: Option<&'input str>,
+    pub symbol: Sym,
+}
+
+/// All the entries of the symbol table held in the `header` section, which should be a
+/// `SHT_SYMTAB` or a `SHT_DYNSYM` section.  Names are read from the string table section `sh_link`
+/// points to.
+pub fn section_symbols<'input>(
+    input_bytes: &'input [u8],
+    elf: &Elf,
+    ctx: Ctx,
+    header: &SectionHeader,
+) -> Result<Vec<NamedSymbol<'input>>, String> {
+    let Some(strtab_header) = elf.section_headers.get(header.sh_link as usize) else {
+        return Err(format!(
+            "String table section index is out of range: {}",
+            header.sh_link
+        ));
+    };
+    let strings = section_bytes(input_bytes, strtab_header);
+    let strtab = Strtab::parse(strings, 0, strings.len(), 0)
+        .map_err(|err| format!("Failed to parse the string table.\nError: {err}"))?;
+
+    let count = section_bytes(input_bytes, header).len() / Sym::size(ctx.container);
+    let symbols = Symtab::parse(input_bytes, header.sh_offset as usize, count, ctx)
+        .map_err(|err| format!("Failed to parse the symbol table.\nError: {err}"))?;
+
+    Ok(symbols
+        .iter()
+        .enumerate()
+        .map(|(index, symbol)| NamedSymbol {
+            index,
+            name: strtab.get_at(symbol.st_name),
+            symbol,
+        })
+        .collect())
+}
+
+/// Version of a dynamic symbol, as recorded in `.gnu.version`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct SymbolVersion<'input> {
+    pub name: &'input str,
+    /// The version is required from another file, via `.gnu.version_r`, rather than defined in
+    /// this file, via `.gnu.version_d`.
+    pub required: bool,
+    /// Hidden versions are not used by the static linker.  For a defined symbol this means the
+    /// version is not the default one.
+    pub hidden: bool,
+}
+
+/// Symbol versions defined in `.gnu.version_d` and required in `.gnu.version_r`, keyed by the
+/// version indices used in `.gnu.version`.  None of the returned versions are hidden.
+///
+/// The base version definition holds the file name, rather than a version, and is not included.
+pub fn symbol_versions<'input>(
+    input_bytes: &'input [u8],
+    elf: &Elf,
+) -> HashMap<u16, SymbolVersion<'input>> {
+    let linked_strtab = |sh_type| {
+        elf.section_headers
+            .iter()
+            .find(|header| header.sh_type == sh_type)
+            .and_then(|header| elf.section_headers.get(header.sh_link as usize))
+            .and_then(|strtab_header| {
+                let strings = section_bytes(input_bytes, strtab_header);
+                Strtab::parse(strings, 0, strings.len(), 0).ok()
+            })
+    };
+
+    let mut res = HashMap::new();
+
+    if let (Some(verdef), Some(strtab)) = (&elf.verdef, linked_strtab(SHT_GNU_VERDEF)) {
+        for definition in verdef.iter() {
+            if definition.vd_flags & VER_FLG_BASE != 0 {
+                continue;
+            }
+            // The first auxiliary entry holds the version name, the rest name its parents.
+            let Some(aux) = definition.iter().next() else {
+                continue;
+            };
+            if let Some(name) = strtab.get_at(aux.vda_name) {
+                res.insert(
+                    definition.vd_ndx,
+                    SymbolVersion {
+                        name,
+                        required: false,
+                        hidden: false,
+                    },
+                );
+            }
+        }
+    }
+
+    if let (Some(verneed), Some(strtab)) = (&elf.verneed, linked_strtab(SHT_GNU_VERNEED)) {
+        for need in verneed.iter() {
+            for aux in need.iter() {
+                if let Some(name) = strtab.get_at(aux.vna_name) {
+                    res.insert(
+                        aux.vna_other,
+                        SymbolVersion {
+                            name,
+                            required: true,
+                            hidden: false,
+                        },
+                    );
+                }
+            }
+        }
+    }
+
+    res
+}
+
+/// Version of the `.dynsym` entry at `index`, given the `versions` from [`symbol_versions()`].
+/// `None` for local and unversioned global symbols, and for unknown versions.
+pub fn symbol_version<'input>(
+    elf: &Elf,
+    versions: &HashMap<u16, SymbolVersion<'input>>,
+    index: usize,
+) -> Option<SymbolVersion<'input>> {
+    let versym = elf.versym.as_ref()?.get_at(index)?;
+    if versym.is_local() || versym.is_global() {
+        return None;
+    }
+
+    Some(SymbolVersion {
+        hidden: versym.is_hidden(),
+        ..*versions.get(&versym.version())?
+    })
+}
+
+/// Demangled form of a Rust, legacy or v0, or a C++ symbol name.  `None` if the name is not
+/// mangled, or is not valid for either scheme.
+///
+/// Hashes at the end of the legacy Rust symbol names are omitted.
+pub fn demangle(name: &str) -> Option<String> {
+    // Legacy Rust names are valid C++ names as well, so Rust is tried first.
+    if let Ok(demangled) = rustc_demangle::try_demangle(name) {
+        return Some(format!("{demangled:#}"));
+    }
+
+    if !name.starts_with("_Z") {
+        return None;
+    }
+    cpp_demangle::Symbol::new(name).ok()?.demangle().ok()
+}
+
+/// Checks if the section is placed inside the program header, following the same rules `readelf`
+/// uses for its section to segment mapping.
+///
+/// Sections have to be fully inside the segment file range, and, if they are loaded, inside the
+/// segment memory range.  Empty sections have to start strictly inside the segment, so that they
+/// are not attributed to the neighbouring segment.
+pub fn section_in_segment(section: &SectionHeader, segment: &ProgramHeader) -> bool {
+    if !could_hold(section, segment) {
+        return false;
+    }
+
+    let SectionHeader {
+        sh_type,
+        sh_addr,
+        sh_offset,
+        sh_size,
+        ..
+    } = *section;
+    let ProgramHeader {
+        p_offset,
+        p_vaddr,
+        p_filesz,
+        p_memsz,
+        ..
+    } = *segment;
+
+    if sh_type != SHT_NOBITS && !range_within(sh_offset, sh_size, p_offset, p_filesz) {
+        return false;
+    }
+
+    !is_alloc(section) || range_within(sh_addr, sh_size, p_vaddr, p_memsz)
+}
+
+/// Checks if only a part of the section is inside the program header.  Such sections are not
+/// reported by [`section_in_segment()`], but likely indicate a broken layout.
+pub fn section_partially_in_segment(section: &SectionHeader, segment: &ProgramHeader) -> bool {
+    if !could_hold(section, segment) || section_in_segment(section, segment) {
+        return false;
+    }
+
+    let SectionHeader {
+        sh_type,
+        sh_addr,
+        sh_offset,
+        sh_size,
+        ..
+    } = *section;
+    let ProgramHeader {
+        p_offset,
+        p_vaddr,
+        p_filesz,
+        p_memsz,
+        ..
+    } = *segment;
+
+    (sh_type != SHT_NOBITS && ranges_overlap(sh_offset, sh_size, p_offset, p_filesz))
+        || (is_alloc(section) && ranges_overlap(sh_addr, sh_size, p_vaddr, p_memsz))
+}
+
+fn is_alloc(section: &SectionHeader) -> bool {
+    section.sh_flags & u64::from(SHF_ALLOC) != 0
+}
+
+/// Checks if the section and the segment kinds allow the section to be part of the segment, not
+/// looking at their placement.
+fn could_hold(section: &SectionHeader, segment: &ProgramHeader) -> bool {
+    let SectionHeader {
+        sh_type, sh_flags, ..
+    } = *section;
+    let p_type = segment.p_type;
+
+    let is_tls = sh_flags & u64::from(SHF_TLS) != 0;
+
+    if sh_type == SHT_NULL {
+        return false;
+    }
+
+    // `PT_TLS` holds only the thread local sections, and those could otherwise only be part of the
+    // segments that load them.
+    if is_tls != (p_type == PT_TLS) && !matches!(p_type, PT_LOAD | PT_GNU_RELRO) {
+        return false;
+    }
+
+    // `.tbss` has no space allocated for it in the loaded image, only in the TLS template.
+    if is_tls && sh_type == SHT_NOBITS && p_type != PT_TLS {
+        return false;
+    }
+
+    // Sections that are not loaded could only be part of the segments that are not loaded either.
+    is_alloc(section)
+        || !matches!(
+            p_type,
+            PT_LOAD | PT_DYNAMIC | PT_GNU_EH_FRAME | PT_GNU_RELRO | PT_GNU_STACK
+        )
+}
+
+/// Checks that `start..start + size` is inside `outer_start..outer_start + outer_size`.  An empty
+/// range has to start strictly before the outer range end.
+fn range_within(start: u64, size: u64, outer_start: u64, outer_size: u64) -> bool {
+    let Some(offset) = start.checked_sub(outer_start) else {
+        return false;
+    };
+
+    if size == 0 {
+        return offset < outer_size;
+    }
+
+    offset
+        .checked_add(size)
+        .is_some_and(|end| end <= outer_size)
+}
+
+/// Checks if two non-empty ranges share at least one byte.
+fn ranges_overlap(start: u64, size: u64, other_start: u64, other_size: u64) -> bool {
+    size != 0
+        && other_size != 0
+        && start < other_start.saturating_add(other_size)
+        && other_start < start.saturating_add(size)
+}
+
+/// Matches `text` against a shell style `pattern`, where `*` matches any sequence of characters,
+/// including an empty one, and `?` matches exactly one character.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let text = text.chars().collect::<Vec<_>>();
+
+    let (mut p, mut t) = (0, 0);
+    // Position right after the last `*` seen in the pattern, and the text position it was matched
+    // at.  On a mismatch, the `*` is extended by one more character.
+    let mut backtrack = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                p += 1;
+                backtrack = Some((p, t));
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => {
+                let Some((star_p, star_t)) = backtrack else {
+                    return false;
+                };
+                p = star_p;
+                t = star_t + 1;
+                backtrack = Some((star_p, t));
+            }
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use goblin::elf::{
+        program_header::{PT_LOAD, PT_NOTE, PT_TLS},
+        section_header::{SHF_ALLOC, SHF_TLS, SHF_WRITE, SHT_NOBITS, SHT_NOTE, SHT_PROGBITS},
+        ProgramHeader, SectionHeader,
+    };
+
+    use super::{demangle, glob_match, section_in_segment, section_partially_in_segment};
+
+    #[test]
+    fn glob() {
+        assert!(glob_match(".text", ".text"));
+        assert!(!glob_match(".text", ".text.unlikely"));
+        assert!(glob_match(".text*", ".text.unlikely"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("*_start*", "__libc_start_main"));
+        assert!(glob_match(".rela.???", ".rela.dyn"));
+        assert!(!glob_match(".rela.???", ".rela.plt.got"));
+        assert!(glob_match("a*b*c", "aXbYbZc"));
+        assert!(!glob_match("a*b*c", "aXbYbZ"));
+    }
+
+    #[test]
+    fn demangled_names() {
+        assert_eq!(
+            demangle("_ZN4core3fmt5write17h0123456789abcdefE").as_deref(),
+            Some("core::fmt::write")
+        );
+        assert_eq!(
+            demangle("_RNvCs1234_7mycrate3foo").as_deref(),
+            Some("mycrate::foo")
+        );
+        assert_eq!(
+            demangle("_ZNSt6vectorIiSaIiEE9push_backERKi").as_deref(),
+            Some("std::vector<int, std::allocator<int> >::push_back(int const&)")
+        );
+        assert_eq!(demangle("_Z3addii").as_deref(), Some("add(int, int)"));
+        assert_eq!(demangle("main"), None);
+        assert_eq!(demangle("_Zinvalid"), None);
+    }
+
+    fn section(
+        sh_type: u32,
+        sh_flags: u32,
+        sh_addr: u64,
+        sh_offset: u64,
+        sh_size: u64,
+    ) -> SectionHeader {
+        SectionHeader {
+            sh_type,
+            sh_flags: sh_flags.into(),
+            sh_addr,
+            sh_offset,
+            sh_size,
+            ..SectionHeader::default()
+        }
+    }
+
+    fn segment(
+        p_type: u32,
+        p_offset: u64,
+        p_vaddr: u64,
+        p_filesz: u64,
+        p_memsz: u64,
+    ) -> ProgramHeader {
+        ProgramHeader {
+            p_type,
+            p_offset,
+            p_vaddr,
+            p_filesz,
+            p_memsz,
+            ..ProgramHeader::default()
+        }
+    }
+
+    #[test]
+    fn sections_in_segments() {
+        let load = segment(PT_LOAD, 0x1000, 0x2000, 0x100, 0x200);
+        let data = section(SHT_PROGBITS, SHF_ALLOC | SHF_WRITE, 0x2010, 0x1010, 0x10);
+        let bss = section(SHT_NOBITS, SHF_ALLOC | SHF_WRITE, 0x2100, 0x1100, 0x100);
+        let past_end = section(SHT_PROGBITS, SHF_ALLOC, 0x20f8, 0x10f8, 0x10);
+        let empty_at_end = section(SHT_PROGBITS, SHF_ALLOC, 0x2100, 0x1100, 0);
+        let note = section(SHT_NOTE, 0, 0, 0x1010, 0x10);
+
+        assert!(section_in_segment(&data, &load));
+        assert!(section_in_segment(&bss, &load));
+        assert!(!section_in_segment(&past_end, &load));
+        assert!(!section_in_segment(&empty_at_end, &load));
+        // Not loaded sections are not part of `PT_LOAD`, but could be in `PT_NOTE`.
+        assert!(!section_in_segment(&note, &load));
+        assert!(section_in_segment(
+            &note,
+            &segment(PT_NOTE, 0x1000, 0, 0x100, 0)
+        ));
+
+        let tls = segment(PT_TLS, 0x1000, 0x2000, 0x10, 0x20);
+        let tdata = section(
+            SHT_PROGBITS,
+            SHF_ALLOC | SHF_WRITE | SHF_TLS,
+            0x2000,
+            0x1000,
+            0x10,
+        );
+        let tbss = section(
+            SHT_NOBITS,
+            SHF_ALLOC | SHF_WRITE | SHF_TLS,
+            0x2010,
+            0x1010,
+            0x10,
+        );
+        assert!(section_in_segment(&tdata, &tls));
+        assert!(section_in_segment(&tbss, &tls));
+        assert!(section_in_segment(&tdata, &load));
+        assert!(!section_in_segment(&tbss, &load));
+        assert!(!section_in_segment(&data, &tls));
+    }
+
+    #[test]
+    fn sections_partially_in_segments() {
+        let load = segment(PT_LOAD, 0x1000, 0x2000, 0x100, 0x200);
+        let inside = section(SHT_PROGBITS, SHF_ALLOC, 0x2010, 0x1010, 0x10);
+        let past_end = section(SHT_PROGBITS, SHF_ALLOC, 0x20f8, 0x10f8, 0x10);
+        let before = section(SHT_PROGBITS, SHF_ALLOC, 0x1ff0, 0xff0, 0x10);
+        let not_loaded = section(SHT_NOTE, 0, 0, 0x10f8, 0x10);
+
+        assert!(!section_partially_in_segment(&inside, &load));
+        assert!(section_partially_in_segment(&past_end, &load));
+        assert!(!section_partially_in_segment(&before, &load));
+        assert!(!section_partially_in_segment(&not_loaded, &load));
+
+        // `PT_TLS` memory size includes `.tbss`, that overlaps the sections that follow `.tdata`.
+        let tls = segment(PT_TLS, 0x1000, 0x2000, 0x10, 0x20);
+        let after_tdata = section(SHT_PROGBITS, SHF_ALLOC | SHF_WRITE, 0x2010, 0x1010, 0x10);
+        assert!(!section_partially_in_segment(&after_tdata, &tls));
+    }
+}
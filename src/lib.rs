@@ -0,0 +1,16 @@
+//! Reading, verifying and editing of ELF files.
+//!
+//! The `elf-editor` binary is a command line interface on top of this library.  Custom edits can
+//! be built using [`document::ElfDocument`], or by combining section transformers from
+//! [`transformer::combinators`] and passing them to [`transformer::transform_elf_sections()`].
+//! New files are assembled with [`builder::ElfBuilder`], and existing files are compared with
+//! [`compare::compare_elfs()`].
+
+pub mod builder;
+pub mod compare;
+pub mod document;
+pub mod image;
+pub mod inspect;
+pub mod output_check;
+pub mod structure;
+pub mod transformer;
@@ -1,49 +1,119 @@
-use std::{fs, io};
+use std::{fs, fs::File, io, path::Path, process::ExitCode};
 
 use clap::Parser as _;
 use goblin::{container::Ctx, elf::Elf};
+use memmap2::Mmap;
 
-use structure::verify_elf_structure;
+use elf_editor::structure::verify_elf_structure;
 
 mod args;
-mod inspect;
+mod create;
+mod diff;
+mod extract;
 mod modify;
 mod show;
-mod structure;
-mod transformer;
+mod verify;
 
-fn main() -> io::Result<()> {
+fn main() -> io::Result<ExitCode> {
     let args::Args {
         input: input_path,
+        format,
         command,
     } = args::Args::parse();
 
-    let input_bytes = fs::read(input_path)?;
+    let input_file = File::open(&input_path)?;
+    // SAFETY: The input is expected to stay unchanged while we are running.  Same as any other
+    // tool that reads files, we can not prevent other processes from modifying it.  We do make
+    // sure we never write into the input file ourselves.
+    let input_bytes = unsafe { Mmap::map(&input_file)? };
 
-    let elf = Elf::parse(&input_bytes).expect("Input is an ELF");
+    // Input of the `create` commands is not an ELF file.
+    let command = match command {
+        args::Command::Create(args) => {
+            if output_is_input(&input_path, &args.output)? {
+                return Ok(ExitCode::FAILURE);
+            }
 
-    let ctx = Ctx {
-        container: elf
-            .header
-            .container()
-            .expect("Input ELF header has a valid size"),
-        le: elf
-            .header
-            .endianness()
-            .expect("Input ELF header has a valid endianness"),
+            if !create::run(&input_bytes, args) {
+                return Ok(ExitCode::FAILURE);
+            }
+            return Ok(ExitCode::SUCCESS);
+        }
+        command => command,
+    };
+
+    let elf = match Elf::parse(&input_bytes) {
+        Ok(elf) => elf,
+        Err(err) => {
+            println!("Input is not a valid ELF file.\nError: {err}");
+            return Ok(ExitCode::FAILURE);
+        }
     };
 
+    let (Ok(container), Ok(le)) = (elf.header.container(), elf.header.endianness()) else {
+        println!("Input ELF header has an invalid class or endianness.");
+        return Ok(ExitCode::FAILURE);
+    };
+    let ctx = Ctx { container, le };
+
     match command {
-        args::Command::Show(args) => show::run(&input_bytes, &elf, ctx, args),
+        args::Command::Show(args) => show::run(&input_bytes, &elf, ctx, args, format),
         args::Command::Modify(args) => {
-            if let Err(err) = verify_elf_structure(&input_bytes, &elf, ctx) {
+            if let Err(err) =
+                verify_elf_structure(&input_bytes, &elf, ctx, args.unknown_bytes.into())
+            {
                 println!("Unsupported ELF structure:\n{err}");
-                return Ok(());
+                return Ok(ExitCode::FAILURE);
             };
 
-            modify::run(&input_bytes, &elf, ctx, args);
+            if output_is_input(&input_path, &args.output)? {
+                return Ok(ExitCode::FAILURE);
+            }
+
+            if !modify::run(&input_bytes, &elf, ctx, args) {
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+        args::Command::Verify(args) => {
+            if !verify::run(&input_bytes, &elf, ctx, args) {
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+        args::Command::Extract(args) => {
+            if output_is_input(&input_path, args.output())? {
+                return Ok(ExitCode::FAILURE);
+            }
+
+            if !extract::run(&input_bytes, &elf, args) {
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+        args::Command::Diff(args) => {
+            let other_file = File::open(&args.other)?;
+            // SAFETY: Same as for the input file above.
+            let other_bytes = unsafe { Mmap::map(&other_file)? };
+
+            if !diff::run(&input_bytes, &elf, ctx, &other_bytes, format) {
+                return Ok(ExitCode::FAILURE);
+            }
         }
+        args::Command::Create(_) => unreachable!("`create` commands are handled above"),
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Input is memory mapped, so it must not be truncated by the output file creation.  Prints an
+/// explanation and returns `true` if the output path points to the input file.
+fn output_is_input(input_path: &Path, output_path: &Path) -> io::Result<bool> {
+    let input_path = fs::canonicalize(input_path)?;
+    if fs::canonicalize(output_path).is_ok_and(|output_path| output_path == input_path) {
+        println!(
+            "Output file must be different from the input file: {}",
+            input_path.to_string_lossy(),
+        );
+        return Ok(true);
     }
 
-    Ok(())
+    Ok(false)
 }
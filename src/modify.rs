@@ -1,25 +1,43 @@
-use std::{fs::File, io};
-
-use goblin::{
-    container::Ctx,
-    elf::{Elf, SectionHeader},
+use std::{
+    fs::{self, File},
+    io::BufWriter,
+    path::Path,
 };
 
+use goblin::{container::Ctx, elf::Elf};
+use memmap2::Mmap;
+
+use elf_editor::{output_check::check_output, structure::UnknownBytes, transformer::LayoutOptions};
+
 use crate::args::modify::{ModifyArgs, ModifyCommand};
 
 mod dyn_sym;
 
+/// Confirms that the parsed output file contains the change made by a modify command.
+type ConfirmEdit = dyn Fn(&[u8], &Elf, Ctx) -> Result<(), String>;
+
+/// What a modify command has changed, used to check the output file.
+pub struct Edit {
+    /// Indices of the sections with content changed by the command.
+    changed_sections: Vec<usize>,
+    confirm: Box<ConfirmEdit>,
+}
+
+/// Returns `true` if the output file was produced and passed the self-check.  Otherwise, the
+/// output file is removed.
 pub fn run(
     input_bytes: &[u8],
     elf: &Elf,
     ctx: Ctx,
     ModifyArgs {
         output: output_path,
+        layout,
+        unknown_bytes,
         command,
     }: ModifyArgs,
-) {
+) -> bool {
     let output = match File::create(&output_path) {
-        Ok(output) => output,
+        Ok(output) => BufWriter::new(output),
         Err(err) => {
             println!(
                 "Failed to open the output file: {}\n\
@@ -27,29 +45,154 @@ pub fn run(
                 output_path.to_string_lossy(),
                 err,
             );
-            return;
+            return false;
         }
     };
 
-    match command {
-        ModifyCommand::DynSym(args) => dyn_sym::run(input_bytes, elf, ctx, output, args),
+    let unknown_bytes = unknown_bytes.into();
+    let layout = LayoutOptions {
+        strategy: layout.into(),
+        unknown_bytes,
+    };
+
+    let edit = match command {
+        ModifyCommand::DynSym(args) => dyn_sym::run(input_bytes, elf, ctx, layout, output, args),
+    };
+
+    keep_checked_output(input_bytes, elf, ctx, unknown_bytes, &output_path, edit)
+}
+
+/// Returns `true` if the output file written for the `edit` passes the self-check.  Otherwise, or
+/// if there is no `edit`, as the command has failed, the output file is removed.
+fn keep_checked_output(
+    input_bytes: &[u8],
+    elf: &Elf,
+    ctx: Ctx,
+    unknown_bytes: UnknownBytes,
+    output_path: &Path,
+    edit: Option<Edit>,
+) -> bool {
+    let Some(edit) = edit else {
+        remove_output(output_path);
+        return false;
+    };
+
+    if let Err(err) = check_output_file(input_bytes, elf, ctx, unknown_bytes, output_path, edit) {
+        println!("Output file failed the self-check:\n{err}");
+        remove_output(output_path);
+        return false;
+    }
+
+    true
+}
+
+/// Parses the output file back and checks that it is consistent with the input, and contains the
+/// `edit`.
+fn check_output_file(
+    input_bytes: &[u8],
+    elf: &Elf,
+    ctx: Ctx,
+    unknown_bytes: UnknownBytes,
+    output_path: &Path,
+    Edit {
+        changed_sections,
+        confirm,
+    }: Edit,
+) -> Result<(), String> {
+    let output_file = File::open(output_path)
+        .map_err(|err| format!("Failed to open the output file for reading.\nError: {err}"))?;
+    // SAFETY: Output file has just been written by us, and is not expected to change.
+    let output_bytes = unsafe { Mmap::map(&output_file) }
+        .map_err(|err| format!("Failed to read the output file.\nError: {err}"))?;
+
+    let output_elf = Elf::parse(&output_bytes)
+        .map_err(|err| format!("Output is not a valid ELF.\nError: {err}"))?;
+
+    check_output(
+        input_bytes,
+        elf,
+        &output_bytes,
+        &output_elf,
+        ctx,
+        unknown_bytes,
+        &changed_sections,
+    )?;
+
+    confirm(&output_bytes, &output_elf, ctx)
+}
+
+pub fn remove_output(output_path: &Path) {
+    match fs::remove_file(output_path) {
+        Ok(()) => println!("Removed the output file: {}", output_path.to_string_lossy()),
+        Err(err) => println!(
+            "Failed to remove the output file: {}\n\
+             Error: {}",
+            output_path.to_string_lossy(),
+            err,
+        ),
     }
 }
 
-#[allow(unused)]
-fn keep_all_sections_as_is() -> Box<
-    impl for<'bytes, 'header, 'output> Fn(
-        /* input_bytes: */ &'bytes [u8],
-        /* section_header: */ &'header SectionHeader,
-        /* ctx: */ Ctx,
-        /* output: */ &'output mut dyn io::Write,
-    ) -> Option<u64>,
-> {
-    Box::new(
-        move |_input_bytes: &[u8],
-              _section_headers: &SectionHeader,
-              _ctx: Ctx,
-              _output: &mut dyn io::Write|
-              -> Option<u64> { None },
-    )
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, path::Path, process};
+
+    use goblin::{container::Ctx, elf::Elf};
+
+    use elf_editor::structure::UnknownBytes;
+
+    use super::{keep_checked_output, Edit};
+
+    // Edit that claims to change no sections.
+    fn no_op_edit() -> Option<Edit> {
+        Some(Edit {
+            changed_sections: vec![],
+            confirm: Box::new(|_, _, _| Ok(())),
+        })
+    }
+
+    #[test]
+    fn failed_self_check_removes_output() {
+        let input_path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/x86_64.so");
+        let input_bytes = fs::read(input_path).expect("Fixture is readable");
+        let elf = Elf::parse(&input_bytes).expect("Fixture is a valid ELF");
+        let ctx = Ctx::new(
+            elf.header.container().expect("Valid ELF class"),
+            elf.header.endianness().expect("Valid ELF endianness"),
+        );
+        let text = elf
+            .section_headers
+            .iter()
+            .find(|header| elf.shdr_strtab.get_at(header.sh_name) == Some(".text"))
+            .expect("Fixture has a .text section");
+        let output_path = env::temp_dir().join(format!("elf-editor-self-check-{}", process::id()));
+
+        fs::write(&output_path, &input_bytes).expect("Output is written");
+        assert!(keep_checked_output(
+            &input_bytes,
+            &elf,
+            ctx,
+            UnknownBytes::Reject,
+            &output_path,
+            no_op_edit(),
+        ));
+        assert!(output_path.exists(), "Unchanged output is kept");
+
+        // `.text` is changed, while the edit claims that no sections were.
+        let mut output_bytes = input_bytes.clone();
+        output_bytes[text.sh_offset as usize] ^= 0xff;
+        fs::write(&output_path, &output_bytes).expect("Output is written");
+        assert!(!keep_checked_output(
+            &input_bytes,
+            &elf,
+            ctx,
+            UnknownBytes::Reject,
+            &output_path,
+            no_op_edit(),
+        ));
+        assert!(
+            !output_path.exists(),
+            "Output that fails the check is removed"
+        );
+    }
 }
@@ -1,120 +1,210 @@
-use std::{fs::File, io};
+use std::{fs::File, io::BufWriter};
 
 use goblin::{
     container::Ctx,
-    elf::{self, Elf, SectionHeader},
+    elf::{self, dynamic::DT_STRSZ, section_header::SHT_DYNSYM, Elf, Symtab},
 };
-use scroll::{ctx::SizeWith, IOwrite};
+
+use elf_editor::{document::ElfDocument, transformer::LayoutOptions};
 
 use crate::{
     args::modify::dyn_sym::{add::AddArgs, remove::RemoveArgs, DynSymArgs},
-    inspect::find_in_strtab,
-    transformer::transform_elf_sections,
+    modify::Edit,
 };
 
-pub fn run(input_bytes: &[u8], elf: &Elf, ctx: Ctx, output: File, args: DynSymArgs) {
+pub fn run(
+    input_bytes: &[u8],
+    elf: &Elf,
+    ctx: Ctx,
+    layout: LayoutOptions,
+    output: BufWriter<File>,
+    args: DynSymArgs,
+) -> Option<Edit> {
     match args {
-        DynSymArgs::Add(args) => add(input_bytes, elf, ctx, output, args),
-        DynSymArgs::Remove(args) => remove(input_bytes, elf, ctx, output, args),
+        DynSymArgs::Add(args) => add(input_bytes, elf, ctx, layout, output, args),
+        DynSymArgs::Remove(args) => remove(input_bytes, elf, ctx, layout, output, args),
     }
 }
 
-fn add(input_bytes: &[u8], elf: &Elf, ctx: Ctx, mut output: File, args: AddArgs) {
+fn add(
+    input_bytes: &[u8],
+    elf: &Elf,
+    ctx: Ctx,
+    layout: LayoutOptions,
+    output: BufWriter<File>,
+    args: AddArgs,
+) -> Option<Edit> {
+    let mut document = ElfDocument::new(input_bytes, elf, ctx);
+
+    let dynstr = document
+        .section_index(".dynstr")
+        .expect("Input ELF has a .dynstr section");
+    let dynsym = document
+        .section_index(".dynsym")
+        .expect("Input ELF has a .dynsym section");
+
+    let strings = document
+        .section_mut(dynstr)
+        .data
+        .as_strings_mut()
+        .expect(".dynstr is a string table");
     let symbol = elf::Sym {
-        // This will be populated by `append_to_dynsyms`.
-        st_name: 0,
+        st_name: strings.add(&args.name),
         st_info: args.info,
         st_other: args.other,
         st_shndx: args.shndx,
         st_value: args.value,
         st_size: args.size,
     };
+    let strings_size = strings.len() as u64;
+
+    document
+        .section_mut(dynsym)
+        .data
+        .as_symbols_mut()
+        .expect(".dynsym is a symbol table")
+        .push(symbol);
 
-    transform_elf_sections(
-        input_bytes,
-        elf,
-        ctx,
-        &mut output,
-        append_to_dynsyms(elf, &args.name, symbol),
-    );
+    if let Some(dynamic) = document.dynamic_mut() {
+        for entry in dynamic.iter_mut().filter(|entry| entry.d_tag == DT_STRSZ) {
+            entry.d_val = strings_size;
+        }
+    }
+
+    let lookup_tables = match document.update_symbol_lookup_tables() {
+        Ok(updated) => updated,
+        Err(err) => {
+            println!("Failed to update the symbol lookup tables:\n{err}");
+            return None;
+        }
+    };
+
+    if let Err(err) = document.write(output, layout) {
+        println!("Failed to lay out the output file:\n{err}");
+        return None;
+    }
+
+    let name = args.name;
+    Some(Edit {
+        changed_sections: [dynstr, dynsym].into_iter().chain(lookup_tables).collect(),
+        confirm: Box::new(move |output_bytes, output, ctx| {
+            confirm_symbol(output_bytes, output, ctx, &name, &symbol)
+        }),
+    })
 }
 
-fn remove(_input_bytes: &[u8], _elf: &Elf, _ctx: Ctx, _output: File, _args: RemoveArgs) {
-    todo!("TODO Not implemented yet");
+/// Checks that the `output` has the `expected` dynamic symbol, that can be found by its `name`.
+fn confirm_symbol(
+    output_bytes: &[u8],
+    output: &Elf,
+    ctx: Ctx,
+    name: &str,
+    expected: &elf::Sym,
+) -> Result<(), String> {
+    let mut named = lookup_symbols(output_bytes, output, ctx)?
+        .into_iter()
+        .filter(|symbol| output.dynstrtab.get_at(symbol.st_name) == Some(name))
+        .peekable();
+
+    if named.peek().is_none() {
+        return Err(format!(
+            "Output .dynsym does not contain a symbol named \"{name}\"."
+        ));
+    }
+
+    if !named.any(|symbol| symbol == *expected) {
+        return Err(format!(
+            "None of the output .dynsym symbols named \"{name}\" match the added one.\n\
+             Expected: {expected:?}",
+        ));
+    }
+
+    Ok(())
 }
 
-/// `symbol.st_name` should be `0`.  It will be replaced by a reference to a new `.dynstr` entry
-/// that will hold the `symbol_name` value.
-#[allow(unused)]
-fn append_to_dynsyms<'symbol_name>(
-    elf: &Elf<'_>,
-    symbol_name: &'symbol_name str,
-    mut symbol: elf::Sym,
-) -> Box<
-    impl for<'bytes, 'header, 'output> Fn(
-            /* input_bytes: */ &'bytes [u8],
-            /* section_header: */ &'header SectionHeader,
-            /* ctx: */ Ctx,
-            /* output: */ &'output mut dyn io::Write,
-        ) -> Option<u64>
-        + 'symbol_name,
-> {
-    // We are going to append to the `.dynstr` string table, so the new string will start where the
-    // table currently ends.
-    let st_name = elf.dynstrtab.len();
-    symbol.st_name = st_name;
-
-    let dynstr_sh_name =
-        find_in_strtab(&elf.shdr_strtab, ".dynstr").expect("Input ELF has a .dynstr section");
-    let dynsym_sh_name =
-        find_in_strtab(&elf.shdr_strtab, ".dynsym").expect("Input ELF has a .dynsym section");
-
-    fn copy_existing_section_bytes(
-        output: &mut dyn io::Write,
-        input_bytes: &[u8],
-        sh_offset: u64,
-        sh_size: u64,
-    ) {
-        let input_start = sh_offset as usize;
-        let input_end = (sh_offset + sh_size) as usize;
-        output
-            .write_all(&input_bytes[input_start..input_end])
-            .expect("Output can consume all the produced data");
+/// Dynamic symbols of the `output`, as seen by a loader: via the hash table, if there is one.
+/// Otherwise, via the `.dynsym` section header, same as the Solana VM loader.
+fn lookup_symbols(output_bytes: &[u8], output: &Elf, ctx: Ctx) -> Result<Vec<elf::Sym>, String> {
+    let has_hash_table = output
+        .dynamic
+        .as_ref()
+        .is_some_and(|dynamic| dynamic.info.gnu_hash.is_some() || dynamic.info.hash.is_some());
+
+    if has_hash_table {
+        Ok(output.dynsyms.to_vec())
+    } else {
+        let header = output
+            .section_headers
+            .iter()
+            .find(|header| header.sh_type == SHT_DYNSYM)
+            .ok_or_else(|| "Output does not have a .dynsym section.".to_owned())?;
+        let count = header.sh_size as usize / elf::Sym::size(ctx.container);
+        Symtab::parse(output_bytes, header.sh_offset as usize, count, ctx)
+            .map(|symbols| symbols.to_vec())
+            .map_err(|err| format!("Failed to parse the output .dynsym section.\nError: {err}"))
     }
+}
+
+fn remove(
+    input_bytes: &[u8],
+    elf: &Elf,
+    ctx: Ctx,
+    layout: LayoutOptions,
+    output: BufWriter<File>,
+    args: RemoveArgs,
+) -> Option<Edit> {
+    let mut document = ElfDocument::new(input_bytes, elf, ctx);
+
+    let dynsym = document
+        .section_index(".dynsym")
+        .expect("Input ELF has a .dynsym section");
+
+    let Some(symbol_index) = document.symbol_index(dynsym, &args.name) else {
+        println!(
+            "Input .dynsym does not contain a symbol named \"{}\".",
+            args.name
+        );
+        return None;
+    };
 
-    let process = move |input_bytes: &[u8],
-                        SectionHeader {
-                            sh_name,
-                            sh_offset,
-                            sh_size,
-                            ..
-                        }: &SectionHeader,
-                        ctx: Ctx,
-                        output: &mut dyn io::Write|
-          -> Option<u64> {
-        if *sh_name == dynstr_sh_name {
-            copy_existing_section_bytes(output, input_bytes, *sh_offset, *sh_size);
-
-            output
-                .write_all(symbol_name.as_bytes())
-                .expect("Output can consume all the produced data");
-            output
-                .write_all(&[0])
-                .expect("Output can consume all the produced data");
-
-            Some(*sh_size + symbol_name.len() as u64 + 1)
-        } else if *sh_name == dynsym_sh_name {
-            copy_existing_section_bytes(output, input_bytes, *sh_offset, *sh_size);
-
-            output
-                .iowrite_with(symbol, ctx)
-                .expect("Output can consume all the produced data");
-
-            Some(*sh_size + elf::Sym::size_with(&ctx) as u64)
-        } else {
-            None
+    let changed_sections = match document.remove_symbol(dynsym, symbol_index) {
+        Ok(updated) => updated,
+        Err(err) => {
+            println!("Failed to remove the \"{}\" symbol:\n{err}", args.name);
+            return None;
         }
     };
 
-    Box::new(process)
+    if let Err(err) = document.write(output, layout) {
+        println!("Failed to lay out the output file:\n{err}");
+        return None;
+    }
+
+    let name = args.name;
+    Some(Edit {
+        changed_sections,
+        confirm: Box::new(move |output_bytes, output, ctx| {
+            confirm_no_symbol(output_bytes, output, ctx, &name)
+        }),
+    })
+}
+
+/// Checks that none of the `output` dynamic symbols is called `name`.
+fn confirm_no_symbol(
+    output_bytes: &[u8],
+    output: &Elf,
+    ctx: Ctx,
+    name: &str,
+) -> Result<(), String> {
+    let symbols = lookup_symbols(output_bytes, output, ctx)?;
+    if symbols
+        .iter()
+        .any(|symbol| output.dynstrtab.get_at(symbol.st_name) == Some(name))
+    {
+        return Err(format!(
+            "Output .dynsym still contains a symbol named \"{name}\"."
+        ));
+    }
+
+    Ok(())
 }
@@ -0,0 +1,251 @@
+//! Checks that a produced ELF file is consistent with the input it was produced from.
+//!
+//! Layout code is complex enough that it is worth double checking its result, before the output is
+//! handed to the user.
+
+use goblin::{
+    container::Ctx,
+    elf::{
+        program_header::PT_LOAD,
+        section_header::{SHF_ALLOC, SHT_DYNAMIC, SHT_NOBITS, SHT_NULL},
+        Elf, ProgramHeader, SectionHeader,
+    },
+};
+
+use crate::structure::{verify_elf_structure, UnknownBytes};
+
+/// Checks the `output_elf`, produced from the `input_elf` by changing the content of the
+/// `changed_sections`:
+///
+/// * Output structure passes [`verify_elf_structure()`].
+/// * Both files have the same sections, and all the sections, except for the `changed_sections`,
+///   have the same content.  `SHT_DYNAMIC` sections are not compared, as the layout code updates
+///   addresses stored in them.
+/// * Every allocated section that is loaded by a `PT_LOAD` segment in the input, is also loaded
+///   by a `PT_LOAD` segment in the output, at the address from its section header.
+///
+/// Returns an explanation of all the problems found, if there are any.
+pub fn check_output(
+    input_bytes: &[u8],
+    input_elf: &Elf,
+    output_bytes: &[u8],
+    output_elf: &Elf,
+    ctx: Ctx,
+    unknown_bytes: UnknownBytes,
+    changed_sections: &[usize],
+) -> Result<(), String> {
+    verify_elf_structure(output_bytes, output_elf, ctx, unknown_bytes)?;
+
+    let input_sections = &input_elf.section_headers;
+    let output_sections = &output_elf.section_headers;
+
+    if input_sections.len() != output_sections.len() {
+        return Err(format!(
+            "Output has {} sections, while input has {}.",
+            output_sections.len(),
+            input_sections.len(),
+        ));
+    }
+
+    let section_name = |index: usize| {
+        input_elf
+            .shdr_strtab
+            .get_at(input_sections[index].sh_name)
+            .unwrap_or("<invalid>")
+    };
+
+    let mut problems = vec![];
+
+    for index in changed_content(
+        input_bytes,
+        input_sections,
+        output_bytes,
+        output_sections,
+        changed_sections,
+    ) {
+        problems.push(format!(
+            "Section {} content differs from the input.",
+            section_name(index),
+        ));
+    }
+
+    for index in lost_load_coverage(
+        &input_elf.program_headers,
+        input_sections,
+        &output_elf.program_headers,
+        output_sections,
+    ) {
+        problems.push(format!(
+            "Section {} is not loaded by any PT_LOAD segment at its address, while it is in the \
+             input.",
+            section_name(index),
+        ));
+    }
+
+    if problems.is_empty() {
+        return Ok(());
+    }
+
+    Err(problems.join("\n"))
+}
+
+/// Indices of sections, other than the `changed_sections`, that have different content in the
+/// input and the output.
+fn changed_content(
+    input_bytes: &[u8],
+    input_sections: &[SectionHeader],
+    output_bytes: &[u8],
+    output_sections: &[SectionHeader],
+    changed_sections: &[usize],
+) -> Vec<usize> {
+    let content = |bytes: &'_ [u8], header: &SectionHeader| {
+        let start = header.sh_offset as usize;
+        let end = start.checked_add(header.sh_size as usize)?;
+        bytes.get(start..end).map(<[u8]>::to_vec)
+    };
+
+    input_sections
+        .iter()
+        .zip(output_sections)
+        .enumerate()
+        .filter(|(index, (input, _))| {
+            !changed_sections.contains(index)
+                && input.sh_type != SHT_NULL
+                && input.sh_type != SHT_NOBITS
+                && input.sh_type != SHT_DYNAMIC
+        })
+        .filter(|(_, (input, output))| {
+            input.sh_type != output.sh_type
+                || content(input_bytes, input) != content(output_bytes, output)
+        })
+        .map(|(index, _)| index)
+        .collect()
+}
+
+/// Indices of allocated sections that are loaded by a `PT_LOAD` segment in the input, but not in
+/// the output.
+fn lost_load_coverage(
+    input_program_headers: &[ProgramHeader],
+    input_sections: &[SectionHeader],
+    output_program_headers: &[ProgramHeader],
+    output_sections: &[SectionHeader],
+) -> Vec<usize> {
+    input_sections
+        .iter()
+        .zip(output_sections)
+        .enumerate()
+        .filter(|(_, (input, _))| {
+            input.sh_type != SHT_NULL && input.sh_flags & u64::from(SHF_ALLOC) != 0
+        })
+        .filter(|(_, (input, output))| {
+            is_loaded(input_program_headers, input) && !is_loaded(output_program_headers, output)
+        })
+        .map(|(index, _)| index)
+        .collect()
+}
+
+/// Checks if a `PT_LOAD` segment maps the section at its `sh_addr`.
+fn is_loaded(program_headers: &[ProgramHeader], section: &SectionHeader) -> bool {
+    let is_nobits = section.sh_type == SHT_NOBITS;
+
+    program_headers
+        .iter()
+        .filter(|header| header.p_type == PT_LOAD)
+        .any(|header| {
+            let in_memory = header.p_vaddr <= section.sh_addr
+                && section.sh_addr + section.sh_size <= header.p_vaddr + header.p_memsz;
+            in_memory
+                && (is_nobits
+                    || (header.p_offset <= section.sh_offset
+                        && section.sh_offset + section.sh_size
+                            <= header.p_offset + header.p_filesz
+                        && section.sh_offset - header.p_offset == section.sh_addr - header.p_vaddr))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use goblin::elf::{
+        program_header::PT_LOAD,
+        section_header::{SHF_ALLOC, SHT_PROGBITS},
+        ProgramHeader, SectionHeader,
+    };
+    use pretty_assertions::assert_eq;
+
+    use super::{changed_content, lost_load_coverage};
+
+    fn test_section_header(sh_offset: u64, sh_addr: u64, sh_size: u64) -> SectionHeader {
+        SectionHeader {
+            sh_type: SHT_PROGBITS,
+            sh_flags: u64::from(SHF_ALLOC),
+            sh_offset,
+            sh_addr,
+            sh_size,
+            ..SectionHeader::default()
+        }
+    }
+
+    fn test_load_header(p_offset: u64, p_vaddr: u64, p_filesz: u64) -> ProgramHeader {
+        ProgramHeader {
+            p_type: PT_LOAD,
+            p_offset,
+            p_vaddr,
+            p_filesz,
+            p_memsz: p_filesz,
+            ..ProgramHeader::default()
+        }
+    }
+
+    #[test]
+    fn moved_section_content_is_compared() {
+        let input_bytes = b"aaaabbbbcccc";
+        let output_bytes = b"aaaaXXXXbbbbcccC";
+        let input_sections = [
+            test_section_header(0, 0, 4),
+            test_section_header(4, 4, 4),
+            test_section_header(8, 8, 4),
+        ];
+        let output_sections = [
+            test_section_header(0, 0, 4),
+            test_section_header(8, 8, 4),
+            test_section_header(12, 12, 4),
+        ];
+
+        assert_eq!(
+            changed_content(
+                input_bytes,
+                &input_sections,
+                output_bytes,
+                &output_sections,
+                &[0],
+            ),
+            vec![2]
+        );
+    }
+
+    #[test]
+    fn section_moved_without_its_address() {
+        let input_program_headers = [test_load_header(0, 0x1000, 0x20)];
+        let input_sections = [
+            test_section_header(0x0, 0x1000, 0x10),
+            test_section_header(0x10, 0x1010, 0x10),
+        ];
+
+        // Second section moved in the file, but kept its address.
+        let output_program_headers = [test_load_header(0, 0x1000, 0x30)];
+        let output_sections = [
+            test_section_header(0x0, 0x1000, 0x10),
+            test_section_header(0x20, 0x1010, 0x10),
+        ];
+
+        assert_eq!(
+            lost_load_coverage(
+                &input_program_headers,
+                &input_sections,
+                &output_program_headers,
+                &output_sections,
+            ),
+            vec![1]
+        );
+    }
+}
@@ -1,20 +1,49 @@
-///! Helpers for inspection of the input ELF.
+//! Helpers for inspection of the input ELF.
+
+use std::{borrow::Cow, cmp::Reverse};
 
 use goblin::{
     container::Ctx,
-    elf::{self, Elf, Header, ProgramHeader, SectionHeader},
+    elf::{self, program_header, section_header, sym, Elf, Header, ProgramHeader, SectionHeader},
+    strtab::Strtab,
 };
 use scroll::ctx::SizeWith as _;
 
-use crate::{args::show::ShowArgs, inspect::{find_current_entrypoint, SymbolInfo}};
+use elf_editor::inspect::{
+    self, find_current_entrypoint, glob_match, section_in_segment, section_partially_in_segment,
+    section_symbols, symbol_version, symbol_versions, NamedSymbol, SymbolInfo, SymbolVersion,
+};
+
+use crate::args::{
+    show::{SectionsArgs, ShowArgs, SymbolOrder, SymbolsArgs, SymtabArgs},
+    OutputFormat,
+};
+
+mod json;
+
+pub fn run<'input>(
+    input_bytes: &'input [u8],
+    elf: &Elf<'input>,
+    ctx: Ctx,
+    args: ShowArgs,
+    format: OutputFormat,
+) {
+    if format == OutputFormat::Json {
+        json::run(input_bytes, elf, ctx, args);
+        return;
+    }
 
-pub fn run(input_bytes: &[u8], elf: &Elf, ctx: Ctx, args: ShowArgs) {
     match args {
         ShowArgs::Header => print_header(elf, ctx),
         ShowArgs::Layout => print_layout(input_bytes, elf, ctx),
-        ShowArgs::ProgramSections => print_program_sections(elf),
-        ShowArgs::FileSegments => print_file_segments(&elf),
-        ShowArgs::DynSym => print_dynsyms(elf),
+        ShowArgs::ProgramSections => {
+            print_program_sections(elf);
+            print_segment_sections(elf);
+        }
+        ShowArgs::FileSegments => print_file_segments(elf),
+        ShowArgs::Sections(args) => print_sections(elf, &args),
+        ShowArgs::DynSym(args) => print_dynsyms(input_bytes, elf, ctx, &args),
+        ShowArgs::Symtab(args) => print_symtab(input_bytes, elf, ctx, &args),
         ShowArgs::ShStrTab => print_shstrtab(elf),
         ShowArgs::Relocations => print_relocations(elf),
         ShowArgs::Entrypoint => print_entrypoint(elf),
@@ -22,7 +51,8 @@ pub fn run(input_bytes: &[u8], elf: &Elf, ctx: Ctx, args: ShowArgs) {
 }
 
 fn print_header(elf: &Elf, ctx: Ctx) {
-    println!("ELF header offsets: 0x{:0>16x} - 0x{:0>16x}",
+    println!(
+        "ELF header offsets: 0x{:0>16x} - 0x{:0>16x}",
         0,
         Header::size_with(&ctx)
     );
@@ -54,12 +84,12 @@ pub fn print_layout(input_bytes: &[u8], elf: &Elf, ctx: Ctx) {
         println!(
             "  0x{:0>16x} - 0x{:0>16x}",
             e_phoff,
-            e_phoff + u64::from(e_phentsize) * u64::from(e_phnum)
+            e_phoff.saturating_add(u64::from(e_phentsize) * u64::from(e_phnum))
         );
     }
 
-    print_program_sections(&elf);
-    print_file_segments(&elf);
+    print_program_sections(elf);
+    print_file_segments(elf);
 
     println!("File segment header table:");
     {
@@ -72,7 +102,7 @@ pub fn print_layout(input_bytes: &[u8], elf: &Elf, ctx: Ctx) {
         println!(
             "  0x{:0>16x} - 0x{:0>16x}",
             e_shoff,
-            e_shoff + u64::from(e_shentsize) * u64::from(e_shnum)
+            e_shoff.saturating_add(u64::from(e_shentsize) * u64::from(e_shnum))
         );
     }
 
@@ -83,26 +113,77 @@ fn print_program_sections(elf: &Elf) {
     println!("All programs sections byte offsets:");
     for ProgramHeader {
         p_type,
+        p_flags,
         p_offset,
         p_filesz,
         p_vaddr,
         p_paddr,
         p_memsz,
         p_align,
-        ..
     } in &elf.program_headers
     {
         println!(
-            "  {:16}: 0x{:0>16x} - 0x{:0>16x}, \
+            "  {:16}: 0x{:0>16x} - 0x{:0>16x}, flags: {}, \
              paddr: 0x{:0>16x}, vaddr: 0x{:0>16x}, memsz: 0x{:0>16x}, align: {}",
             elf::program_header::pt_to_str(*p_type),
             p_offset,
-            p_offset + p_filesz,
+            p_offset.saturating_add(*p_filesz),
+            segment_flags_name(*p_flags),
             p_paddr,
             p_vaddr,
             p_memsz,
             p_align,
         );
+
+        if *p_type == program_header::PT_INTERP {
+            match elf.interpreter {
+                Some(interpreter) => println!("    interpreter: \"{interpreter}\""),
+                None => println!("    interpreter: ---"),
+            }
+        }
+    }
+}
+
+/// `p_flags` as `R`, `W` and `X` letters, with a space for every flag that is not set.  For
+/// example, `R X`.
+fn segment_flags_name(p_flags: u32) -> String {
+    use program_header::{PF_R, PF_W, PF_X};
+
+    [(PF_R, 'R'), (PF_W, 'W'), (PF_X, 'X')]
+        .iter()
+        .map(|&(flag, letter)| if p_flags & flag != 0 { letter } else { ' ' })
+        .collect()
+}
+
+/// Sections each program section holds, similar to the `readelf -l` mapping.  Program sections
+/// that hold only a part of some section, or do not hold any sections, are marked.
+fn print_segment_sections(elf: &Elf) {
+    println!("Program section to section mapping:");
+    for (index, segment) in elf.program_headers.iter().enumerate() {
+        let names = |holds: fn(&SectionHeader, &ProgramHeader) -> bool| {
+            elf.section_headers
+                .iter()
+                .filter(|section| holds(section, segment))
+                .map(|section| section_name(elf, section).unwrap_or("---"))
+                .collect::<Vec<_>>()
+        };
+        let sections = names(section_in_segment);
+        let partial = names(section_partially_in_segment);
+
+        let sections = if sections.is_empty() {
+            "no sections".to_owned()
+        } else {
+            sections.join(" ")
+        };
+        println!(
+            "  [{:>2}] {:16}: {}",
+            index,
+            program_header::pt_to_str(segment.p_type),
+            sections,
+        );
+        if !partial.is_empty() {
+            println!("       partially holds: {}", partial.join(" "));
+        }
     }
 }
 
@@ -120,36 +201,424 @@ fn print_file_segments(elf: &Elf) {
             "  {:16}: 0x{:0>16x} - 0x{:0>16x}, align: {}",
             elf.shdr_strtab.get_at(*sh_name).unwrap_or("---"),
             sh_offset,
-            sh_offset + sh_size,
+            sh_offset.saturating_add(*sh_size),
             sh_addralign,
         );
     }
 }
 
-fn print_dynsyms(elf: &Elf) {
-    println!("Dynamic symbols ({}):", elf.dynsyms.len());
-    for symbol in elf.dynsyms.iter() {
-        println!(
-            "  {}:",
-            elf.dynstrtab.get_at(symbol.st_name).unwrap_or("---")
+fn print_sections(elf: &Elf, args: &SectionsArgs) {
+    let sections = selected_sections(elf, args);
+
+    println!(
+        "Section headers ({} of {}):",
+        sections.len(),
+        elf.section_headers.len()
+    );
+    println!(
+        "  [Nr] {:18} {:14} {:18} {:10} {:10} {:6} {:5} {:18} {:>4} {:>5} Segments",
+        "Name", "Type", "Address", "Offset", "Size", "EntSz", "Flags", "Link", "Info", "Align",
+    );
+    for (index, header) in sections {
+        let SectionHeader {
+            sh_type,
+            sh_flags,
+            sh_addr,
+            sh_offset,
+            sh_size,
+            sh_link,
+            sh_info,
+            sh_addralign,
+            sh_entsize,
+            ..
+        } = *header;
+
+        let link = match linked_section_name(elf, header) {
+            Some(name) => format!("{sh_link} ({name})"),
+            None => sh_link.to_string(),
+        };
+        let segments = section_segments(elf, header)
+            .iter()
+            .map(usize::to_string)
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        let line = format!(
+            "  [{:>2}] {:18} {:14} 0x{:0>16x} 0x{:0>8x} 0x{:0>8x} 0x{:0>4x} {:5} {:18} {:>4} {:>5} {}",
+            index,
+            section_name(elf, header).unwrap_or("---"),
+            section_type_name(sh_type),
+            sh_addr,
+            sh_offset,
+            sh_size,
+            sh_entsize,
+            section_flags_name(sh_flags),
+            link,
+            sh_info,
+            sh_addralign,
+            segments,
         );
-        println!("    {:?}", symbol);
+        println!("{}", line.trim_end());
+    }
+
+    println!("Key to flags:");
+    println!("  W (write), A (alloc), X (execute), M (merge), S (strings), I (info),");
+    println!("  L (link order), O (extra OS processing required), G (group), T (TLS),");
+    println!("  C (compressed), E (exclude), x (unknown), o (OS specific), p (processor specific)");
+}
+
+/// Sections matching the `show sections` filters, with their indices.
+fn selected_sections<'elf>(
+    elf: &'elf Elf,
+    args: &SectionsArgs,
+) -> Vec<(usize, &'elf SectionHeader)> {
+    let SectionsArgs { name, section_type } = args;
+
+    elf.section_headers
+        .iter()
+        .enumerate()
+        .filter(|(_, header)| {
+            name.as_ref()
+                .is_none_or(|pattern| glob_match(pattern, section_name(elf, header).unwrap_or("")))
+        })
+        .filter(|(_, header)| section_type.is_none_or(|sh_type| header.sh_type == sh_type))
+        .collect()
+}
+
+fn section_name<'elf>(elf: &'elf Elf, header: &SectionHeader) -> Option<&'elf str> {
+    elf.shdr_strtab.get_at(header.sh_name)
+}
+
+/// Name of the section `sh_link` points to, if `sh_link` is set.
+fn linked_section_name<'elf>(elf: &'elf Elf, header: &SectionHeader) -> Option<&'elf str> {
+    if header.sh_link == 0 {
+        return None;
+    }
+
+    elf.section_headers
+        .get(header.sh_link as usize)
+        .and_then(|linked| section_name(elf, linked))
+}
+
+/// `sh_type` name without the `SHT_` prefix, or a hex value for unknown types.
+fn section_type_name(sh_type: u32) -> String {
+    match section_header::sht_to_str(sh_type) {
+        "UNKNOWN_SHT" => format!("0x{sh_type:x}"),
+        name => name.trim_start_matches("SHT_").to_owned(),
+    }
+}
+
+/// `sh_flags` as letters, the same way `readelf` shows them.
+fn section_flags_name(sh_flags: u64) -> String {
+    use section_header::{
+        SHF_ALLOC, SHF_COMPRESSED, SHF_EXCLUDE, SHF_EXECINSTR, SHF_GROUP, SHF_INFO_LINK,
+        SHF_LINK_ORDER, SHF_MASKOS, SHF_MASKPROC, SHF_MERGE, SHF_OS_NONCONFORMING, SHF_STRINGS,
+        SHF_TLS, SHF_WRITE,
+    };
+
+    const FLAGS: [(u32, char); 12] = [
+        (SHF_WRITE, 'W'),
+        (SHF_ALLOC, 'A'),
+        (SHF_EXECINSTR, 'X'),
+        (SHF_MERGE, 'M'),
+        (SHF_STRINGS, 'S'),
+        (SHF_INFO_LINK, 'I'),
+        (SHF_LINK_ORDER, 'L'),
+        (SHF_OS_NONCONFORMING, 'O'),
+        (SHF_GROUP, 'G'),
+        (SHF_TLS, 'T'),
+        (SHF_COMPRESSED, 'C'),
+        (SHF_EXCLUDE, 'E'),
+    ];
+
+    let mut res = String::new();
+    let mut rest = sh_flags;
+    for (flag, letter) in FLAGS {
+        if sh_flags & u64::from(flag) != 0 {
+            res.push(letter);
+            rest &= !u64::from(flag);
+        }
     }
 
-    println!(".dynstr content:");
-    for string in elf.dynstrtab.to_vec().expect("Input .dynstr is parsable") {
-        println!("  \"{string}\"");
+    if rest & u64::from(SHF_MASKOS) != 0 {
+        res.push('o');
+    }
+    if rest & u64::from(SHF_MASKPROC) != 0 {
+        res.push('p');
+    }
+    if rest & !u64::from(SHF_MASKOS | SHF_MASKPROC) != 0 {
+        res.push('x');
+    }
+
+    res
+}
+
+/// Indices of the program sections that hold this section.
+fn section_segments(elf: &Elf, header: &SectionHeader) -> Vec<usize> {
+    elf.program_headers
+        .iter()
+        .enumerate()
+        .filter(|(_, segment)| section_in_segment(header, segment))
+        .map(|(index, _)| index)
+        .collect()
+}
+
+fn print_dynsyms<'input>(
+    input_bytes: &'input [u8],
+    elf: &Elf<'input>,
+    ctx: Ctx,
+    args: &SymbolsArgs,
+) {
+    let symbols = match dynamic_symbols(input_bytes, elf, ctx) {
+        Ok(Some(symbols)) => symbols,
+        Ok(None) => {
+            println!("Input does not have a .dynsym symbol table");
+            return;
+        }
+        Err(err) => {
+            println!("Failed to read the .dynsym symbol table.\n{err}");
+            return;
+        }
+    };
+    let total = symbols.len();
+    let symbols = selected_symbols(symbols, args, false);
+    let versions = symbol_versions(input_bytes, elf);
+
+    println!("Dynamic symbols ({} of {}):", symbols.len(), total);
+    print_symbols_header();
+    for symbol in &symbols {
+        let version = symbol_version(elf, &versions, symbol.index);
+        print_symbol(elf, symbol, version, false);
+    }
+}
+
+fn print_symtab(input_bytes: &[u8], elf: &Elf, ctx: Ctx, args: &SymtabArgs) {
+    let symbols = match static_symbols(input_bytes, elf, ctx) {
+        Ok(Some(symbols)) => symbols,
+        Ok(None) => {
+            println!("Input does not have a .symtab symbol table");
+            return;
+        }
+        Err(err) => {
+            println!("Failed to read the .symtab symbol table.\n{err}");
+            return;
+        }
+    };
+    let total = symbols.len();
+    let mut symbols = selected_symbols(symbols, &args.symbols, args.demangle);
+    sort_symbols(&mut symbols, args.sort);
+
+    println!("Static symbols ({} of {}):", symbols.len(), total);
+    print_symbols_header();
+    for symbol in &symbols {
+        print_symbol(elf, symbol, None, args.demangle);
+    }
+}
+
+/// Entries of `.dynsym`, or `None` if the file does not have one.
+///
+/// The table is located via the section headers, as goblin only finds it via the dynamic section
+/// when there is a hash table.  The dynamic section is used when there are no section headers.
+fn dynamic_symbols<'input>(
+    input_bytes: &'input [u8],
+    elf: &Elf<'input>,
+    ctx: Ctx,
+) -> Result<Option<Vec<NamedSymbol<'input>>>, String> {
+    if let Some(symbols) = find_section_symbols(input_bytes, elf, ctx, section_header::SHT_DYNSYM) {
+        return symbols.map(Some);
+    }
+
+    if elf.dynsyms.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some(
+        elf.dynsyms
+            .iter()
+            .enumerate()
+            .map(|(index, symbol)| NamedSymbol {
+                index,
+                name: elf.dynstrtab.get_at(symbol.st_name),
+                symbol,
+            })
+            .collect(),
+    ))
+}
+
+/// Entries of `.symtab`, or `None` if the file does not have one.
+fn static_symbols<'input>(
+    input_bytes: &'input [u8],
+    elf: &Elf,
+    ctx: Ctx,
+) -> Result<Option<Vec<NamedSymbol<'input>>>, String> {
+    find_section_symbols(input_bytes, elf, ctx, section_header::SHT_SYMTAB).transpose()
+}
+
+/// Entries of the first symbol table section of the `sh_type` type.
+fn find_section_symbols<'input>(
+    input_bytes: &'input [u8],
+    elf: &Elf,
+    ctx: Ctx,
+    sh_type: u32,
+) -> Option<Result<Vec<NamedSymbol<'input>>, String>> {
+    let header = elf
+        .section_headers
+        .iter()
+        .find(|header| header.sh_type == sh_type)?;
+    Some(section_symbols(input_bytes, elf, ctx, header))
+}
+
+/// Symbols matching the symbol table filters.  With `demangle`, names are matched after
+/// demangling.
+fn selected_symbols<'input>(
+    symbols: Vec<NamedSymbol<'input>>,
+    args: &SymbolsArgs,
+    demangle: bool,
+) -> Vec<NamedSymbol<'input>> {
+    let SymbolsArgs {
+        name,
+        symbol_type,
+        defined,
+        undefined,
+    } = args;
+
+    symbols
+        .into_iter()
+        .filter(|symbol| {
+            name.as_ref().is_none_or(|pattern| {
+                glob_match(pattern, &symbol_name(symbol, demangle).unwrap_or_default())
+            })
+        })
+        .filter(|symbol| symbol_type.is_none_or(|st_type| symbol.symbol.st_type() == st_type))
+        .filter(|symbol| {
+            // The first entry is reserved, and is neither defined nor undefined.
+            let is_undefined = symbol.symbol.st_shndx == section_header::SHN_UNDEF as usize;
+            let is_reserved = symbol.index == 0;
+            match (defined, undefined) {
+                (true, _) => !is_undefined,
+                (_, true) => is_undefined && !is_reserved,
+                _ => true,
+            }
+        })
+        .collect()
+}
+
+/// Reorders the symbols, keeping the symbol table order for the symbols that compare equal.
+fn sort_symbols(symbols: &mut [NamedSymbol], order: Option<SymbolOrder>) {
+    match order {
+        None => (),
+        Some(SymbolOrder::Address) => symbols.sort_by_key(|symbol| symbol.symbol.st_value),
+        Some(SymbolOrder::Size) => symbols.sort_by_key(|symbol| Reverse(symbol.symbol.st_size)),
+    }
+}
+
+/// Symbol name, demangled if `demangle` is set and the name is mangled.
+fn symbol_name<'input>(symbol: &NamedSymbol<'input>, demangle: bool) -> Option<Cow<'input, str>> {
+    let name = symbol.name?;
+    match demangle.then(|| inspect::demangle(name)).flatten() {
+        Some(demangled) => Some(Cow::Owned(demangled)),
+        None => Some(Cow::Borrowed(name)),
+    }
+}
+
+fn print_symbols_header() {
+    println!(
+        "  {:>5} {:18} {:10} {:9} {:10} {:9} {:14} Name",
+        "Num", "Value", "Size", "Type", "Bind", "Vis", "Section",
+    );
+}
+
+fn print_symbol(elf: &Elf, symbol: &NamedSymbol, version: Option<SymbolVersion>, demangle: bool) {
+    let name = symbol_name(symbol, demangle);
+    let NamedSymbol { index, symbol, .. } = symbol;
+
+    let line = format!(
+        "  {:>5} 0x{:0>16x} 0x{:0>8x} {:9} {:10} {:9} {:14} {}",
+        index,
+        symbol.st_value,
+        symbol.st_size,
+        symbol_type_name(symbol.st_type()),
+        symbol_bind_name(symbol.st_bind()),
+        symbol_visibility_name(symbol.st_visibility()),
+        symbol_section_name(elf, symbol.st_shndx),
+        versioned_symbol_name(name.as_deref().unwrap_or("---"), version),
+    );
+    println!("{}", line.trim_end());
+}
+
+/// `st_type` name without the `STT_` prefix, or a hex value for unknown types.
+fn symbol_type_name(st_type: u8) -> String {
+    match sym::type_to_str(st_type) {
+        "UNKNOWN_STT" => format!("0x{st_type:x}"),
+        name => name.to_owned(),
+    }
+}
+
+/// `st_bind` name without the `STB_` prefix, or a hex value for unknown bindings.
+fn symbol_bind_name(st_bind: u8) -> String {
+    match sym::bind_to_str(st_bind) {
+        "UNKNOWN_STB" => format!("0x{st_bind:x}"),
+        name => name.to_owned(),
+    }
+}
+
+/// `st_other` visibility name without the `STV_` prefix, or a hex value for unknown values.
+fn symbol_visibility_name(st_visibility: u8) -> String {
+    match sym::visibility_to_str(st_visibility) {
+        "UNKNOWN_STV" => format!("0x{st_visibility:x}"),
+        name => name.to_owned(),
+    }
+}
+
+/// Name of the section `st_shndx` refers to, or the name of the special index, the same way
+/// `readelf` shows them.
+fn symbol_section_name(elf: &Elf, st_shndx: usize) -> String {
+    use section_header::{SHN_ABS, SHN_COMMON, SHN_UNDEF, SHN_XINDEX};
+
+    match st_shndx as u32 {
+        SHN_UNDEF => "UND".to_owned(),
+        SHN_ABS => "ABS".to_owned(),
+        SHN_COMMON => "COMMON".to_owned(),
+        SHN_XINDEX => "XINDEX".to_owned(),
+        _ => match elf
+            .section_headers
+            .get(st_shndx)
+            .and_then(|header| section_name(elf, header))
+        {
+            Some(name) if !name.is_empty() => name.to_owned(),
+            _ => st_shndx.to_string(),
+        },
+    }
+}
+
+/// Symbol name with the version appended, the same way `readelf` shows it.
+fn versioned_symbol_name(name: &str, version: Option<SymbolVersion>) -> String {
+    match version {
+        // Symbols that name the version definitions themselves are shown without the version.
+        Some(SymbolVersion { name: version, .. }) if version == name => name.to_owned(),
+        Some(SymbolVersion {
+            name: version,
+            required: false,
+            hidden: false,
+        }) => format!("{name}@@{version}"),
+        Some(SymbolVersion { name: version, .. }) => format!("{name}@{version}"),
+        None => name.to_owned(),
     }
 }
 
 fn print_shstrtab(elf: &Elf) {
     println!(".shstrtab content:");
-    for string in elf
-        .shdr_strtab
-        .to_vec()
-        .expect("Input .shstrtab is parsable")
-    {
-        println!("  \"{string}\"");
+    print_strtab(&elf.shdr_strtab);
+}
+
+fn print_strtab(strtab: &Strtab) {
+    match strtab.to_vec() {
+        Ok(strings) => {
+            for string in strings {
+                println!("  \"{string}\"");
+            }
+        }
+        Err(err) => println!("  Failed to parse: {err}"),
     }
 }
 
@@ -171,7 +640,7 @@ fn print_entrypoint(elf: &Elf) {
     println!(
         "\"entrypoint\" address: 0x{:0>16x} - 0x{:0>16x}, size: 0x{:0>8x}",
         offset,
-        offset + size,
+        offset.saturating_add(size),
         size,
     );
 }
@@ -0,0 +1,545 @@
+//! JSON output of the `show` commands.
+//!
+//! Every command prints a single JSON object.  Numbers are written as plain JSON integers, ranges
+//! of file offsets as `{ "start": ..., "end": ... }` with an exclusive `end`.  Fields that hold
+//! numeric ELF constants have a `_name` companion with the name used by the ELF specification,
+//! where one is known.  New fields could be added to the objects, but existing fields keep their
+//! names and meaning.
+
+use goblin::{
+    container::Ctx,
+    elf::{
+        self, header, program_header, reloc, section_header, sym, Elf, ProgramHeader, SectionHeader,
+    },
+    strtab::Strtab,
+};
+use scroll::ctx::SizeWith as _;
+use serde::Serialize;
+
+use elf_editor::inspect::{
+    self, find_current_entrypoint, section_in_segment, section_partially_in_segment,
+    symbol_version, symbol_versions, NamedSymbol, SymbolInfo, SymbolVersion,
+};
+
+use super::{
+    dynamic_symbols, linked_section_name, section_flags_name, section_segments, segment_flags_name,
+    selected_sections, selected_symbols, sort_symbols, static_symbols, symbol_section_name,
+};
+use crate::args::show::{SectionsArgs, ShowArgs, SymbolsArgs, SymtabArgs};
+
+pub fn run<'input>(input_bytes: &'input [u8], elf: &Elf<'input>, ctx: Ctx, args: ShowArgs) {
+    let json = match args {
+        ShowArgs::Header => to_json(&header(elf, ctx)),
+        ShowArgs::Layout => to_json(&layout(input_bytes, elf, ctx)),
+        ShowArgs::ProgramSections => to_json(&ProgramSections {
+            program_headers: program_headers(elf),
+            interpreter: elf.interpreter,
+        }),
+        ShowArgs::FileSegments => to_json(&FileSegments {
+            sections: sections(elf),
+        }),
+        ShowArgs::Sections(args) => to_json(&section_headers(elf, &args)),
+        ShowArgs::DynSym(args) => to_json(&dynsyms(input_bytes, elf, ctx, &args)),
+        ShowArgs::Symtab(args) => to_json(&symtab(input_bytes, elf, ctx, &args)),
+        ShowArgs::ShStrTab => to_json(&string_table(&elf.shdr_strtab)),
+        ShowArgs::Relocations => to_json(&relocations(elf)),
+        ShowArgs::Entrypoint => to_json(&Entrypoint {
+            entrypoint: find_current_entrypoint(elf).map(|SymbolInfo { offset, size }| {
+                EntrypointSymbol {
+                    address: offset,
+                    size,
+                }
+            }),
+        }),
+    };
+
+    println!("{json}");
+}
+
+fn to_json<Value>(value: &Value) -> String
+where
+    Value: Serialize,
+{
+    serde_json::to_string_pretty(value).expect("Show output serializes into JSON")
+}
+
+/// Range of file offsets.  `end` is exclusive.
+#[derive(Serialize)]
+struct Range {
+    start: u64,
+    end: u64,
+}
+
+impl Range {
+    fn new(start: u64, size: u64) -> Self {
+        Self {
+            start,
+            end: start.saturating_add(size),
+        }
+    }
+}
+
+/// `show header`
+#[derive(Serialize)]
+struct Header {
+    range: Range,
+    ei_class: u8,
+    ei_data: u8,
+    ei_version: u8,
+    ei_osabi: u8,
+    ei_abiversion: u8,
+    e_type: u16,
+    e_type_name: &'static str,
+    e_machine: u16,
+    e_machine_name: &'static str,
+    e_version: u32,
+    e_entry: u64,
+    e_phoff: u64,
+    e_shoff: u64,
+    e_flags: u32,
+    e_ehsize: u16,
+    e_phentsize: u16,
+    e_phnum: u16,
+    e_shentsize: u16,
+    e_shnum: u16,
+    e_shstrndx: u16,
+}
+
+fn header(elf: &Elf, ctx: Ctx) -> Header {
+    let header = &elf.header;
+    Header {
+        range: Range::new(0, elf::Header::size_with(&ctx) as u64),
+        ei_class: header.e_ident[header::EI_CLASS],
+        ei_data: header.e_ident[header::EI_DATA],
+        ei_version: header.e_ident[header::EI_VERSION],
+        ei_osabi: header.e_ident[header::EI_OSABI],
+        ei_abiversion: header.e_ident[header::EI_ABIVERSION],
+        e_type: header.e_type,
+        e_type_name: header::et_to_str(header.e_type),
+        e_machine: header.e_machine,
+        e_machine_name: header::machine_to_str(header.e_machine),
+        e_version: header.e_version,
+        e_entry: header.e_entry,
+        e_phoff: header.e_phoff,
+        e_shoff: header.e_shoff,
+        e_flags: header.e_flags,
+        e_ehsize: header.e_ehsize,
+        e_phentsize: header.e_phentsize,
+        e_phnum: header.e_phnum,
+        e_shentsize: header.e_shentsize,
+        e_shnum: header.e_shnum,
+        e_shstrndx: header.e_shstrndx,
+    }
+}
+
+/// `show layout`
+#[derive(Serialize)]
+struct Layout<'elf> {
+    file_size: u64,
+    e_type: u16,
+    e_type_name: &'static str,
+    elf_header: Range,
+    program_header_table: Range,
+    program_headers: Vec<ProgramHeaderInfo>,
+    sections: Vec<SectionInfo<'elf>>,
+    section_header_table: Range,
+}
+
+fn layout<'elf>(input_bytes: &[u8], elf: &'elf Elf, ctx: Ctx) -> Layout<'elf> {
+    let header = &elf.header;
+    Layout {
+        file_size: input_bytes.len() as u64,
+        e_type: header.e_type,
+        e_type_name: header::et_to_str(header.e_type),
+        elf_header: Range::new(0, elf::Header::size_with(&ctx) as u64),
+        program_header_table: Range::new(
+            header.e_phoff,
+            u64::from(header.e_phentsize) * u64::from(header.e_phnum),
+        ),
+        program_headers: program_headers(elf),
+        sections: sections(elf),
+        section_header_table: Range::new(
+            header.e_shoff,
+            u64::from(header.e_shentsize) * u64::from(header.e_shnum),
+        ),
+    }
+}
+
+/// `show program-sections`
+#[derive(Serialize)]
+struct ProgramSections<'elf> {
+    program_headers: Vec<ProgramHeaderInfo>,
+    /// Content of the `PT_INTERP` program section, or `null`.
+    interpreter: Option<&'elf str>,
+}
+
+#[derive(Serialize)]
+struct ProgramHeaderInfo {
+    index: usize,
+    p_type: u32,
+    p_type_name: &'static str,
+    p_flags: u32,
+    /// `p_flags` the same way `readelf` shows them.  For example, `R E`.
+    p_flags_name: String,
+    /// `p_offset` to `p_offset + p_filesz`.
+    range: Range,
+    p_vaddr: u64,
+    p_paddr: u64,
+    p_memsz: u64,
+    p_align: u64,
+    /// Indices of the sections this program section holds.
+    sections: Vec<usize>,
+    /// Indices of the sections this program section holds only a part of.
+    partial_sections: Vec<usize>,
+}
+
+fn program_headers(elf: &Elf) -> Vec<ProgramHeaderInfo> {
+    elf.program_headers
+        .iter()
+        .enumerate()
+        .map(|(index, header)| {
+            let ProgramHeader {
+                p_type,
+                p_flags,
+                p_offset,
+                p_vaddr,
+                p_paddr,
+                p_filesz,
+                p_memsz,
+                p_align,
+            } = *header;
+            let sections = |holds: fn(&SectionHeader, &ProgramHeader) -> bool| {
+                elf.section_headers
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, section)| holds(section, header))
+                    .map(|(index, _)| index)
+                    .collect()
+            };
+            ProgramHeaderInfo {
+                index,
+                p_type,
+                p_type_name: program_header::pt_to_str(p_type),
+                p_flags,
+                p_flags_name: segment_flags_name(p_flags),
+                range: Range::new(p_offset, p_filesz),
+                p_vaddr,
+                p_paddr,
+                p_memsz,
+                p_align,
+                sections: sections(section_in_segment),
+                partial_sections: sections(section_partially_in_segment),
+            }
+        })
+        .collect()
+}
+
+/// `show file-segments`
+#[derive(Serialize)]
+struct FileSegments<'elf> {
+    sections: Vec<SectionInfo<'elf>>,
+}
+
+#[derive(Serialize)]
+struct SectionInfo<'elf> {
+    index: usize,
+    /// `null` if the name can not be read from `.shstrtab`.
+    name: Option<&'elf str>,
+    sh_type: u32,
+    sh_type_name: &'static str,
+    sh_flags: u64,
+    sh_addr: u64,
+    /// `sh_offset` to `sh_offset + sh_size`.  Sections of type `SHT_NOBITS` occupy no space in
+    /// the file, even though the range is not empty.
+    range: Range,
+    sh_link: u32,
+    sh_info: u32,
+    sh_addralign: u64,
+    sh_entsize: u64,
+}
+
+fn sections<'elf>(elf: &'elf Elf) -> Vec<SectionInfo<'elf>> {
+    elf.section_headers
+        .iter()
+        .enumerate()
+        .map(|(index, header)| section_info(elf, index, header))
+        .collect()
+}
+
+fn section_info<'elf>(elf: &'elf Elf, index: usize, header: &SectionHeader) -> SectionInfo<'elf> {
+    let SectionHeader {
+        sh_name,
+        sh_type,
+        sh_flags,
+        sh_addr,
+        sh_offset,
+        sh_size,
+        sh_link,
+        sh_info,
+        sh_addralign,
+        sh_entsize,
+    } = *header;
+    SectionInfo {
+        index,
+        name: elf.shdr_strtab.get_at(sh_name),
+        sh_type,
+        sh_type_name: section_header::sht_to_str(sh_type),
+        sh_flags,
+        sh_addr,
+        range: Range::new(sh_offset, sh_size),
+        sh_link,
+        sh_info,
+        sh_addralign,
+        sh_entsize,
+    }
+}
+
+/// `show sections`
+#[derive(Serialize)]
+struct SectionHeaders<'elf> {
+    /// Sections that match the filters.
+    sections: Vec<SectionHeaderEntry<'elf>>,
+}
+
+#[derive(Serialize)]
+struct SectionHeaderEntry<'elf> {
+    #[serde(flatten)]
+    section: SectionInfo<'elf>,
+    /// `sh_flags` as letters, the same way `readelf` shows them.  For example, `WA`.
+    sh_flags_name: String,
+    /// Name of the section `sh_link` points to.  `null` if `sh_link` is 0, or the name is not
+    /// known.
+    sh_link_name: Option<&'elf str>,
+    /// Indices of the program sections that hold this section.
+    segments: Vec<usize>,
+}
+
+fn section_headers<'elf>(elf: &'elf Elf, args: &SectionsArgs) -> SectionHeaders<'elf> {
+    SectionHeaders {
+        sections: selected_sections(elf, args)
+            .into_iter()
+            .map(|(index, header)| SectionHeaderEntry {
+                section: section_info(elf, index, header),
+                sh_flags_name: section_flags_name(header.sh_flags),
+                sh_link_name: linked_section_name(elf, header),
+                segments: section_segments(elf, header),
+            })
+            .collect(),
+    }
+}
+
+/// `show dyn-sym`
+#[derive(Serialize)]
+struct DynSyms<'elf> {
+    /// Symbols that match the filters.
+    symbols: Vec<Symbol<'elf>>,
+    dynstr: StringTable<'elf>,
+    /// Explanation of why `.dynsym` could not be read, or `null`.
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Symbol<'elf> {
+    /// Index of the entry in the symbol table.
+    index: usize,
+    /// `null` if the name can not be read from the string table.
+    name: Option<&'elf str>,
+    st_name: usize,
+    st_info: u8,
+    st_bind: u8,
+    st_bind_name: &'static str,
+    st_type: u8,
+    st_type_name: &'static str,
+    st_other: u8,
+    st_visibility: u8,
+    st_visibility_name: &'static str,
+    st_shndx: usize,
+    /// Name of the section `st_shndx` refers to, or `UND`, `ABS`, `COMMON` for the special
+    /// indices.
+    st_shndx_name: String,
+    st_value: u64,
+    st_size: u64,
+    /// `null` for local and unversioned symbols.
+    version: Option<SymbolVersion<'elf>>,
+    /// `null` unless demangling was requested, and the name is mangled.
+    demangled_name: Option<String>,
+}
+
+fn symbol_info<'elf>(
+    elf: &Elf,
+    symbol: &NamedSymbol<'elf>,
+    version: Option<SymbolVersion<'elf>>,
+    demangle: bool,
+) -> Symbol<'elf> {
+    let NamedSymbol {
+        index,
+        name,
+        symbol,
+    } = *symbol;
+
+    Symbol {
+        index,
+        name,
+        st_name: symbol.st_name,
+        st_info: symbol.st_info,
+        st_bind: symbol.st_bind(),
+        st_bind_name: sym::bind_to_str(symbol.st_bind()),
+        st_type: symbol.st_type(),
+        st_type_name: sym::type_to_str(symbol.st_type()),
+        st_other: symbol.st_other,
+        st_visibility: symbol.st_visibility(),
+        st_visibility_name: sym::visibility_to_str(symbol.st_visibility()),
+        st_shndx: symbol.st_shndx,
+        st_shndx_name: symbol_section_name(elf, symbol.st_shndx),
+        st_value: symbol.st_value,
+        st_size: symbol.st_size,
+        version,
+        demangled_name: name.filter(|_| demangle).and_then(inspect::demangle),
+    }
+}
+
+fn dynsyms<'elf>(
+    input_bytes: &'elf [u8],
+    elf: &Elf<'elf>,
+    ctx: Ctx,
+    args: &SymbolsArgs,
+) -> DynSyms<'elf> {
+    let (symbols, error) = match dynamic_symbols(input_bytes, elf, ctx) {
+        Ok(symbols) => (symbols.unwrap_or_default(), None),
+        Err(err) => (vec![], Some(err)),
+    };
+    let versions = symbol_versions(input_bytes, elf);
+
+    DynSyms {
+        symbols: selected_symbols(symbols, args, false)
+            .iter()
+            .map(|symbol| {
+                let version = symbol_version(elf, &versions, symbol.index);
+                symbol_info(elf, symbol, version, false)
+            })
+            .collect(),
+        dynstr: string_table(&elf.dynstrtab),
+        error,
+    }
+}
+
+/// `show symtab`
+#[derive(Serialize)]
+struct Symtab<'elf> {
+    /// Symbols that match the filters, in the requested order.
+    symbols: Vec<Symbol<'elf>>,
+    /// Explanation of why `.symtab` could not be read, or `null`.
+    error: Option<String>,
+}
+
+fn symtab<'elf>(input_bytes: &'elf [u8], elf: &Elf, ctx: Ctx, args: &SymtabArgs) -> Symtab<'elf> {
+    let (symbols, error) = match static_symbols(input_bytes, elf, ctx) {
+        Ok(symbols) => (symbols.unwrap_or_default(), None),
+        Err(err) => (vec![], Some(err)),
+    };
+    let mut symbols = selected_symbols(symbols, &args.symbols, args.demangle);
+    sort_symbols(&mut symbols, args.sort);
+
+    Symtab {
+        symbols: symbols
+            .iter()
+            .map(|symbol| symbol_info(elf, symbol, None, args.demangle))
+            .collect(),
+        error,
+    }
+}
+
+/// `show sh-str-tab`, and the `.dynstr` content in `show dyn-sym`
+#[derive(Serialize)]
+struct StringTable<'elf> {
+    /// All the strings, in the order they are stored.  Empty if the table could not be parsed.
+    strings: Vec<&'elf str>,
+    /// Explanation of why the table could not be parsed, or `null`.
+    error: Option<String>,
+}
+
+fn string_table<'elf>(strtab: &Strtab<'elf>) -> StringTable<'elf> {
+    match strtab.to_vec() {
+        Ok(strings) => StringTable {
+            strings,
+            error: None,
+        },
+        Err(err) => StringTable {
+            strings: vec![],
+            error: Some(err.to_string()),
+        },
+    }
+}
+
+/// `show relocations`
+#[derive(Serialize)]
+struct Relocations {
+    /// Entries of the `DT_RELA` table.
+    dynrelas: Vec<Relocation>,
+    /// Entries of the `DT_REL` table.
+    dynrels: Vec<Relocation>,
+    /// Entries of the `DT_JMPREL` table.
+    pltrelocs: Vec<Relocation>,
+    /// Relocation sections, found via the section headers.
+    sections: Vec<RelocationSection>,
+}
+
+#[derive(Serialize)]
+struct RelocationSection {
+    /// Index of the `SHT_REL` or `SHT_RELA` section.
+    index: usize,
+    relocations: Vec<Relocation>,
+}
+
+#[derive(Serialize)]
+struct Relocation {
+    r_offset: u64,
+    r_type: u32,
+    r_type_name: &'static str,
+    r_sym: usize,
+    /// `null` for the `SHT_REL` entries.
+    r_addend: Option<i64>,
+}
+
+fn relocations(elf: &Elf) -> Relocations {
+    let machine = elf.header.e_machine;
+    let entries = |relocs: &reloc::RelocSection| {
+        relocs
+            .iter()
+            .map(|reloc| Relocation {
+                r_offset: reloc.r_offset,
+                r_type: reloc.r_type,
+                r_type_name: reloc::r_to_str(reloc.r_type, machine),
+                r_sym: reloc.r_sym,
+                r_addend: reloc.r_addend,
+            })
+            .collect()
+    };
+
+    Relocations {
+        dynrelas: entries(&elf.dynrelas),
+        dynrels: entries(&elf.dynrels),
+        pltrelocs: entries(&elf.pltrelocs),
+        sections: elf
+            .shdr_relocs
+            .iter()
+            .map(|(index, relocs)| RelocationSection {
+                index: *index,
+                relocations: entries(relocs),
+            })
+            .collect(),
+    }
+}
+
+/// `show entrypoint`
+#[derive(Serialize)]
+struct Entrypoint {
+    /// `null` if there is no "entrypoint" dynamic symbol.
+    entrypoint: Option<EntrypointSymbol>,
+}
+
+#[derive(Serialize)]
+struct EntrypointSymbol {
+    address: u64,
+    size: u64,
+}
@@ -4,166 +4,969 @@
 //! This module provides functionality for checking that the structure is as expected by the rest of
 //! the code.
 
+use std::{fmt, ops::Range};
+
 use goblin::{
     container::Ctx,
-    elf::{self, Elf, Header, SectionHeader},
+    elf::{
+        self,
+        dynamic::Dyn,
+        program_header::{pt_to_str, PT_LOAD, PT_TLS},
+        reloc::Reloc,
+        section_header::{
+            SHF_ALLOC, SHF_TLS, SHT_DYNAMIC, SHT_DYNSYM, SHT_GNU_HASH, SHT_GNU_VERDEF,
+            SHT_GNU_VERNEED, SHT_GNU_VERSYM, SHT_HASH, SHT_NOBITS, SHT_NULL, SHT_REL, SHT_RELA,
+            SHT_STRTAB, SHT_SYMTAB, SHT_SYMTAB_SHNDX,
+        },
+        Elf, ProgramHeader, SectionHeader, Sym,
+    },
 };
 use scroll::ctx::SizeWith;
 
+use crate::inspect::{file_regions, FileRegion};
+
+/// How to treat bytes that are not covered by any known structure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UnknownBytes {
+    /// Only zero bytes are allowed outside of the known structures.  They are considered padding
+    /// and are not preserved when the file is modified.
+    #[default]
+    Reject,
+    /// Ranges that contain non-zero bytes are kept as opaque blobs, attached to the preceding
+    /// structure, and are copied into the modified file unchanged.
+    Preserve,
+}
+
+/// Category of a structure violation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ViolationKind {
+    /// The file is structured in a way this tool does not handle at all.
+    Unsupported,
+    /// A structure points outside of the file.
+    OutOfBounds,
+    /// Two structures claim the same bytes in the file.
+    Overlap,
+    /// Bytes that are not covered by any known structure are not all zeros.
+    NonZeroGap,
+    /// `sh_link` (or `e_shstrndx`) does not point to a section of an expected type.
+    BadLink,
+    /// Program header boundaries do not match the boundaries of the sections it holds.
+    SegmentMismatch,
+    /// Entry size does not match the entry type, or the table size is not a multiple of it.
+    EntsizeMismatch,
+    /// Alignment is not a power of two, or a structure is not placed according to its alignment.
+    Misaligned,
+}
+
+impl fmt::Display for ViolationKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ViolationKind::Unsupported => "unsupported",
+            ViolationKind::OutOfBounds => "out of bounds",
+            ViolationKind::Overlap => "overlap",
+            ViolationKind::NonZeroGap => "non-zero gap",
+            ViolationKind::BadLink => "bad link",
+            ViolationKind::SegmentMismatch => "segment/section mismatch",
+            ViolationKind::EntsizeMismatch => "entsize mismatch",
+            ViolationKind::Misaligned => "misaligned",
+        };
+        f.write_str(name)
+    }
+}
+
+/// A single place where the ELF structure does not match the expectations of the rest of the code.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    pub kind: ViolationKind,
+    /// Explanation of the problem, including the relevant offsets and sizes.
+    pub message: String,
+    /// Range of the file bytes the problem is about.  Could be empty, or extend past the end of the
+    /// file for the `OutOfBounds` violations.
+    pub range: Range<u64>,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.kind, self.message)
+    }
+}
+
+macro_rules! report {
+    ($violations:expr, $kind:ident, $range:expr, $($message:tt)*) => {
+        $violations.push(Violation {
+            kind: ViolationKind::$kind,
+            message: format!($($message)*),
+            range: $range,
+        })
+    };
+}
+
 /// Verifies that the ELF structure matches all the assumptions the rest of the functions expect.
 /// Should be called for the input file ELF.
 ///
-/// Returns true if it does.  Prints an explanation and returns false if it does not.
-pub fn verify_elf_structure(bytes: &[u8], elf: &Elf, ctx: Ctx) -> Result<(), String> {
-    macro_rules! check_that {
-        ($cond:expr, on_fail: $($on_fail:tt)*) => {
-            if !$cond {
-                return Err(format!($($on_fail)*));
+/// Returns an explanation of all the problems found, if there are any.
+pub fn verify_elf_structure(
+    bytes: &[u8],
+    elf: &Elf,
+    ctx: Ctx,
+    unknown_bytes: UnknownBytes,
+) -> Result<(), String> {
+    let violations = find_structure_violations(bytes, elf, ctx, unknown_bytes);
+
+    if violations.is_empty() {
+        return Ok(());
+    }
+
+    Err(violations
+        .iter()
+        .map(Violation::to_string)
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+/// Same checks as [`verify_elf_structure()`], but instead of a text report returns all the
+/// individual violations, in the order they were found.
+pub fn find_structure_violations(
+    bytes: &[u8],
+    elf: &Elf,
+    ctx: Ctx,
+    unknown_bytes: UnknownBytes,
+) -> Vec<Violation> {
+    let mut violations = vec![];
+
+    check_file_coverage(bytes, elf, ctx, unknown_bytes, &mut violations);
+    check_section_links(elf, &mut violations);
+    check_segments(bytes, elf, ctx, &mut violations);
+    check_entry_sizes(elf, ctx, &mut violations);
+    check_alignments(elf, &mut violations);
+
+    violations
+}
+
+fn section_name<'elf>(elf: &'elf Elf, header: &SectionHeader) -> &'elf str {
+    elf.shdr_strtab.get_at(header.sh_name).unwrap_or("---")
+}
+
+/// Reports a violation if there are non-zero bytes in the `gap` range, unless they are to be
+/// preserved.  `describe` provides a description of the gap location, to be included into the
+/// message.
+fn check_zero_gap(
+    bytes: &[u8],
+    gap: Range<u64>,
+    unknown_bytes: UnknownBytes,
+    violations: &mut Vec<Violation>,
+    describe: impl FnOnce() -> String,
+) {
+    let Some(gap_bytes) = bytes.get(gap.start as usize..gap.end as usize) else {
+        report!(
+            violations,
+            OutOfBounds,
+            gap.clone(),
+            "{}\n\
+             Gap: 0x{:x} - 0x{:x}\n\
+             File size: 0x{:x}",
+            describe(),
+            gap.start,
+            gap.end,
+            bytes.len(),
+        );
+        return;
+    };
+
+    if unknown_bytes == UnknownBytes::Preserve {
+        return;
+    }
+
+    let Some(first_non_zero) = gap_bytes.iter().position(|&v| v != 0) else {
+        return;
+    };
+    let last_non_zero = gap_bytes
+        .iter()
+        .rposition(|&v| v != 0)
+        .expect("There is at least one non-zero byte");
+
+    report!(
+        violations,
+        NonZeroGap,
+        gap.start + first_non_zero as u64..gap.start + last_non_zero as u64 + 1,
+        "{}\n\
+         Gap: 0x{:x} - 0x{:x}\n\
+         Non-zero bytes: 0x{:x} - 0x{:x}",
+        describe(),
+        gap.start,
+        gap.end,
+        gap.start + first_non_zero as u64,
+        gap.start + last_non_zero as u64 + 1,
+    );
+}
+
+/// We expect all the bytes in the ELF to be covered by known structures, except that there could be
+/// zero byte gaps.
+///
+/// I've seen them used for alignment purposes.  One option would be to be more strict and only
+/// allow for zero byte gaps in cases when we size is does not fall on an word boundary (for some
+/// word size). But as I am not sure if there is a certain alignment requirements or conventions for
+/// ELF sections.  And I've seen at least two different cases.  So allowing for arbitrary gaps, as
+/// long as they are all zero bytes.
+///
+/// With [`UnknownBytes::Preserve`] gaps may contain arbitrary bytes.
+fn check_file_coverage(
+    bytes: &[u8],
+    elf: &Elf,
+    ctx: Ctx,
+    unknown_bytes: UnknownBytes,
+    violations: &mut Vec<Violation>,
+) {
+    let file_size = bytes.len() as u64;
+    let file_sections = elf.section_headers.as_slice();
+
+    if file_sections.len() < 2 {
+        report!(
+            violations,
+            Unsupported,
+            0..0,
+            "ELF must have at least 2 sections.  Got: {}",
+            file_sections.len(),
+        );
+        return;
+    }
+
+    {
+        let SectionHeader {
+            sh_offset, sh_size, ..
+        } = &file_sections[0];
+        if *sh_offset != 0 || *sh_size != 0 {
+            report!(
+                violations,
+                Unsupported,
+                *sh_offset..sh_offset.saturating_add(*sh_size),
+                "First section is not 0/0.\n\
+                 Got offset: 0x{sh_offset:x}, size: 0x{sh_size:x}",
+            );
+        }
+    }
+
+    // The program headers table, sections, and the section headers table could be placed in any
+    // order after the ELF header, as long as they do not overlap.  Section header table order does
+    // not have to match the order of the sections in the file either.
+    let mut covered_up_to = 0;
+    let mut previous = None;
+
+    for (region, range) in file_regions(&elf.header, file_sections, ctx) {
+        let Range { start, end } = range;
+        let name = region_name(elf, region);
+        let size = end - start;
+
+        if end > file_size {
+            report!(
+                violations,
+                OutOfBounds,
+                start..end,
+                "{} extends past the end of the file.\n\
+                 Offset: 0x{start:x}, size: 0x{size:x}\n\
+                 File size: 0x{file_size:x}",
+                capitalize(&name),
+            );
+            continue;
+        }
+
+        let previous_name = previous.map_or_else(String::new, |region| region_name(elf, region));
+
+        if start < covered_up_to {
+            report!(
+                violations,
+                Overlap,
+                start..covered_up_to.min(end),
+                "{} overlaps with the {previous_name}.\n\
+                 Offset: 0x{start:x}, size: 0x{size:x}\n\
+                 The {previous_name} ends at: 0x{covered_up_to:x}",
+                capitalize(&name),
+            );
+        } else if start > covered_up_to {
+            check_zero_gap(
+                bytes,
+                covered_up_to..start,
+                unknown_bytes,
+                violations,
+                || {
+                    format!(
+                        "There is a non-zero byte gap between the {previous_name} and the \
+                         {name}.\n\
+                         The {previous_name} ends at: 0x{covered_up_to:x}\n\
+                         The {name} offset: 0x{start:x}, size: 0x{size:x}",
+                    )
+                },
+            );
+        }
+
+        if end >= covered_up_to {
+            covered_up_to = end;
+            previous = Some(region);
+        }
+    }
+
+    if covered_up_to < file_size {
+        let previous_name = previous.map_or_else(String::new, |region| region_name(elf, region));
+        check_zero_gap(
+            bytes,
+            covered_up_to..file_size,
+            unknown_bytes,
+            violations,
+            || {
+                format!(
+                    "There are non-zero bytes after the {previous_name}, that is the last known \
+                     structure in the file.\n\
+                     The {previous_name} ends at: 0x{covered_up_to:x}\n\
+                     File size: 0x{file_size:x}",
+                )
+            },
+        );
+    }
+}
+
+/// Human readable name of a file region, for use in the violation messages.
+fn region_name(elf: &Elf, region: FileRegion) -> String {
+    match region {
+        FileRegion::ElfHeader => "ELF header".to_owned(),
+        FileRegion::ProgramHeaders => "program section headers table".to_owned(),
+        FileRegion::Section(index) => {
+            format!("section {}", section_name(elf, &elf.section_headers[index]))
+        }
+        FileRegion::SectionHeaders => "section headers table".to_owned(),
+    }
+}
+
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// Section types that `sh_link` of a section of the specified type must point to.  The flag
+/// indicates if a zero `sh_link` is allowed.
+fn expected_link_types(sh_type: u32) -> Option<(&'static [u32], bool)> {
+    const STRING_TABLES: &[u32] = &[SHT_STRTAB];
+    const SYMBOL_TABLES: &[u32] = &[SHT_SYMTAB, SHT_DYNSYM];
+
+    match sh_type {
+        SHT_SYMTAB | SHT_DYNSYM | SHT_DYNAMIC | SHT_GNU_VERDEF | SHT_GNU_VERNEED => {
+            Some((STRING_TABLES, false))
+        }
+        SHT_HASH | SHT_GNU_HASH | SHT_GNU_VERSYM | SHT_SYMTAB_SHNDX => Some((SYMBOL_TABLES, false)),
+        SHT_REL | SHT_RELA => Some((SYMBOL_TABLES, true)),
+        _ => None,
+    }
+}
+
+fn check_section_links(elf: &Elf, violations: &mut Vec<Violation>) {
+    let sections = elf.section_headers.as_slice();
+
+    {
+        let e_shstrndx = elf.header.e_shstrndx as usize;
+        if e_shstrndx != 0 {
+            match sections.get(e_shstrndx) {
+                Some(target) if target.sh_type == SHT_STRTAB => (),
+                target => report!(
+                    violations,
+                    BadLink,
+                    0..0,
+                    "`e_shstrndx` does not point to a string table.\n\
+                     e_shstrndx: {e_shstrndx}, target type: {}",
+                    target.map_or("<out of range>", |target| {
+                        elf::section_header::sht_to_str(target.sh_type)
+                    }),
+                ),
             }
+        }
+    }
+
+    for (index, header) in sections.iter().enumerate() {
+        let SectionHeader {
+            sh_type,
+            sh_offset,
+            sh_size,
+            sh_link,
+            ..
+        } = *header;
+        let name = section_name(elf, header);
+        let range = sh_offset..sh_offset.saturating_add(sh_size);
+
+        let Some(target) = sections.get(sh_link as usize) else {
+            report!(
+                violations,
+                BadLink,
+                range,
+                "Section `sh_link` is out of range.\n\
+                 Section index: {index}, name: {name}, sh_link: {sh_link}\n\
+                 Number of sections: {}",
+                sections.len(),
+            );
+            continue;
         };
+
+        let Some((expected_types, zero_allowed)) = expected_link_types(sh_type) else {
+            continue;
+        };
+
+        if sh_link == 0 && zero_allowed {
+            continue;
+        }
+
+        if !expected_types.contains(&target.sh_type) {
+            report!(
+                violations,
+                BadLink,
+                range,
+                "Section `sh_link` points to a section of an unexpected type.\n\
+                 Section index: {index}, name: {name}, type: {}\n\
+                 sh_link: {sh_link}, target name: {}, target type: {}\n\
+                 Expected target types: {}",
+                elf::section_header::sht_to_str(sh_type),
+                section_name(elf, target),
+                elf::section_header::sht_to_str(target.sh_type),
+                expected_types
+                    .iter()
+                    .map(|sht| elf::section_header::sht_to_str(*sht))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            );
+        }
     }
+}
+
+/// The transformer updates program headers based on the sections that start and end at the program
+/// header boundaries.  So every program header must start where some section starts, and end where
+/// some section ends.  And a section can not cross a program header boundary.
+///
+/// `SHT_NOBITS` sections are not considered for the file ranges, but they must fit into the memory
+/// range of some `PT_LOAD` program header.
+fn check_segments(bytes: &[u8], elf: &Elf, ctx: Ctx, violations: &mut Vec<Violation>) {
+    let file_size = bytes.len() as u64;
+    // Program sections could start or end at any known structure, including the ELF header and the
+    // header tables.
+    let regions = file_regions(&elf.header, &elf.section_headers, ctx);
+
+    for (index, header) in elf.program_headers.iter().enumerate() {
+        let ProgramHeader {
+            p_type,
+            p_offset,
+            p_filesz,
+            p_memsz,
+            ..
+        } = *header;
+        let p_type = pt_to_str(p_type);
+
+        let Some(p_end) = p_offset
+            .checked_add(p_filesz)
+            .filter(|end| *end <= file_size)
+        else {
+            report!(
+                violations,
+                OutOfBounds,
+                p_offset..p_offset.saturating_add(p_filesz),
+                "Program section extends past the end of the file.\n\
+                 Program section index: {index}, type: {p_type}, \
+                 offset: 0x{p_offset:x}, filesz: 0x{p_filesz:x}\n\
+                 File size: 0x{file_size:x}",
+            );
+            continue;
+        };
+
+        if header.p_vaddr.checked_add(p_memsz).is_none() {
+            report!(
+                violations,
+                OutOfBounds,
+                p_offset..p_end,
+                "Program section memory range extends past the end of the address space.\n\
+                 Program section index: {index}, type: {p_type}, \
+                 vaddr: 0x{:x}, memsz: 0x{p_memsz:x}",
+                header.p_vaddr,
+            );
+        }
 
-    macro_rules! must_be_zero_bytes_gap {
-        ($bytes:expr, on_fail: $($on_fail:tt)*) => {
-            if $bytes.iter().any(|&v| v != 0) {
-                return Err(format!($($on_fail)*));
+        if p_filesz > p_memsz {
+            report!(
+                violations,
+                SegmentMismatch,
+                p_offset..p_end,
+                "Program section file size is larger than its memory size.\n\
+                 Program section index: {index}, type: {p_type}, \
+                 filesz: 0x{p_filesz:x}, memsz: 0x{p_memsz:x}",
+            );
+        }
+
+        // Program sections that do not occupy any space in the file, like `PT_GNU_STACK`, do not
+        // need to match any structure.
+        if p_filesz == 0 {
+            continue;
+        }
+
+        if !regions.iter().any(|(_, range)| range.start == p_offset) {
+            report!(
+                violations,
+                SegmentMismatch,
+                p_offset..p_end,
+                "No section or header table starts at the start of the program section.\n\
+                 Program section index: {index}, type: {p_type}, \
+                 offset: 0x{p_offset:x}, filesz: 0x{p_filesz:x}",
+            );
+        }
+
+        if !regions.iter().any(|(_, range)| range.end == p_end) {
+            report!(
+                violations,
+                SegmentMismatch,
+                p_offset..p_end,
+                "No section or header table ends at the end of the program section.\n\
+                 Program section index: {index}, type: {p_type}, \
+                 offset: 0x{p_offset:x}, filesz: 0x{p_filesz:x}",
+            );
+        }
+
+        for (region, range) in &regions {
+            let Range { start, end } = *range;
+
+            let overlaps = start < p_end && p_offset < end;
+            let contained = p_offset <= start && end <= p_end;
+            if overlaps && !contained {
+                report!(
+                    violations,
+                    SegmentMismatch,
+                    start.max(p_offset)..end.min(p_end),
+                    "{} crosses a program section boundary.\n\
+                     Program section index: {index}, type: {p_type}, \
+                     offset: 0x{p_offset:x}, filesz: 0x{p_filesz:x}\n\
+                     Offset: 0x{start:x}, size: 0x{:x}",
+                    capitalize(&region_name(elf, *region)),
+                    end - start,
+                );
             }
+        }
+    }
+
+    for (index, section) in elf.section_headers.iter().enumerate() {
+        let SectionHeader {
+            sh_type,
+            sh_flags,
+            sh_addr,
+            sh_size,
+            ..
+        } = *section;
+
+        if sh_type != SHT_NOBITS || sh_flags & u64::from(SHF_ALLOC) == 0 || sh_size == 0 {
+            continue;
+        }
+
+        // Thread local `SHT_NOBITS` sections, like `.tbss`, are only a part of the thread local
+        // storage template.  Their addresses may overlap the sections that follow them.
+        let (segment_type, segment_name) = if sh_flags & u64::from(SHF_TLS) != 0 {
+            (PT_TLS, "PT_TLS")
+        } else {
+            (PT_LOAD, "PT_LOAD")
         };
+
+        let sh_addr_end = sh_addr.saturating_add(sh_size);
+        let is_covered = elf.program_headers.iter().any(|header| {
+            header.p_type == segment_type
+                && header.p_vaddr <= sh_addr
+                && sh_addr_end <= header.p_vaddr.saturating_add(header.p_memsz)
+        });
+        if !is_covered {
+            report!(
+                violations,
+                SegmentMismatch,
+                section.sh_offset..section.sh_offset,
+                "`SHT_NOBITS` section memory range is not covered by any `{segment_name}` program \
+                 section.\n\
+                 Section index: {index}, name: {}, addr: 0x{sh_addr:x}, size: 0x{sh_size:x}",
+                section_name(elf, section),
+            );
+        }
     }
+}
 
-    // We expect all the bytes in the ELF to be covered by known structures, except that there could
-    // be zero byte gaps.
-    //
-    // I've seen them used for alignment purposes.  One option would be to be more strict and only
-    // allow for zero byte gaps in cases when we size is does not fall on an word boundary (for some
-    // word size). But as I am not sure if there is a certain alignment requirements or conventions
-    // for ELF sections.  And I've seen at least two different cases.  So allowing for arbitrary
-    // gaps, as long as they are all zero bytes.
-    let mut covered_up_to = Header::size_with(&ctx) as u64;
+/// Entry size for the tables that are expected to hold entries of a known type.
+fn expected_entsize(sh_type: u32, ctx: Ctx) -> Option<u64> {
+    let size = match sh_type {
+        SHT_SYMTAB | SHT_DYNSYM => Sym::size_with(&ctx),
+        SHT_RELA => Reloc::size(true, ctx),
+        SHT_REL => Reloc::size(false, ctx),
+        SHT_DYNAMIC => Dyn::size_with(&ctx),
+        SHT_GNU_VERSYM => 2,
+        _ => return None,
+    };
+    Some(size as u64)
+}
 
+fn check_entry_sizes(elf: &Elf, ctx: Ctx, violations: &mut Vec<Violation>) {
     {
         let elf::Header {
             e_phoff,
             e_phentsize,
             e_phnum,
+            e_shoff,
+            e_shentsize,
+            e_shnum,
             ..
         } = elf.header;
-        let size = u64::from(e_phentsize) * u64::from(e_phnum);
-
-        if e_phoff < covered_up_to {
-            return Err(format!(
-                "Program section headers table overlaps with the ELF header.\n\
-                 Program section headers table offset: 0x{e_phoff:x}, size: 0x{size:x}\n\
-                 ELF header ends at: 0x{covered_up_to:x}",
-            ));
-        } else if e_phoff > covered_up_to {
-            must_be_zero_bytes_gap! {
-                bytes[covered_up_to as usize .. e_phoff as usize],
-                on_fail:
-                "There is a non-zero byte gap between the ELF header and the program section \
-                 headers table.\n\
-                 Program section headers table offset: 0x{e_phoff:x}, size: 0x{size:x}\n\
-                 Last section ends at: 0x{covered_up_to:x}",
+
+        let expected = ProgramHeader::size_with(&ctx);
+        if e_phnum != 0 && usize::from(e_phentsize) != expected {
+            report!(
+                violations,
+                EntsizeMismatch,
+                e_phoff..e_phoff.saturating_add(u64::from(e_phentsize) * u64::from(e_phnum)),
+                "`e_phentsize` does not match the program header size.\n\
+                 e_phentsize: {e_phentsize}, expected: {expected}",
+            );
+        }
+
+        let expected = SectionHeader::size_with(&ctx);
+        if e_shnum != 0 && usize::from(e_shentsize) != expected {
+            report!(
+                violations,
+                EntsizeMismatch,
+                e_shoff..e_shoff.saturating_add(u64::from(e_shentsize) * u64::from(e_shnum)),
+                "`e_shentsize` does not match the section header size.\n\
+                 e_shentsize: {e_shentsize}, expected: {expected}",
+            );
+        }
+    }
+
+    for (index, header) in elf.section_headers.iter().enumerate() {
+        let SectionHeader {
+            sh_type,
+            sh_offset,
+            sh_size,
+            sh_entsize,
+            ..
+        } = *header;
+        let name = section_name(elf, header);
+        let range = sh_offset..sh_offset.saturating_add(sh_size);
+
+        match expected_entsize(sh_type, ctx) {
+            Some(expected) if sh_entsize != expected => {
+                report!(
+                    violations,
+                    EntsizeMismatch,
+                    range,
+                    "Section `sh_entsize` does not match the entry size for the section type.\n\
+                     Section index: {index}, name: {name}, type: {}\n\
+                     sh_entsize: {sh_entsize}, expected: {expected}",
+                    elf::section_header::sht_to_str(sh_type),
+                );
+            }
+            _ if sh_entsize != 0 && sh_size % sh_entsize != 0 => {
+                report!(
+                    violations,
+                    EntsizeMismatch,
+                    range,
+                    "Section size is not a multiple of `sh_entsize`.\n\
+                     Section index: {index}, name: {name}, \
+                     size: 0x{sh_size:x}, sh_entsize: {sh_entsize}",
+                );
             }
+            _ => (),
+        }
+    }
+}
+
+fn is_valid_alignment(alignment: u64) -> bool {
+    alignment == 0 || alignment.is_power_of_two()
+}
+
+/// The transformer aligns every structure it places.  An alignment that is not a power of two, or
+/// an input that does not follow its own alignment, could move structures arbitrarily far.
+fn check_alignments(elf: &Elf, violations: &mut Vec<Violation>) {
+    for (index, header) in elf.section_headers.iter().enumerate() {
+        let SectionHeader {
+            sh_type,
+            sh_flags,
+            sh_offset,
+            sh_size,
+            sh_addralign,
+            ..
+        } = *header;
+        let name = section_name(elf, header);
+        let range = sh_offset..sh_offset.saturating_add(sh_size);
+
+        if !is_valid_alignment(sh_addralign) {
+            report!(
+                violations,
+                Misaligned,
+                range,
+                "Section `sh_addralign` is not a power of two.\n\
+                 Section index: {index}, name: {name}, sh_addralign: 0x{sh_addralign:x}",
+            );
+            continue;
+        }
+
+        if sh_type == SHT_NULL || sh_type == SHT_NOBITS || sh_addralign <= 1 {
+            continue;
         }
 
-        covered_up_to = e_phoff + size;
+        // Layout aligns the offsets of all the sections it places.  Sections that are not loaded
+        // can just move in the file, but loaded sections are also mapped at a fixed address, and
+        // would end up at a different offset even when no section changes.
+        if sh_flags & u64::from(SHF_ALLOC) != 0 && sh_offset % sh_addralign != 0 {
+            report!(
+                violations,
+                Misaligned,
+                range,
+                "Loaded section offset is not a multiple of its alignment.\n\
+                 Section index: {index}, name: {name}, \
+                 offset: 0x{sh_offset:x}, sh_addralign: 0x{sh_addralign:x}",
+            );
+        }
     }
 
-    let file_sections = elf.section_headers.as_slice();
+    for (index, header) in elf.program_headers.iter().enumerate() {
+        let ProgramHeader {
+            p_type,
+            p_offset,
+            p_vaddr,
+            p_filesz,
+            p_align,
+            ..
+        } = *header;
+        let p_type_name = pt_to_str(p_type);
+        let range = p_offset..p_offset.saturating_add(p_filesz);
+
+        if !is_valid_alignment(p_align) {
+            report!(
+                violations,
+                Misaligned,
+                range,
+                "Program section `p_align` is not a power of two.\n\
+                 Program section index: {index}, type: {p_type_name}, p_align: 0x{p_align:x}",
+            );
+            continue;
+        }
+
+        if p_type == PT_LOAD && p_align > 1 && p_offset % p_align != p_vaddr % p_align {
+            report!(
+                violations,
+                Misaligned,
+                range,
+                "PT_LOAD offset is not congruent with its virtual address, modulo the alignment.\n\
+                 Program section index: {index}, \
+                 offset: 0x{p_offset:x}, vaddr: 0x{p_vaddr:x}, p_align: 0x{p_align:x}",
+            );
+        }
+    }
+}
 
-    check_that! {
-        file_sections.len() > 1,
-        on_fail:
-        "ELF must have at least 2 sections.  Got: {}",
-        file_sections.len()
+#[cfg(test)]
+mod tests {
+    use goblin::{
+        container::{Container, Ctx},
+        elf::{
+            program_header::{PF_R, PT_LOAD, PT_TLS},
+            section_header::{SHF_ALLOC, SHF_TLS, SHF_WRITE, SHT_NOBITS, SHT_PROGBITS, SHT_STRTAB},
+            Elf, Header, ProgramHeader, SectionHeader,
+        },
     };
+    use pretty_assertions::assert_eq;
+    use scroll::Endian;
 
-    {
-        let SectionHeader {
-            sh_offset, sh_size, ..
-        } = &file_sections[0];
-        check_that! {
-            *sh_offset == 0 && *sh_size == 0,
-            on_fail:
-            "First section is not 0/0.\n\
-             Got offset: 0x{sh_offset:x}, size: 0x{sh_size:x}",
+    use super::{find_structure_violations, UnknownBytes, ViolationKind};
+
+    const CTX: Ctx = Ctx {
+        container: Container::Big,
+        le: Endian::Little,
+    };
+
+    // Program headers table is placed in the zero gap between `.data` and the section headers
+    // table, with space for two entries.
+    const PROGRAM_HEADERS_OFFSET: u64 = 0x60;
+    const SECTION_HEADERS_OFFSET: u64 = 0x100;
+
+    // `.shstrtab` at 0x40 - 0x50 and `.data` at 0x50 - 0x60.
+    fn sections() -> Vec<SectionHeader> {
+        vec![
+            SectionHeader::default(),
+            SectionHeader {
+                sh_type: SHT_STRTAB,
+                sh_offset: 0x40,
+                sh_size: 0x10,
+                sh_addralign: 1,
+                ..SectionHeader::default()
+            },
+            SectionHeader {
+                sh_type: SHT_PROGBITS,
+                sh_offset: 0x50,
+                sh_size: 0x10,
+                sh_addralign: 8,
+                ..SectionHeader::default()
+            },
+        ]
+    }
+
+    // `PT_LOAD` of `.data`.
+    fn program_header() -> ProgramHeader {
+        ProgramHeader {
+            p_type: PT_LOAD,
+            p_flags: PF_R,
+            p_offset: 0x50,
+            p_vaddr: 0x1050,
+            p_paddr: 0x1050,
+            p_filesz: 0x10,
+            p_memsz: 0x10,
+            p_align: 0x1000,
+        }
+    }
+
+    fn file_bytes(sections: &[SectionHeader]) -> Vec<u8> {
+        vec![0; SECTION_HEADERS_OFFSET as usize + sections.len() * 0x40]
+    }
+
+    /// Kinds of the violations found in a file with the specified headers, without repetitions.
+    fn violation_kinds(
+        bytes: &[u8],
+        sections: Vec<SectionHeader>,
+        program_headers: Vec<ProgramHeader>,
+    ) -> Vec<ViolationKind> {
+        let mut header = Header::new(CTX);
+        header.e_phoff = PROGRAM_HEADERS_OFFSET;
+        header.e_phnum = program_headers.len() as u16;
+        header.e_shoff = SECTION_HEADERS_OFFSET;
+        header.e_shnum = sections.len() as u16;
+        header.e_shstrndx = 1;
+
+        let mut elf = Elf::lazy_parse(header).expect("Header is valid");
+        elf.section_headers = sections;
+        elf.program_headers = program_headers;
+
+        let mut kinds: Vec<_> = find_structure_violations(bytes, &elf, CTX, UnknownBytes::Reject)
+            .into_iter()
+            .map(|violation| violation.kind)
+            .collect();
+        kinds.dedup();
+        kinds
+    }
+
+    #[test]
+    fn minimal_file_is_supported() {
+        let sections = sections();
+        let bytes = file_bytes(&sections);
+        assert_eq!(
+            violation_kinds(&bytes, sections, vec![program_header()]),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn overlap() {
+        let mut sections = sections();
+        sections[2].sh_offset = 0x48;
+        let bytes = file_bytes(&sections);
+        assert_eq!(
+            violation_kinds(&bytes, sections, vec![]),
+            vec![ViolationKind::Overlap]
+        );
+    }
+
+    #[test]
+    fn non_zero_gap() {
+        let sections = sections();
+        let mut bytes = file_bytes(&sections);
+        bytes[0x80] = 1;
+        assert_eq!(
+            violation_kinds(&bytes, sections, vec![]),
+            vec![ViolationKind::NonZeroGap]
+        );
+    }
+
+    #[test]
+    fn bad_link() {
+        let mut sections = sections();
+        sections[2].sh_link = 7;
+        let bytes = file_bytes(&sections);
+        assert_eq!(
+            violation_kinds(&bytes, sections, vec![]),
+            vec![ViolationKind::BadLink]
+        );
+    }
+
+    #[test]
+    fn segment_mismatch() {
+        let sections = sections();
+        let bytes = file_bytes(&sections);
+        // Ends in the middle of `.data`.
+        let program_header = ProgramHeader {
+            p_filesz: 0x8,
+            ..program_header()
         };
+        assert_eq!(
+            violation_kinds(&bytes, sections, vec![program_header]),
+            vec![ViolationKind::SegmentMismatch]
+        );
     }
 
-    for SectionHeader {
-        sh_name,
-        sh_offset,
-        sh_size,
-        ..
-    } in &file_sections[1..]
-    {
-        if *sh_offset < covered_up_to {
-            return Err(format!(
-                "Section offset points to a range already covered by a previous section.\n\
-                 Section name: {}, offset: 0x{:x}, size: 0x{:x}\n\
-                 Previous section ends at: 0x{:x}",
-                elf.shdr_strtab.get_at(*sh_name).unwrap_or("---"),
-                sh_offset,
-                sh_size,
-                covered_up_to,
-            ));
-        } else if *sh_offset > covered_up_to {
-            must_be_zero_bytes_gap! {
-                bytes[covered_up_to as usize .. *sh_offset as usize],
-                on_fail:
-                "There is a non-zero byte gab after the previous section end.\n\
-                 Section name: {}, offset: 0x{:x}, size: 0x{:x}\n\
-                 Previous section ends at: 0x{:x}",
-                elf.shdr_strtab.get_at(*sh_name).unwrap_or("---"),
-                sh_offset,
-                sh_size,
-                covered_up_to,
-            };
-        }
-
-        covered_up_to = sh_offset + sh_size;
+    #[test]
+    fn tbss_is_checked_against_tls_program_section() {
+        // `.tbss` right after `.data`, outside of the `PT_LOAD` memory range.
+        let mut sections = sections();
+        sections.push(SectionHeader {
+            sh_type: SHT_NOBITS,
+            sh_flags: u64::from(SHF_ALLOC | SHF_WRITE | SHF_TLS),
+            sh_addr: 0x1060,
+            sh_offset: 0x60,
+            sh_size: 0x20,
+            sh_addralign: 8,
+            ..SectionHeader::default()
+        });
+        let bytes = file_bytes(&sections);
+        let tls_program_header = ProgramHeader {
+            p_type: PT_TLS,
+            p_flags: PF_R,
+            p_offset: 0x60,
+            p_vaddr: 0x1060,
+            p_paddr: 0x1060,
+            p_filesz: 0,
+            p_memsz: 0x20,
+            p_align: 8,
+        };
+
+        assert_eq!(
+            violation_kinds(
+                &bytes,
+                sections.clone(),
+                vec![program_header(), tls_program_header]
+            ),
+            vec![]
+        );
+        assert_eq!(
+            violation_kinds(&bytes, sections, vec![program_header()]),
+            vec![ViolationKind::SegmentMismatch]
+        );
     }
 
-    {
-        let elf::Header {
-            e_shoff,
-            e_shentsize,
-            e_shnum,
-            ..
-        } = elf.header;
-        let size = u64::from(e_shentsize) * u64::from(e_shnum);
+    #[test]
+    fn misaligned_loaded_section() {
+        // `.data` at 0x50 is not aligned to 0x20.
+        let mut sections = sections();
+        sections[2].sh_addralign = 0x20;
+        let bytes = file_bytes(&sections);
+        assert_eq!(violation_kinds(&bytes, sections.clone(), vec![]), vec![]);
 
-        if e_shoff < covered_up_to {
-            return Err(format!(
-                "Section headers table starts at a point that is already covered by the previous \
-                 section.\n\
-                 Section headers table offset: 0x{e_shoff:x}, size: 0x{size:x}\n\
-                 Last section ends at: 0x{covered_up_to:x}",
-            ));
-        } else if e_shoff > covered_up_to {
-            must_be_zero_bytes_gap! {
-                bytes[covered_up_to as usize .. e_shoff as usize],
-                on_fail:
-                "There is a non-zero byte gap between the last section and the section headers \
-                 table.\n\
-                 Section headers table offset: 0x{e_shoff:x}, size: 0x{size:x}\n\
-                 Last section ends at: 0x{covered_up_to:x}",
-            };
-        }
-
-        covered_up_to = e_shoff + size;
-    }
-
-    must_be_zero_bytes_gap! {
-        bytes[covered_up_to as usize .. bytes.len()],
-        on_fail:
-        "There are non-zero bytes after the section headers table that is expected to be the last \
-         element of the file.\n\
-         Section headers table end: 0x{covered_up_to:x}\n\
-         File size: 0x{:x}",
-        bytes.len(),
-    };
+        sections[2].sh_flags = u64::from(SHF_ALLOC);
+        assert_eq!(
+            violation_kinds(&bytes, sections, vec![]),
+            vec![ViolationKind::Misaligned]
+        );
+    }
 
-    Ok(())
+    #[test]
+    fn entsize_mismatch() {
+        let mut sections = sections();
+        sections[2].sh_entsize = 3;
+        let bytes = file_bytes(&sections);
+        assert_eq!(
+            violation_kinds(&bytes, sections, vec![]),
+            vec![ViolationKind::EntsizeMismatch]
+        );
+    }
 }
@@ -2,32 +2,102 @@
 //!
 //! This module describes this transformation process.
 
-use std::{io, mem::size_of_val};
+use std::{collections::HashMap, io, mem::size_of_val, ops::Range};
 
 use goblin::{
-    container::Ctx,
-    elf::{self, Elf, ProgramHeader, SectionHeader},
+    container::{Container, Ctx},
+    elf::{
+        self,
+        dynamic::{
+            Dyn, DT_FINI_ARRAY, DT_FINI_ARRAYSZ, DT_GNU_HASH, DT_HASH, DT_INIT_ARRAY,
+            DT_INIT_ARRAYSZ, DT_JMPREL, DT_PLTRELSZ, DT_PREINIT_ARRAY, DT_PREINIT_ARRAYSZ, DT_REL,
+            DT_RELA, DT_RELASZ, DT_RELSZ, DT_STRSZ, DT_STRTAB, DT_SYMTAB, DT_VERDEF, DT_VERNEED,
+            DT_VERSYM,
+        },
+        program_header::{PF_R, PF_W, PF_X, PT_LOAD, PT_PHDR},
+        section_header::{SHF_ALLOC, SHF_EXECINSTR, SHF_WRITE, SHT_DYNAMIC, SHT_NOBITS, SHT_NULL},
+        Elf, Header, ProgramHeader, SectionHeader,
+    },
 };
 use scroll::{
     ctx::{SizeWith, TryIntoCtx},
-    IOwrite,
+    IOwrite, Pread, Pwrite,
 };
 
-#[allow(unused)]
-pub fn transform_elf_sections<Output, SectionTransformer>(
+use crate::{
+    inspect::{file_regions, FileRegion},
+    structure::UnknownBytes,
+};
+
+pub mod combinators;
+
+/// Edit applied to individual sections of an ELF file.
+///
+/// Transformation runs in two phases.  First, [`SectionTransformer::plan()`] is called once for
+/// every section, to learn the new section sizes.  This is enough to compute the output layout.
+/// Then, [`SectionTransformer::write()`] is called for every section that has a plan, with that
+/// plan, to produce the new section bytes.  Any work done while planning can be stored in the plan,
+/// so it is not repeated.
+pub trait SectionTransformer {
+    /// Whatever [`SectionTransformer::write()`] needs to produce the section bytes.
+    type Plan;
+
+    /// Decides if the section described by `section_header` should be changed.  `section_index` is
+    /// the index of the section in the input section header table.  `None` means the section is
+    /// copied into the output unchanged.
+    fn plan(
+        &self,
+        input_bytes: &[u8],
+        section_index: usize,
+        section_header: &SectionHeader,
+        ctx: Ctx,
+    ) -> Option<SectionPlan<Self::Plan>>;
+
+    /// Writes the new section bytes into `output`.  Exactly [`SectionPlan::new_size`] bytes must be
+    /// written.
+    fn write(
+        &self,
+        input_bytes: &[u8],
+        section_index: usize,
+        section_header: &SectionHeader,
+        ctx: Ctx,
+        plan: Self::Plan,
+        output: &mut dyn io::Write,
+    );
+
+    /// Applies `next` to the sections produced by this transformer.
+    fn chain<Next>(self, next: Next) -> combinators::Chain<Self, Next>
+    where
+        Self: Sized,
+        Next: SectionTransformer,
+    {
+        combinators::Chain::new(self, next)
+    }
+}
+
+/// Result of [`SectionTransformer::plan()`] for a section that is changed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SectionPlan<Plan> {
+    /// Section size after the transformation.
+    pub new_size: u64,
+    /// Passed to [`SectionTransformer::write()`].
+    pub plan: Plan,
+}
+
+/// Produces a new version of the `elf` file in the `output`, with sections changed by the
+/// `transformer`.  The rest of the file is updated to match the new section sizes, according to
+/// the `options`.
+pub fn transform_elf_sections<Output, Transformer>(
     input_bytes: &[u8],
     elf: &Elf,
     ctx: Ctx,
     mut output: Output,
-    transformer: SectionTransformer,
-) where
+    options: LayoutOptions,
+    transformer: Transformer,
+) -> Result<(), String>
+where
     Output: io::Write,
-    SectionTransformer: for<'bytes, 'header, 'output> Fn(
-        /* input_bytes: */ &'bytes [u8],
-        /* section_header: */ &'header SectionHeader,
-        /* ctx: */ Ctx,
-        /* output: */ &'output mut dyn io::Write,
-    ) -> Option<u64>,
+    Transformer: SectionTransformer,
 {
     // Serialization buffer.
     let mut buf = [0u8; 256];
@@ -48,22 +118,50 @@ pub fn transform_elf_sections<Output, SectionTransformer>(
         SectionHeader::size_with(&ctx),
     );
 
+    // Indexed by the section index.
+    let mut plans = elf
+        .section_headers
+        .iter()
+        .enumerate()
+        .map(|(index, header)| {
+            if header.sh_type == SHT_NULL {
+                None
+            } else {
+                transformer.plan(input_bytes, index, header, ctx)
+            }
+        })
+        .collect::<Vec<_>>();
+    let new_section_sizes = plans
+        .iter()
+        .zip(&elf.section_headers)
+        .map(|(plan, header)| plan.as_ref().map_or(header.sh_size, |plan| plan.new_size))
+        .collect::<Vec<_>>();
+
     let ComputeShiftsResult {
         program_headers: output_program_headers,
         section_headers: output_section_headers,
+        program_headers_start,
         section_headers_start,
+        preserved_bytes,
     } = compute_shifts(
         input_bytes,
+        &elf.header,
         &elf.program_headers,
         &elf.section_headers,
         ctx,
-        &transformer,
-    );
+        options,
+        &new_section_sizes,
+    )?;
 
     let mut written_up_to = 0;
 
     let new_header = {
-        let mut res = elf.header.clone();
+        let mut res = elf.header;
+        res.e_phoff = program_headers_start;
+        res.e_phnum = output_program_headers
+            .len()
+            .try_into()
+            .expect("Number of program headers fits into `e_phnum`");
         res.e_shoff = section_headers_start;
         res
     };
@@ -73,63 +171,119 @@ pub fn transform_elf_sections<Output, SectionTransformer>(
         .expect("ELF header serializes correctly and fits into the output");
     written_up_to += elf::Header::size_with(&ctx) as u64;
 
-    // We do not allow adding or removing sections for now, so the position or the side of the
-    // program headers is not expected to be any different.
-    assert_eq!(written_up_to, elf.header.e_phoff);
-    for header in output_program_headers {
-        iowrite_from_scroll(&mut buf, &mut output, header, ctx)
-            .expect("`ProgramHeader` values serialize correctly");
-        written_up_to += ProgramHeader::size_with(&ctx) as u64;
-    }
-
-    {
-        let mut input_section_headers = elf.section_headers.iter();
-        let mut output_section_headers = output_section_headers.iter();
+    assert_eq!(elf.section_headers.len(), output_section_headers.len());
 
-        while let (Some(input_section_header), Some(output_section_header)) =
-            (input_section_headers.next(), output_section_headers.next())
-        {
-            add_padding(
-                &mut output,
-                &mut buf,
-                output_section_header.sh_offset,
-                &mut written_up_to,
-            );
-
-            match transformer(input_bytes, &input_section_header, ctx, &mut output) {
-                Some(_) => {
-                    // `transformer` is expected to write the updated bytes into `output`.
+    // Everything after the ELF header is written in the output offset order.
+    let mut chunks = vec![];
+    if !output_program_headers.is_empty() {
+        chunks.push((program_headers_start, OutputChunk::ProgramHeaders));
+    }
+    chunks.extend(
+        output_section_headers
+            .iter()
+            .enumerate()
+            // `SHT_NOBITS` sections do not occupy any space in the file.
+            .filter(|(_, header)| header.sh_type != SHT_NULL && header.sh_type != SHT_NOBITS)
+            .map(|(index, header)| (header.sh_offset, OutputChunk::Section(index))),
+    );
+    if !output_section_headers.is_empty() {
+        chunks.push((section_headers_start, OutputChunk::SectionHeaders));
+    }
+    chunks.extend(preserved_bytes.iter().map(
+        |PreservedBytes {
+             output_offset,
+             input,
+         }| { (*output_offset, OutputChunk::Preserved(input.clone())) },
+    ));
+    chunks.sort_by_key(|(offset, _)| *offset);
+
+    for (offset, chunk) in chunks {
+        add_padding(&mut output, &mut buf, offset, &mut written_up_to);
+
+        match chunk {
+            OutputChunk::ProgramHeaders => {
+                for header in &output_program_headers {
+                    iowrite_from_scroll(&mut buf, &mut output, header.clone(), ctx)
+                        .expect("`ProgramHeader` values serialize correctly");
+                    written_up_to += ProgramHeader::size_with(&ctx) as u64;
                 }
-                None => {
+            }
+            OutputChunk::Section(index) => {
+                let input_section_header = &elf.section_headers[index];
+
+                let transformed = plans[index].take().map(|SectionPlan { new_size, plan }| {
+                    let mut res = Vec::with_capacity(new_size as usize);
+                    transformer.write(
+                        input_bytes,
+                        index,
+                        input_section_header,
+                        ctx,
+                        plan,
+                        &mut res,
+                    );
+                    assert_eq!(
+                        res.len() as u64,
+                        new_size,
+                        "Section transformer must write exactly as many bytes as it has \
+                         planned.\n\
+                         Section offset: 0x{:x}",
+                        input_section_header.sh_offset,
+                    );
+                    res
+                });
+                let section_bytes = transformed.as_deref().unwrap_or_else(|| {
                     let section_start = input_section_header.sh_offset as usize;
                     let section_end = section_start + input_section_header.sh_size as usize;
-
-                    output
-                        .write_all(&input_bytes[section_start..section_end])
-                        .expect("Output can consume all the section data");
+                    &input_bytes[section_start..section_end]
+                });
+
+                let updated_dynamic = (input_section_header.sh_type == SHT_DYNAMIC)
+                    .then(|| {
+                        update_dynamic_entries(
+                            section_bytes,
+                            &elf.section_headers,
+                            &output_section_headers,
+                            ctx,
+                        )
+                    })
+                    .flatten();
+                let section_bytes = updated_dynamic.as_deref().unwrap_or(section_bytes);
+
+                output
+                    .write_all(section_bytes)
+                    .expect("Output can consume all the section data");
+                written_up_to += section_bytes.len() as u64;
+            }
+            OutputChunk::Preserved(input) => {
+                output
+                    .write_all(&input_bytes[input.start as usize..input.end as usize])
+                    .expect("Output can consume all the preserved data");
+                written_up_to += input.end - input.start;
+            }
+            OutputChunk::SectionHeaders => {
+                for header in &output_section_headers {
+                    iowrite_from_scroll(&mut buf, &mut output, header.clone(), ctx)
+                        .expect("`SectionHeader` values serialize correctly");
+                    written_up_to += SectionHeader::size_with(&ctx) as u64;
                 }
-            };
+            }
         }
-
-        assert_eq!(input_section_headers.len(), 0);
-        assert_eq!(output_section_headers.len(), 0);
     }
 
-    add_padding(
-        &mut output,
-        &mut buf,
-        section_headers_start,
-        &mut written_up_to,
-    );
+    output.flush().expect("Output can be flushed");
 
-    for header in output_section_headers {
-        iowrite_from_scroll(&mut buf, &mut output, header, ctx)
-            .expect("`SectionHeader` values serialize correctly");
-        written_up_to += SectionHeader::size_with(&ctx) as u64;
-    }
+    Ok(())
+}
+
+/// Part of the output written after the ELF header.
+enum OutputChunk {
+    ProgramHeaders,
+    Section(usize),
+    Preserved(Range<u64>),
+    SectionHeaders,
 }
 
-fn iowrite_from_scroll<Output, T, Ctx>(
+pub(crate) fn iowrite_from_scroll<Output, T, Ctx>(
     buf: &mut [u8],
     output: &mut Output,
     value: T,
@@ -149,7 +303,7 @@ where
     Ok(())
 }
 
-fn add_padding<Output>(
+pub(crate) fn add_padding<Output>(
     output: &mut Output,
     buf: &mut [u8],
     target_offset: u64,
@@ -160,11 +314,11 @@ fn add_padding<Output>(
     while *written_up_to < target_offset {
         let size = target_offset
             .saturating_sub(*written_up_to)
-            .min(size_of_val(&buf) as u64);
+            .min(buf.len() as u64);
         let buf = &mut buf[0..size as usize];
         buf.fill(0);
         output
-            .write_all(&buf)
+            .write_all(buf)
             .expect("Output can fit all the section paddings");
 
         *written_up_to += size;
@@ -179,161 +333,175 @@ fn strict_signed_diff(a: u64, b: u64) -> i64 {
     res
 }
 
+/// Options that control how the output file is laid out.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LayoutOptions {
+    /// How the structures are placed in the output file.
+    pub strategy: LayoutStrategy,
+    /// What to do with the bytes that are not covered by any known structure.
+    pub unknown_bytes: UnknownBytes,
+}
+
+/// How the structures are placed in the output file, after some of the sections change size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LayoutStrategy {
+    /// Each structure is placed right after the previous one, aligned according to its alignment
+    /// requirements.  Any padding between the structures is removed.
+    #[default]
+    Pack,
+    /// Structures stay at their original offsets, unless a preceding structure moved or grew past
+    /// them.  A structure is moved by the same amount as the structure before it, or further, if
+    /// that one grew into it.  So a gap after a structure that grew shrinks, or disappears.  Output
+    /// differs minimally from the input.
+    PreserveGaps,
+    /// Sections that grow are moved to the end of the file, into a new `PT_LOAD` program section
+    /// with a fresh virtual address range.  All the other structures keep their offsets and
+    /// addresses.  Dynamic section entries that point to the moved sections are updated.
+    AppendToEnd,
+}
+
+/// Unknown bytes copied into the output as is.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PreservedBytes {
+    pub output_offset: u64,
+    pub input: Range<u64>,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct ComputeShiftsResult {
     program_headers: Vec<ProgramHeader>,
     section_headers: Vec<SectionHeader>,
+    program_headers_start: u64,
     section_headers_start: u64,
+    preserved_bytes: Vec<PreservedBytes>,
 }
 
-/// Records when we update a program header, to make sure we only update each program header once
-/// and each program headers is updated.  This help identify bugs, and unexpected input, it does not
-/// affect the output produced.
-struct ProgramHeaderUpdate {
-    /// We have seen a section that starts at this program header start.
-    start: bool,
-    /// We have seen a section that ends at this program header end.
-    end: bool,
-}
-
-impl ProgramHeaderUpdate {
-    fn no_updates() -> Self {
-        Self {
-            start: false,
-            end: false,
-        }
+/// With [`UnknownBytes::Preserve`], bytes between the end of a known structure and the start of
+/// the next one are kept with the preceding structure, if any of them is not zero.  Zero-only gaps
+/// are treated as padding, and are recomputed.
+///
+/// Returns input ranges of the bytes that should be preserved after the `regions`.
+fn preserved_trailing_bytes(
+    input_bytes: &[u8],
+    regions: &[(FileRegion, Range<u64>)],
+    unknown_bytes: UnknownBytes,
+) -> HashMap<FileRegion, Range<u64>> {
+    if unknown_bytes == UnknownBytes::Reject {
+        return HashMap::new();
     }
+
+    let file_end = input_bytes.len() as u64;
+
+    regions
+        .iter()
+        .enumerate()
+        .filter_map(|(i, (region, range))| {
+            let gap_end = regions
+                .get(i + 1)
+                .map_or(file_end, |(_, next)| next.start)
+                .min(file_end);
+            let gap = range.end..gap_end;
+            let gap_bytes = input_bytes.get(gap.start as usize..gap.end as usize)?;
+
+            gap_bytes.iter().any(|&v| v != 0).then_some((*region, gap))
+        })
+        .collect()
 }
 
+#[derive(Debug, Clone, Copy)]
 struct SectionDimensions {
     offset: u64,
     size: u64,
 }
 
+impl SectionDimensions {
+    fn end(&self) -> u64 {
+        self.offset + self.size
+    }
+}
+
 /// Helper used to update program headers.
+///
+/// Program sections are updated based on the structures they contain in the input file.  A program
+/// section may contain any number of sections, and other program sections may contain the same
+/// sections.  For example, `PT_DYNAMIC` is nested inside a `PT_LOAD`, and `PT_GNU_RELRO` covers
+/// several sections at the start of a writable `PT_LOAD`.
 struct OutputProgramHeadersUpdater {
-    /// Holds exiting section offset and size, and flags that indicate if this section was updated or
-    /// not.  Same size as `output` and matches based on the index.
-    meta: Vec<(SectionDimensions, ProgramHeaderUpdate)>,
-    /// Holds a value for the new program header after the edit.  Same size as `meta` and matches
-    /// based on the index.
-    output: Vec<ProgramHeader>,
+    input: Vec<ProgramHeader>,
+    /// Old and new dimensions of all the structures placed in the output file.
+    observed: Vec<(SectionDimensions, SectionDimensions)>,
 }
 
 impl OutputProgramHeadersUpdater {
-    /// Initially `OutputProgramHeaders` contains a copy of the `program_headers`, and none are
-    /// marked as updated.
     fn new(program_headers: &[ProgramHeader]) -> Self {
         Self {
-            meta: program_headers
-                .iter()
-                .map(|section| {
-                    (
-                        SectionDimensions {
-                            offset: section.p_offset,
-                            size: section.p_filesz,
-                        },
-                        ProgramHeaderUpdate::no_updates(),
-                    )
-                })
-                .collect(),
-            output: program_headers.to_vec(),
+            input: program_headers.to_vec(),
+            observed: vec![],
         }
     }
 
-    /// Every time a file section is updated we might need to update a program section that holds
-    /// it.  This method does it, under an assumption that a file section start or end with match a
-    /// program section start or end, respectively.  And that there should be only one such match.
-    ///
-    /// It does a linear search through program sections, but there should not be that many of them.
+    /// Records the new position of a structure in the file.  `SHT_NOBITS` sections are observed
+    /// with zero size, as they do not occupy any space in the file.
     fn observe_file_section(&mut self, old: SectionDimensions, new: SectionDimensions) {
-        let Self { meta, output } = self;
-
-        let SectionDimensions {
-            offset: old_offset,
-            size: old_size,
-        } = old;
-        let SectionDimensions {
-            offset: new_offset,
-            size: new_size,
-        } = new;
-
-        if let Some(i) = meta
-            .iter()
-            .position(|(SectionDimensions { offset, .. }, _)| *offset == old_offset)
-        {
-            let updates = &mut meta[i].1;
-
-            assert!(
-                !updates.start,
-                "Program section at offset 0x{old_offset:0>16x}: Two file sections coincide with \
-                 the start of this program section.\n\
-                 This tool code does not support ELF files with such structure, as it makes it \
-                 harder to know when such a program section offset needs to be updated.",
-            );
-
-            updates.start = true;
-            output[i].p_offset = new_offset;
-        };
-
-        if let Some(i) = meta
-            .iter()
-            .position(|(SectionDimensions { offset, size }, _)| {
-                offset + size == old_offset + old_size
-            })
-        {
-            let updates = &mut meta[i].1;
-            let output = &mut output[i];
-
-            assert!(
-                !updates.end,
-                "Program section at offset 0x{old_offset:0>16x}: Two file sections coincide with \
-                 the start of this program section.\n\
-                 This tool code does not support ELF files with such structure, as it makes it \
-                 harder to know when such a program section offset needs to be updated.",
-            );
-
-            updates.end = true;
-            // This is a bit tricky, as we need to compute the program section size, but we only
-            // know the file section size.  And the file section may not cover the whole program
-            // section.  So we need to go to absolute values and then back to relative.
-            let new_filesz = new_offset
-                .checked_add(new_size)
-                .expect("File section size end fits into u64")
-                .checked_sub(output.p_offset)
-                .expect("Program section size is positive");
-            let size_adjustment = strict_signed_diff(new_filesz, output.p_filesz);
-            output.p_filesz = new_filesz;
-            output.p_memsz = output.p_memsz.checked_add_signed(size_adjustment).expect(
-                "Program section p_memsz is positive and fits into u64 after an adjustment",
-            );
-        };
+        self.observed.push((old, new));
     }
 
+    /// Computes the output program headers.
+    ///
+    /// A program section that occupies space in the file starts at its first member structure, and
+    /// ends at the last one.  Bytes between the program section boundaries and the members, if any,
+    /// are kept.  `p_memsz` changes by the same amount as `p_filesz`.
+    ///
+    /// A program section that occupies no space in the file, like `PT_GNU_STACK` or a `PT_TLS`
+    /// with only `.tbss`, follows the structure that was at the same offset, if any.
     fn into_result(self) -> Vec<ProgramHeader> {
-        let Self { meta, output } = self;
-
-        for (i, (_, ProgramHeaderUpdate { start, end })) in meta.into_iter().enumerate() {
-            let target = &output[i];
-            assert!(
-                start,
-                "Program section at offset 0x{:0>16x}: No file sections coincide with the start of \
-                 this program section.\n\
-                 This tool code does not support ELF files with such structure, as it makes it \
-                 harder to know when such a program section offset needs to be updated.",
-                target.p_offset,
-            );
-            assert!(
-                end,
-                "Program section at offset 0x{:0>16x}: No file sections coincide with the end of \
-                 this program section.\n\
-                 This tool code does not support ELF files with such structure, as it makes it \
-                 harder to know when such a program section size needs to be updated.",
-                target.p_offset,
-            );
-        }
+        let Self { input, observed } = self;
+
+        input
+            .into_iter()
+            .map(|header| {
+                let p_start = header.p_offset;
+                let p_end = p_start.saturating_add(header.p_filesz);
+
+                if header.p_filesz == 0 {
+                    let p_offset = observed
+                        .iter()
+                        .find(|(old, _)| old.offset == p_start)
+                        .map_or(p_start, |(_, new)| new.offset);
+                    return ProgramHeader { p_offset, ..header };
+                }
 
-        output
+                let members = observed
+                    .iter()
+                    .filter(|(old, _)| old.size != 0 && p_start <= old.offset && old.end() <= p_end)
+                    .collect::<Vec<_>>();
+
+                let Some((first_old, first_new)) = members.iter().min_by_key(|(old, _)| old.offset)
+                else {
+                    // Program section does not hold any known structure.  Structure verification
+                    // rejects such files, but there is nothing to update anyway.
+                    return header;
+                };
+                let (last_old, last_new) = members
+                    .iter()
+                    .max_by_key(|(old, _)| old.end())
+                    .expect("`members` is not empty");
+
+                let p_offset = first_new.offset - (first_old.offset - p_start);
+                let p_filesz = last_new.end() + (p_end - last_old.end()) - p_offset;
+                let size_adjustment = strict_signed_diff(p_filesz, header.p_filesz);
+                let p_memsz = header.p_memsz.checked_add_signed(size_adjustment).expect(
+                    "Program section p_memsz is positive and fits into u64 after an adjustment",
+                );
+
+                ProgramHeader {
+                    p_offset,
+                    p_filesz,
+                    p_memsz,
+                    ..header
+                }
+            })
+            .collect()
     }
 }
 
@@ -341,97 +509,689 @@ impl OutputProgramHeadersUpdater {
 /// changes for each section, which allows us to compute correct updates for the ELF header, program
 /// section headers and section headers table in one go.
 ///
-/// Returns a mapping from the existing file section offset to that section size adjustment.
-pub fn compute_shifts<SectionTransformer>(
+/// The ELF header stays at the start of the file.  The program headers table, sections and the
+/// section headers table are placed after each other, in the input file offset order, starting at
+/// the offset of the first of them.  Gaps between the structures are handled according to
+/// `options.strategy`, and unknown bytes are preserved according to `options.unknown_bytes`.
+///
+/// Fails if the produced layout can not be loaded, see [`check_load_segments`].
+pub fn compute_shifts(
     input_bytes: &[u8],
+    input_header: &Header,
     input_program_headers: &[ProgramHeader],
     input_section_headers: &[SectionHeader],
     ctx: Ctx,
-    transformer: SectionTransformer,
-) -> ComputeShiftsResult
-where
-    SectionTransformer: for<'bytes, 'header, 'output> Fn(
-        /* input_bytes: */ &'bytes [u8],
-        /* section_header: */ &'header SectionHeader,
-        /* ctx: */ Ctx,
-        /* output: */ &'output mut dyn io::Write,
-    ) -> Option<u64>,
-{
-    let mut vacant_at = match input_section_headers.first() {
-        Some(first_section_header) => first_section_header.sh_offset,
-        None => {
-            return ComputeShiftsResult {
-                program_headers: vec![],
-                section_headers: vec![],
-                section_headers_start: 0,
-            }
-        }
+    options: LayoutOptions,
+    new_section_sizes: &[u64],
+) -> Result<ComputeShiftsResult, String> {
+    let res = match options.strategy {
+        LayoutStrategy::Pack | LayoutStrategy::PreserveGaps => shift_sections(
+            input_bytes,
+            input_header,
+            input_program_headers,
+            input_section_headers,
+            ctx,
+            options,
+            new_section_sizes,
+        ),
+        LayoutStrategy::AppendToEnd => append_grown_sections(
+            input_bytes,
+            input_header,
+            input_program_headers,
+            input_section_headers,
+            ctx,
+            options,
+            new_section_sizes,
+        ),
     };
 
-    let mut input_section_headers = input_section_headers.iter();
+    check_load_segments(&res.program_headers, &res.section_headers)?;
 
-    let mut output_program_headers_updater =
-        OutputProgramHeadersUpdater::new(input_program_headers);
-    let mut output_section_headers = Vec::with_capacity(input_section_headers.len());
+    Ok(res)
+}
 
-    while let Some(input_section_header) = input_section_headers.next() {
-        let new_section_size =
-            match transformer(input_bytes, &input_section_header, ctx, &mut io::empty()) {
-                Some(new_size) => new_size,
-                None => input_section_header.sh_size,
-            };
+/// [`LayoutStrategy::Pack`] and [`LayoutStrategy::PreserveGaps`] part of [`compute_shifts`].
+///
+/// A structure that starts a `PT_LOAD` program section is placed at an offset that is congruent
+/// with the program section virtual address, modulo its alignment.  Loaders require it, as they
+/// map file pages directly into memory.
+fn shift_sections(
+    input_bytes: &[u8],
+    input_header: &Header,
+    input_program_headers: &[ProgramHeader],
+    input_section_headers: &[SectionHeader],
+    ctx: Ctx,
+    options: LayoutOptions,
+    new_section_sizes: &[u64],
+) -> ComputeShiftsResult {
+    let regions = file_regions(input_header, input_section_headers, ctx);
+    let mut trailing_bytes = preserved_trailing_bytes(input_bytes, &regions, options.unknown_bytes);
+
+    // `SHT_NOBITS` sections do not occupy any space in the file, but they still have an offset.
+    // Linkers put them where they would start, based on their alignment.  We keep them after the
+    // same structure they follow in the input.
+    let placement_order = {
+        let mut res = regions.clone();
+        res.extend(
+            input_section_headers
+                .iter()
+                .enumerate()
+                .filter(|(_, header)| header.sh_type == SHT_NOBITS)
+                .map(|(index, header)| {
+                    (
+                        FileRegion::Section(index),
+                        header.sh_offset..header.sh_offset,
+                    )
+                }),
+        );
+        res.sort_by_key(|(_, range)| (range.start, range.end));
+        res
+    };
+
+    // Input offsets of the `PT_LOAD` program sections starting points, with the congruence
+    // requirements for the structures placed there.
+    let load_starts = input_program_headers
+        .iter()
+        .filter(|header| header.p_type == PT_LOAD)
+        .map(|header| (header.p_offset, (header.p_vaddr, header.p_align)))
+        .collect::<HashMap<_, _>>();
 
-        let old_section_offset = input_section_header.sh_offset;
-        let input_section_alignment = input_section_header.sh_addralign;
-        let new_section_offset = if input_section_alignment <= 1 {
-            vacant_at
-        } else {
-            vacant_at.next_multiple_of(input_section_alignment)
+    let mut output_program_headers_updater =
+        OutputProgramHeadersUpdater::new(input_program_headers);
+    // Section header table order is preserved, even though sections are placed in the file offset
+    // order.  All the entries are overwritten below.
+    let mut output_section_headers = input_section_headers.to_vec();
+    let mut program_headers_start = input_header.e_phoff;
+    let mut section_headers_start = input_header.e_shoff;
+    let mut preserved_bytes = vec![];
+
+    // Everything that follows the ELF header is placed starting at the same offset as in the
+    // input.
+    let mut vacant_at = regions
+        .iter()
+        .find(|(region, _)| *region != FileRegion::ElfHeader)
+        .map_or(Header::size_with(&ctx) as u64, |(_, range)| range.start);
+    // With `LayoutStrategy::PreserveGaps` - how far the last placed structure has moved from its
+    // original offset.  Structures never move towards the start of the file, so the gaps are only
+    // ever kept or reduced.
+    let mut shift = 0;
+
+    for (region, range) in placement_order {
+        let old_offset = range.start;
+        let old_size = range.end - range.start;
+
+        let (new_size, alignment) = match region {
+            FileRegion::ElfHeader => (old_size, 0),
+            FileRegion::ProgramHeaders | FileRegion::SectionHeaders => {
+                (old_size, header_table_alignment(ctx))
+            }
+            FileRegion::Section(index) => (
+                new_section_sizes[index],
+                input_section_headers[index].sh_addralign,
+            ),
         };
 
-        let old_section_size = input_section_header.sh_size;
+        let new_offset = match (region, options.strategy) {
+            (FileRegion::ElfHeader, _) => 0,
+            (_, LayoutStrategy::Pack) => align_offset(vacant_at, alignment),
+            (_, LayoutStrategy::PreserveGaps) => {
+                align_offset(vacant_at.max(old_offset + shift), alignment)
+            }
+            (_, LayoutStrategy::AppendToEnd) => unreachable!("Handled by `append_grown_sections`"),
+        };
+        let new_offset = match (region, load_starts.get(&old_offset)) {
+            (FileRegion::ElfHeader, _) | (_, None) => new_offset,
+            (_, Some(&(p_vaddr, p_align))) => congruent_offset(new_offset, p_vaddr, p_align),
+        };
 
-        output_section_headers.push(SectionHeader {
-            sh_offset: new_section_offset,
-            sh_size: new_section_size,
-            ..input_section_header.clone()
-        });
+        match region {
+            FileRegion::ElfHeader => {}
+            FileRegion::ProgramHeaders => program_headers_start = new_offset,
+            FileRegion::SectionHeaders => section_headers_start = new_offset,
+            FileRegion::Section(index) => {
+                let input_section_header = &input_section_headers[index];
+
+                output_section_headers[index] = SectionHeader {
+                    sh_offset: new_offset,
+                    sh_size: new_size,
+                    ..input_section_header.clone()
+                };
+
+                // `SHT_NOBITS` sections do not affect the position of the following sections, or
+                // the file size of the program sections.  `p_memsz` of the containing program
+                // section already accounts for their memory size.
+                if input_section_header.sh_type == SHT_NOBITS {
+                    output_program_headers_updater.observe_file_section(
+                        SectionDimensions {
+                            offset: old_offset,
+                            size: 0,
+                        },
+                        SectionDimensions {
+                            offset: new_offset,
+                            size: 0,
+                        },
+                    );
+                    continue;
+                }
+            }
+        }
 
         output_program_headers_updater.observe_file_section(
             SectionDimensions {
-                offset: old_section_offset,
-                size: old_section_size,
+                offset: old_offset,
+                size: old_size,
             },
             SectionDimensions {
-                offset: new_section_offset,
-                size: new_section_size,
+                offset: new_offset,
+                size: new_size,
             },
         );
 
-        vacant_at = new_section_offset + new_section_size;
+        let new_end = new_offset + new_size;
+        vacant_at = vacant_at.max(new_end);
+        shift = new_offset.saturating_sub(old_offset);
+
+        if let Some(preserved) = trailing_bytes.remove(&region) {
+            vacant_at = new_end + (preserved.end - preserved.start);
+            preserved_bytes.push(PreservedBytes {
+                output_offset: new_end,
+                input: preserved,
+            });
+        }
     }
 
     ComputeShiftsResult {
         program_headers: output_program_headers_updater.into_result(),
         section_headers: output_section_headers,
-        section_headers_start: vacant_at,
+        program_headers_start,
+        section_headers_start,
+        preserved_bytes,
+    }
+}
+
+/// [`LayoutStrategy::AppendToEnd`] part of [`compute_shifts`].
+///
+/// Sections that grow are placed after the end of the input file.  Allocated sections need to be
+/// loaded, so a new `PT_LOAD` program section is added for them, starting after the highest virtual
+/// address used by the input.  As the program headers table grows by one entry, it is moved into
+/// the new `PT_LOAD` as well.  `PT_PHDR`, and any other program section that exactly covers a moved
+/// structure follows it.
+///
+/// Sections that shrink, or do not change size, stay where they are.  Only the `.dynamic` section
+/// entries are updated to point to the new section addresses, see [`update_dynamic_entries`].
+/// Symbols and relocations that reference the moved sections are not updated.
+fn append_grown_sections(
+    input_bytes: &[u8],
+    input_header: &Header,
+    input_program_headers: &[ProgramHeader],
+    input_section_headers: &[SectionHeader],
+    ctx: Ctx,
+    options: LayoutOptions,
+    new_section_sizes: &[u64],
+) -> ComputeShiftsResult {
+    let regions = file_regions(input_header, input_section_headers, ctx);
+
+    // All the unknown bytes stay where they are, just like the structures they follow.
+    let preserved_bytes = {
+        let mut res = preserved_trailing_bytes(input_bytes, &regions, options.unknown_bytes)
+            .into_values()
+            .map(|input| PreservedBytes {
+                output_offset: input.start,
+                input,
+            })
+            .collect::<Vec<_>>();
+        res.sort_by_key(|preserved| preserved.output_offset);
+        res
+    };
+
+    let mut output_section_headers = input_section_headers.to_vec();
+    let mut moved = vec![];
+    for (index, input_section_header) in input_section_headers.iter().enumerate() {
+        if input_section_header.sh_type == SHT_NULL {
+            continue;
+        }
+
+        let new_size = new_section_sizes[index];
+        output_section_headers[index].sh_size = new_size;
+
+        // `SHT_NOBITS` sections do not occupy any space in the file, so they can grow in place.
+        if new_size > input_section_header.sh_size && input_section_header.sh_type != SHT_NOBITS {
+            moved.push(index);
+        }
+    }
+
+    let is_alloc =
+        |index: &usize| input_section_headers[*index].sh_flags & u64::from(SHF_ALLOC) != 0;
+    let loads = input_program_headers
+        .iter()
+        .filter(|header| header.p_type == PT_LOAD)
+        .collect::<Vec<_>>();
+    let needs_new_load = !loads.is_empty() && moved.iter().any(is_alloc);
+
+    // Allocated sections go first, so that the new `PT_LOAD` covers a single range.
+    moved.sort_by_key(|index| (!is_alloc(index), input_section_headers[*index].sh_offset));
+
+    let input_end = regions
+        .iter()
+        .map(|(_, range)| range.end)
+        .fold(input_bytes.len() as u64, u64::max);
+    let append_start = align_offset(input_end, header_table_alignment(ctx));
+    let mut vacant_at = append_start;
+
+    // New program section must be congruent with its file offset, modulo the alignment.
+    let load_align = loads
+        .iter()
+        .map(|header| header.p_align)
+        .max()
+        .unwrap_or(1)
+        .max(1);
+    let load_vaddr = loads
+        .iter()
+        .map(|header| header.p_vaddr + header.p_memsz)
+        .max()
+        .map_or(0, |end| {
+            align_offset(end, load_align) + append_start % load_align
+        });
+    let vaddr_of = |offset: u64| load_vaddr + (offset - append_start);
+
+    let mut output_program_headers = input_program_headers.to_vec();
+    let mut program_headers_start = input_header.e_phoff;
+
+    if needs_new_load {
+        // Space for the new `PT_LOAD` entry.  Entries removed below leave unused space at the end.
+        program_headers_start = vacant_at;
+        vacant_at +=
+            (input_program_headers.len() as u64 + 1) * ProgramHeader::size_with(&ctx) as u64;
+    }
+
+    let mut load_flags = PF_R;
+    let mut load_end = vacant_at;
+    for &index in &moved {
+        let input_section_header = &input_section_headers[index];
+        let output_section_header = &mut output_section_headers[index];
+
+        let new_offset = align_offset(vacant_at, input_section_header.sh_addralign);
+        let new_size = output_section_header.sh_size;
+        output_section_header.sh_offset = new_offset;
+        vacant_at = new_offset + new_size;
+
+        if needs_new_load && is_alloc(&index) {
+            output_section_header.sh_addr = vaddr_of(new_offset);
+            load_end = vacant_at;
+
+            if input_section_header.sh_flags & u64::from(SHF_WRITE) != 0 {
+                load_flags |= PF_W;
+            }
+            if input_section_header.sh_flags & u64::from(SHF_EXECINSTR) != 0 {
+                load_flags |= PF_X;
+            }
+        }
+
+        // Program sections that cover exactly this section, like `PT_DYNAMIC` or `PT_INTERP`, move
+        // together with it.
+        for header in &mut output_program_headers {
+            if header.p_type == PT_LOAD
+                || header.p_offset != input_section_header.sh_offset
+                || header.p_filesz != input_section_header.sh_size
+            {
+                continue;
+            }
+
+            header.p_offset = new_offset;
+            header.p_vaddr = output_section_header.sh_addr;
+            header.p_paddr = output_section_header.sh_addr;
+            header.p_memsz += new_size - header.p_filesz;
+            header.p_filesz = new_size;
+        }
+    }
+
+    if needs_new_load {
+        output_program_headers = trim_vacated_loads(
+            &regions,
+            &moved,
+            output_program_headers,
+            input_program_headers,
+        );
+    }
+
+    if needs_new_load {
+        // `PT_LOAD` entries must be sorted by their virtual addresses, and the new one has the
+        // highest address.
+        let insert_at = output_program_headers
+            .iter()
+            .rposition(|header| header.p_type == PT_LOAD)
+            .map_or(output_program_headers.len(), |i| i + 1);
+        let size = load_end - append_start;
+        output_program_headers.insert(
+            insert_at,
+            ProgramHeader {
+                p_type: PT_LOAD,
+                p_flags: load_flags,
+                p_offset: append_start,
+                p_vaddr: load_vaddr,
+                p_paddr: load_vaddr,
+                p_filesz: size,
+                p_memsz: size,
+                p_align: load_align,
+            },
+        );
+
+        let size = output_program_headers.len() as u64 * ProgramHeader::size_with(&ctx) as u64;
+        for header in &mut output_program_headers {
+            if header.p_type == PT_PHDR {
+                header.p_offset = program_headers_start;
+                header.p_vaddr = vaddr_of(program_headers_start);
+                header.p_paddr = header.p_vaddr;
+                header.p_filesz = size;
+                header.p_memsz = size;
+            }
+        }
+    }
+
+    ComputeShiftsResult {
+        program_headers: output_program_headers,
+        section_headers: output_section_headers,
+        program_headers_start,
+        section_headers_start: input_header.e_shoff,
+        preserved_bytes,
+    }
+}
+
+/// After the `moved` sections and the program headers table are moved into the new `PT_LOAD`,
+/// existing `PT_LOAD` segments that started or ended with a moved structure are trimmed to the
+/// structures that stay, so they do not start or end in the space left behind.  `PT_LOAD` segments
+/// that are left with no structures at all are removed.
+///
+/// If a trimmed segment has `SHT_NOBITS` sections after the file content, its memory end does not
+/// change, so they stay covered.
+fn trim_vacated_loads(
+    regions: &[(FileRegion, Range<u64>)],
+    moved: &[usize],
+    output_program_headers: Vec<ProgramHeader>,
+    input_program_headers: &[ProgramHeader],
+) -> Vec<ProgramHeader> {
+    let is_moved = |region: &FileRegion| match region {
+        FileRegion::ProgramHeaders => true,
+        FileRegion::Section(index) => moved.contains(index),
+        FileRegion::ElfHeader | FileRegion::SectionHeaders => false,
+    };
+
+    output_program_headers
+        .into_iter()
+        .zip(input_program_headers)
+        .filter_map(|(output, input)| {
+            if input.p_type != PT_LOAD || input.p_filesz == 0 {
+                return Some(output);
+            }
+
+            let p_start = input.p_offset;
+            let p_end = p_start + input.p_filesz;
+            let members = regions
+                .iter()
+                .filter(|(_, range)| {
+                    !range.is_empty() && p_start <= range.start && range.end <= p_end
+                })
+                .collect::<Vec<_>>();
+
+            let (Some(first), Some(last)) = (
+                members.iter().min_by_key(|(_, range)| range.start),
+                members.iter().max_by_key(|(_, range)| range.end),
+            ) else {
+                return Some(output);
+            };
+            if !is_moved(&first.0) && !is_moved(&last.0) {
+                return Some(output);
+            }
+
+            let stayed = members
+                .iter()
+                .filter(|(region, _)| !is_moved(region))
+                .map(|(_, range)| range)
+                .collect::<Vec<_>>();
+            // All the content of this `PT_LOAD` is now in the new one.
+            let stayed_start = stayed.iter().map(|range| range.start).min()?;
+            let stayed_end = stayed.iter().map(|range| range.end).max()?;
+
+            let new_start = if is_moved(&first.0) {
+                stayed_start
+            } else {
+                p_start
+            };
+            let new_end = if is_moved(&last.0) { stayed_end } else { p_end };
+            let p_vaddr = input.p_vaddr + (new_start - p_start);
+            let p_filesz = new_end - new_start;
+            let p_memsz = if input.p_memsz > input.p_filesz {
+                input.p_vaddr + input.p_memsz - p_vaddr
+            } else {
+                p_filesz
+            };
+
+            Some(ProgramHeader {
+                p_offset: new_start,
+                p_vaddr,
+                p_paddr: input.p_paddr + (new_start - p_start),
+                p_filesz,
+                p_memsz,
+                ..output
+            })
+        })
+        .collect()
+}
+
+/// When allocated sections are moved to new addresses, `dynamic` section entries that hold the old
+/// section addresses are updated to hold the new ones.  Entries that hold sizes of the moved
+/// tables, like `DT_STRSZ`, are updated to the new section sizes.
+///
+/// Returns `None` when no entry needs to be updated.
+fn update_dynamic_entries(
+    dynamic: &[u8],
+    input_section_headers: &[SectionHeader],
+    output_section_headers: &[SectionHeader],
+    ctx: Ctx,
+) -> Option<Vec<u8>> {
+    /// Address tags, with the tags that hold the size of the table at that address.
+    const ADDRESS_TAGS: &[(u64, Option<u64>)] = &[
+        (DT_HASH, None),
+        (DT_GNU_HASH, None),
+        (DT_STRTAB, Some(DT_STRSZ)),
+        (DT_SYMTAB, None),
+        (DT_RELA, Some(DT_RELASZ)),
+        (DT_REL, Some(DT_RELSZ)),
+        (DT_JMPREL, Some(DT_PLTRELSZ)),
+        (DT_INIT_ARRAY, Some(DT_INIT_ARRAYSZ)),
+        (DT_FINI_ARRAY, Some(DT_FINI_ARRAYSZ)),
+        (DT_PREINIT_ARRAY, Some(DT_PREINIT_ARRAYSZ)),
+        (DT_VERSYM, None),
+        (DT_VERDEF, None),
+        (DT_VERNEED, None),
+    ];
+
+    let moved = input_section_headers
+        .iter()
+        .zip(output_section_headers)
+        .filter(|(input, output)| {
+            input.sh_flags & u64::from(SHF_ALLOC) != 0 && input.sh_addr != output.sh_addr
+        })
+        .map(|(input, output)| (input.sh_addr, output))
+        .collect::<HashMap<_, _>>();
+    if moved.is_empty() {
+        return None;
+    }
+
+    let entry_size = Dyn::size_with(&ctx);
+    // Trailing bytes that do not form a complete entry are kept as is.
+    let mut res = dynamic.to_vec();
+    let mut entries = res
+        .chunks_exact(entry_size)
+        .map(|bytes| {
+            bytes
+                .pread_with::<Dyn>(0, ctx)
+                .expect("`.dynamic` section holds valid `Dyn` entries")
+        })
+        .collect::<Vec<_>>();
+
+    let mut new_sizes = HashMap::new();
+    for entry in &mut entries {
+        let Some((_, size_tag)) = ADDRESS_TAGS.iter().find(|(tag, _)| *tag == entry.d_tag) else {
+            continue;
+        };
+        let Some(output) = moved.get(&entry.d_val) else {
+            continue;
+        };
+
+        entry.d_val = output.sh_addr;
+        if let Some(size_tag) = size_tag {
+            new_sizes.insert(*size_tag, output.sh_size);
+        }
+    }
+    for entry in &mut entries {
+        if let Some(new_size) = new_sizes.get(&entry.d_tag) {
+            entry.d_val = *new_size;
+        }
+    }
+
+    for (i, entry) in entries.into_iter().enumerate() {
+        res.pwrite_with(entry, i * entry_size, ctx)
+            .expect("`Dyn` entries serialize correctly");
+    }
+
+    Some(res)
+}
+
+/// Smallest offset, starting from `offset`, that is congruent with `vaddr` modulo `alignment`.
+fn congruent_offset(offset: u64, vaddr: u64, alignment: u64) -> u64 {
+    if alignment <= 1 {
+        return offset;
+    }
+
+    offset + (vaddr % alignment + alignment - offset % alignment) % alignment
+}
+
+/// Loaders map `PT_LOAD` program sections page by page, so the file offset of each of them must be
+/// congruent with the virtual address, modulo the alignment.  And no two of them may overlap in
+/// memory.
+///
+/// Loaded sections must stay at the same distance from the start of their `PT_LOAD` in the file,
+/// as they are in memory.  [`LayoutStrategy::Pack`] and [`LayoutStrategy::PreserveGaps`] do not
+/// change section addresses, so they can only resize the last section of a `PT_LOAD`.
+fn check_load_segments(
+    program_headers: &[ProgramHeader],
+    section_headers: &[SectionHeader],
+) -> Result<(), String> {
+    let mut loads = program_headers
+        .iter()
+        .enumerate()
+        .filter(|(_, header)| header.p_type == PT_LOAD)
+        .collect::<Vec<_>>();
+
+    for (index, header) in &loads {
+        let ProgramHeader {
+            p_offset,
+            p_vaddr,
+            p_align,
+            ..
+        } = header;
+
+        if *p_align > 1 && p_offset % p_align != p_vaddr % p_align {
+            return Err(format!(
+                "`PT_LOAD` program section offset is not congruent with its virtual address.\n\
+                 Program section index: {index}, offset: 0x{p_offset:x}, vaddr: 0x{p_vaddr:x}, \
+                 align: 0x{p_align:x}",
+            ));
+        }
+    }
+
+    loads.sort_by_key(|(_, header)| header.p_vaddr);
+    for pair in loads.windows(2) {
+        let [(prev_index, prev), (next_index, next)] = pair else {
+            unreachable!("`windows(2)` produces pairs");
+        };
+        let prev_end = prev.p_vaddr.saturating_add(prev.p_memsz);
+
+        if prev_end > next.p_vaddr {
+            return Err(format!(
+                "`PT_LOAD` program sections overlap in memory.\n\
+                 Program section index: {prev_index}, vaddr: 0x{:x}, memsz: 0x{:x}\n\
+                 Program section index: {next_index}, vaddr: 0x{:x}, memsz: 0x{:x}",
+                prev.p_vaddr, prev.p_memsz, next.p_vaddr, next.p_memsz,
+            ));
+        }
+    }
+
+    for (section_index, section) in section_headers.iter().enumerate() {
+        if section.sh_flags & u64::from(SHF_ALLOC) == 0
+            || section.sh_type == SHT_NOBITS
+            || section.sh_size == 0
+        {
+            continue;
+        }
+
+        let Some((index, load)) = loads.iter().find(|(_, load)| {
+            load.p_vaddr <= section.sh_addr && section.sh_addr - load.p_vaddr < load.p_memsz
+        }) else {
+            continue;
+        };
+
+        let offset_in_load = section.sh_offset.checked_sub(load.p_offset);
+        let addr_in_load = section.sh_addr - load.p_vaddr;
+        if offset_in_load != Some(addr_in_load) {
+            return Err(format!(
+                "Section is not placed in the file at its address within the `PT_LOAD` program \
+                 section, so it would be loaded with the wrong content.\n\
+                 Section index: {section_index}, offset: 0x{:x}, addr: 0x{:x}\n\
+                 Program section index: {index}, offset: 0x{:x}, vaddr: 0x{:x}",
+                section.sh_offset, section.sh_addr, load.p_offset, load.p_vaddr,
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Program headers and section headers tables are aligned to the size of the target word.
+pub(crate) fn header_table_alignment(ctx: Ctx) -> u64 {
+    match ctx.container {
+        Container::Little => 4,
+        Container::Big => 8,
+    }
+}
+
+pub(crate) fn align_offset(offset: u64, alignment: u64) -> u64 {
+    if alignment <= 1 {
+        offset
+    } else {
+        offset.next_multiple_of(alignment)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::transformer::ComputeShiftsResult;
+    use crate::{
+        structure::UnknownBytes,
+        transformer::{ComputeShiftsResult, LayoutOptions, LayoutStrategy, PreservedBytes},
+    };
 
     use super::compute_shifts;
 
-    use std::io;
-
     use goblin::{
         container::Ctx,
-        elf::{self, ProgramHeader, SectionHeader},
+        elf::{self, Header, ProgramHeader, SectionHeader},
     };
     use pretty_assertions::assert_eq;
 
+    // There is no program headers table in the file, and the section headers table follows the last
+    // section.
+    fn test_header(section_headers: &[SectionHeader]) -> Header {
+        let mut header = Header::new(Ctx::default());
+        header.e_shoff = section_headers
+            .iter()
+            .map(|header| header.sh_offset + header.sh_size)
+            .max()
+            .unwrap_or(0);
+        header.e_shnum = section_headers.len() as u16;
+        header
+    }
+
     // We only care about program section offsets and sizes, so is nice to have a helper that
     // populates the rest with arbitrary values.
     fn test_program_header(p_offset: u64, p_filesz: u64, p_align: u64) -> ProgramHeader {
@@ -447,6 +1207,16 @@ mod tests {
         }
     }
 
+    // `PT_LOAD` program section, with the virtual address also specified.
+    fn test_load_header(p_offset: u64, p_vaddr: u64, p_filesz: u64, p_align: u64) -> ProgramHeader {
+        ProgramHeader {
+            p_type: elf::program_header::PT_LOAD,
+            p_vaddr,
+            p_paddr: p_vaddr,
+            ..test_program_header(p_offset, p_filesz, p_align)
+        }
+    }
+
     // We only care about section offsets, sizes, and alignment, and a little about the names, so it
     // is nice to have a helper that populates the rest with arbitrary values.
     fn test_section_header(
@@ -475,35 +1245,33 @@ mod tests {
         }
     }
 
-    fn noop_transformer(
-    ) -> Box<impl Fn(&[u8], &SectionHeader, Ctx, &mut dyn io::Write) -> Option<u64>> {
-        Box::new(
-            move |_input_bytes: &[u8],
-                  _section_header: &SectionHeader,
-                  _ctx: Ctx,
-                  _output: &mut dyn io::Write|
-                  -> Option<u64> { None },
-        )
+    // Section sizes when no section is changed.
+    fn unchanged_sizes(section_headers: &[SectionHeader]) -> Vec<u64> {
+        section_headers
+            .iter()
+            .map(|header| header.sh_size)
+            .collect()
     }
 
+    // Section sizes when a single section, identified by its name, changes size.
     fn adjust_single_section(
+        section_headers: &[SectionHeader],
         target_section_name: usize,
         adjustment: i64,
-    ) -> Box<impl Fn(&[u8], &SectionHeader, Ctx, &mut dyn io::Write) -> Option<u64>> {
-        Box::new(
-            move |_input_bytes: &[u8],
-                  section_header: &SectionHeader,
-                  _ctx: Ctx,
-                  _output: &mut dyn io::Write|
-                  -> Option<u64> {
-                (section_header.sh_name == target_section_name).then(|| {
-                    section_header
+    ) -> Vec<u64> {
+        section_headers
+            .iter()
+            .map(|header| {
+                if header.sh_name == target_section_name {
+                    header
                         .sh_size
                         .checked_add_signed(adjustment)
                         .expect("Adjusted section size fits into u64")
-                })
-            },
-        )
+                } else {
+                    header.sh_size
+                }
+            })
+            .collect()
     }
 
     #[test]
@@ -517,18 +1285,23 @@ mod tests {
 
         let res = compute_shifts(
             &[],
+            &test_header(&input_section_headers),
             &input_program_headers,
             &input_section_headers,
             Ctx::default(),
-            noop_transformer(),
-        );
+            LayoutOptions::default(),
+            &unchanged_sizes(&input_section_headers),
+        )
+        .expect("Layout is valid");
 
         assert_eq!(
             res,
             ComputeShiftsResult {
                 program_headers: input_program_headers.clone(),
                 section_headers: input_section_headers.clone(),
+                program_headers_start: 0,
                 section_headers_start: 168,
+                preserved_bytes: vec![],
             }
         );
     }
@@ -544,11 +1317,14 @@ mod tests {
 
         let res = compute_shifts(
             &[],
+            &test_header(&input_section_headers),
             &input_program_headers,
             &input_section_headers,
             Ctx::default(),
-            noop_transformer(),
-        );
+            LayoutOptions::default(),
+            &unchanged_sizes(&input_section_headers),
+        )
+        .expect("Layout is valid");
 
         let expected_section_headers = vec![
             test_section_header(1, 140, 15, 0),
@@ -561,7 +1337,9 @@ mod tests {
             ComputeShiftsResult {
                 program_headers: input_program_headers.clone(),
                 section_headers: expected_section_headers,
+                program_headers_start: 0,
                 section_headers_start: 168,
+                preserved_bytes: vec![],
             }
         );
     }
@@ -577,11 +1355,14 @@ mod tests {
 
         let res = compute_shifts(
             &[],
+            &test_header(&input_section_headers),
             &input_program_headers,
             &input_section_headers,
             Ctx::default(),
-            noop_transformer(),
-        );
+            LayoutOptions::default(),
+            &unchanged_sizes(&input_section_headers),
+        )
+        .expect("Layout is valid");
 
         let expected_program_headers = vec![test_program_header(140, 24, 4)];
         let expected_section_headers = vec![
@@ -595,7 +1376,9 @@ mod tests {
             ComputeShiftsResult {
                 program_headers: expected_program_headers,
                 section_headers: expected_section_headers,
+                program_headers_start: 0,
                 section_headers_start: 168,
+                preserved_bytes: vec![],
             }
         );
     }
@@ -611,11 +1394,14 @@ mod tests {
 
         let res = compute_shifts(
             &[],
+            &test_header(&input_section_headers),
             &input_program_headers,
             &input_section_headers,
             Ctx::default(),
-            adjust_single_section(2, 3),
-        );
+            LayoutOptions::default(),
+            &adjust_single_section(&input_section_headers, 2, 3),
+        )
+        .expect("Layout is valid");
 
         let expected_program_headers = vec![test_program_header(140, 27, 4)];
         let expected_section_headers = vec![
@@ -629,7 +1415,9 @@ mod tests {
             ComputeShiftsResult {
                 program_headers: expected_program_headers,
                 section_headers: expected_section_headers,
-                section_headers_start: 172,
+                program_headers_start: 0,
+                section_headers_start: 176,
+                preserved_bytes: vec![],
             }
         );
     }
@@ -645,11 +1433,14 @@ mod tests {
 
         let res = compute_shifts(
             &[],
+            &test_header(&input_section_headers),
             &input_program_headers,
             &input_section_headers,
             Ctx::default(),
-            adjust_single_section(2, 1),
-        );
+            LayoutOptions::default(),
+            &adjust_single_section(&input_section_headers, 2, 1),
+        )
+        .expect("Layout is valid");
 
         let expected_program_headers = vec![test_program_header(140, 25, 4)];
         let expected_section_headers = vec![
@@ -663,7 +1454,673 @@ mod tests {
             ComputeShiftsResult {
                 program_headers: expected_program_headers,
                 section_headers: expected_section_headers,
-                section_headers_start: 172,
+                program_headers_start: 0,
+                section_headers_start: 176,
+                preserved_bytes: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn compute_shifts_nobits_section_takes_no_file_space() {
+        let input_program_headers = vec![test_program_header(140, 24, 4)];
+        let input_section_headers = vec![
+            test_section_header(1, 140, 15, 0),
+            test_section_header(2, 160, 4, 16),
+            SectionHeader {
+                sh_type: elf::section_header::SHT_NOBITS,
+                ..test_section_header(3, 164, 32, 4)
+            },
+            test_section_header(4, 164, 4, 4),
+        ];
+
+        let res = compute_shifts(
+            &[],
+            &test_header(&input_section_headers),
+            &input_program_headers,
+            &input_section_headers,
+            Ctx::default(),
+            LayoutOptions::default(),
+            &adjust_single_section(&input_section_headers, 2, 3),
+        )
+        .expect("Layout is valid");
+
+        let expected_program_headers = vec![test_program_header(140, 27, 4)];
+        let expected_section_headers = vec![
+            test_section_header(1, 140, 15, 0),
+            test_section_header(2, 160, 7, 16),
+            SectionHeader {
+                sh_type: elf::section_header::SHT_NOBITS,
+                ..test_section_header(3, 168, 32, 4)
+            },
+            test_section_header(4, 168, 4, 4),
+        ];
+
+        assert_eq!(
+            res,
+            ComputeShiftsResult {
+                program_headers: expected_program_headers,
+                section_headers: expected_section_headers,
+                program_headers_start: 0,
+                section_headers_start: 176,
+                preserved_bytes: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn compute_shifts_section_headers_not_in_file_order() {
+        let input_program_headers = vec![test_program_header(140, 24, 4)];
+        let input_section_headers = vec![
+            test_section_header(1, 140, 15, 0),
+            test_section_header(3, 164, 4, 4),
+            test_section_header(2, 160, 4, 16),
+        ];
+
+        let res = compute_shifts(
+            &[],
+            &test_header(&input_section_headers),
+            &input_program_headers,
+            &input_section_headers,
+            Ctx::default(),
+            LayoutOptions::default(),
+            &adjust_single_section(&input_section_headers, 2, 1),
+        )
+        .expect("Layout is valid");
+
+        let expected_program_headers = vec![test_program_header(140, 25, 4)];
+        let expected_section_headers = vec![
+            test_section_header(1, 140, 15, 0),
+            test_section_header(3, 168, 4, 4),
+            test_section_header(2, 160, 5, 16),
+        ];
+
+        assert_eq!(
+            res,
+            ComputeShiftsResult {
+                program_headers: expected_program_headers,
+                section_headers: expected_section_headers,
+                program_headers_start: 0,
+                section_headers_start: 176,
+                preserved_bytes: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn compute_shifts_preserve_unknown_bytes_after_section() {
+        let input_program_headers = vec![test_program_header(140, 24, 4)];
+        let input_section_headers = vec![
+            test_section_header(1, 140, 15, 0),
+            test_section_header(2, 160, 4, 16),
+            test_section_header(3, 164, 4, 4),
+        ];
+        let header = test_header(&input_section_headers);
+
+        let mut input_bytes =
+            vec![0; (header.e_shoff + u64::from(header.e_shentsize) * 3) as usize];
+        input_bytes[156] = 0xaa;
+
+        let res = compute_shifts(
+            &input_bytes,
+            &header,
+            &input_program_headers,
+            &input_section_headers,
+            Ctx::default(),
+            LayoutOptions {
+                unknown_bytes: UnknownBytes::Preserve,
+                ..LayoutOptions::default()
+            },
+            &adjust_single_section(&input_section_headers, 1, 2),
+        )
+        .expect("Layout is valid");
+
+        let expected_program_headers = vec![test_program_header(140, 40, 4)];
+        let expected_section_headers = vec![
+            test_section_header(1, 140, 17, 0),
+            test_section_header(2, 176, 4, 16),
+            test_section_header(3, 180, 4, 4),
+        ];
+
+        assert_eq!(
+            res,
+            ComputeShiftsResult {
+                program_headers: expected_program_headers,
+                section_headers: expected_section_headers,
+                program_headers_start: 0,
+                section_headers_start: 184,
+                preserved_bytes: vec![PreservedBytes {
+                    output_offset: 157,
+                    input: 155..160,
+                }],
+            }
+        );
+    }
+
+    #[test]
+    fn compute_shifts_reject_unknown_bytes_drops_gaps() {
+        let input_program_headers = vec![test_program_header(140, 24, 4)];
+        let input_section_headers = vec![
+            test_section_header(1, 140, 15, 0),
+            test_section_header(2, 160, 4, 16),
+            test_section_header(3, 164, 4, 4),
+        ];
+        let header = test_header(&input_section_headers);
+
+        let mut input_bytes =
+            vec![0; (header.e_shoff + u64::from(header.e_shentsize) * 3) as usize];
+        input_bytes[156] = 0xaa;
+
+        let res = compute_shifts(
+            &input_bytes,
+            &header,
+            &input_program_headers,
+            &input_section_headers,
+            Ctx::default(),
+            LayoutOptions::default(),
+            &adjust_single_section(&input_section_headers, 1, 2),
+        )
+        .expect("Layout is valid");
+
+        let expected_program_headers = vec![test_program_header(140, 24, 4)];
+        let expected_section_headers = vec![
+            test_section_header(1, 140, 17, 0),
+            test_section_header(2, 160, 4, 16),
+            test_section_header(3, 164, 4, 4),
+        ];
+
+        assert_eq!(
+            res,
+            ComputeShiftsResult {
+                program_headers: expected_program_headers,
+                section_headers: expected_section_headers,
+                program_headers_start: 0,
+                section_headers_start: 168,
+                preserved_bytes: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn compute_shifts_moves_tables_with_sections() {
+        let input_header = {
+            let mut res = Header::new(Ctx::default());
+            res.e_phoff = 72;
+            res.e_phnum = 1;
+            res.e_shoff = 128;
+            res.e_shnum = 2;
+            res
+        };
+        let input_program_headers = vec![test_program_header(64, 8, 8)];
+        let input_section_headers = vec![
+            test_section_header(1, 64, 8, 8),
+            test_section_header(2, 256, 4, 4),
+        ];
+
+        let res = compute_shifts(
+            &[],
+            &input_header,
+            &input_program_headers,
+            &input_section_headers,
+            Ctx::default(),
+            LayoutOptions::default(),
+            &adjust_single_section(&input_section_headers, 1, 4),
+        )
+        .expect("Layout is valid");
+
+        assert_eq!(
+            res,
+            ComputeShiftsResult {
+                program_headers: vec![test_program_header(64, 12, 8)],
+                section_headers: vec![
+                    test_section_header(1, 64, 12, 8),
+                    test_section_header(2, 264, 4, 4),
+                ],
+                program_headers_start: 80,
+                section_headers_start: 136,
+                preserved_bytes: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn compute_shifts_preserve_gaps_growth_within_padding() {
+        let input_program_headers = vec![test_program_header(64, 72, 8)];
+        let input_section_headers = vec![
+            test_section_header(1, 64, 8, 8),
+            test_section_header(2, 96, 4, 4),
+            test_section_header(3, 128, 8, 4),
+        ];
+
+        let res = compute_shifts(
+            &[],
+            &test_header(&input_section_headers),
+            &input_program_headers,
+            &input_section_headers,
+            Ctx::default(),
+            LayoutOptions {
+                strategy: LayoutStrategy::PreserveGaps,
+                ..LayoutOptions::default()
+            },
+            &adjust_single_section(&input_section_headers, 1, 4),
+        )
+        .expect("Layout is valid");
+
+        assert_eq!(
+            res,
+            ComputeShiftsResult {
+                program_headers: vec![test_program_header(64, 72, 8)],
+                section_headers: vec![
+                    test_section_header(1, 64, 12, 8),
+                    test_section_header(2, 96, 4, 4),
+                    test_section_header(3, 128, 8, 4),
+                ],
+                program_headers_start: 0,
+                section_headers_start: 136,
+                preserved_bytes: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn compute_shifts_preserve_gaps_growth_past_padding() {
+        let input_program_headers = vec![test_program_header(64, 72, 8)];
+        let input_section_headers = vec![
+            test_section_header(1, 64, 8, 8),
+            test_section_header(2, 96, 4, 4),
+            test_section_header(3, 128, 8, 4),
+        ];
+
+        let res = compute_shifts(
+            &[],
+            &test_header(&input_section_headers),
+            &input_program_headers,
+            &input_section_headers,
+            Ctx::default(),
+            LayoutOptions {
+                strategy: LayoutStrategy::PreserveGaps,
+                ..LayoutOptions::default()
+            },
+            &adjust_single_section(&input_section_headers, 1, 40),
+        )
+        .expect("Layout is valid");
+
+        // Section 2 is moved right after section 1, and section 3 keeps its original gap of 28 bytes
+        // after section 2.
+        assert_eq!(
+            res,
+            ComputeShiftsResult {
+                program_headers: vec![test_program_header(64, 88, 8)],
+                section_headers: vec![
+                    test_section_header(1, 64, 48, 8),
+                    test_section_header(2, 112, 4, 4),
+                    test_section_header(3, 144, 8, 4),
+                ],
+                program_headers_start: 0,
+                section_headers_start: 152,
+                preserved_bytes: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn compute_shifts_append_to_end_moves_grown_section() {
+        let input_header = {
+            let mut res = Header::new(Ctx::default());
+            res.e_phoff = 64;
+            res.e_phnum = 2;
+            res.e_shoff = 192;
+            res.e_shnum = 3;
+            res
+        };
+        let phdr = ProgramHeader {
+            p_type: elf::program_header::PT_PHDR,
+            p_flags: elf::program_header::PF_R,
+            p_offset: 64,
+            p_vaddr: 64,
+            p_paddr: 64,
+            p_filesz: 112,
+            p_memsz: 112,
+            p_align: 8,
+        };
+        let load = ProgramHeader {
+            p_type: elf::program_header::PT_LOAD,
+            p_flags: elf::program_header::PF_R,
+            p_offset: 0,
+            p_vaddr: 0,
+            p_paddr: 0,
+            p_filesz: 192,
+            p_memsz: 192,
+            p_align: 0x1000,
+        };
+        let alloc_section = |sh_name, sh_offset| SectionHeader {
+            sh_flags: u64::from(elf::section_header::SHF_ALLOC),
+            sh_addr: sh_offset,
+            ..test_section_header(sh_name, sh_offset, 8, 8)
+        };
+        let input_program_headers = vec![phdr.clone(), load.clone()];
+        let input_section_headers = vec![
+            SectionHeader::default(),
+            alloc_section(1, 176),
+            alloc_section(2, 184),
+        ];
+
+        let res = compute_shifts(
+            &[],
+            &input_header,
+            &input_program_headers,
+            &input_section_headers,
+            Ctx::default(),
+            LayoutOptions {
+                strategy: LayoutStrategy::AppendToEnd,
+                ..LayoutOptions::default()
+            },
+            &adjust_single_section(&input_section_headers, 1, 4),
+        )
+        .expect("Layout is valid");
+
+        // Program headers table, with one more entry, and the grown section are placed after the
+        // section headers table.  The new `PT_LOAD` starts on the next page in memory, keeping the
+        // offset within a page the same as in the file.
+        assert_eq!(
+            res,
+            ComputeShiftsResult {
+                program_headers: vec![
+                    ProgramHeader {
+                        p_offset: 384,
+                        p_vaddr: 0x1180,
+                        p_paddr: 0x1180,
+                        p_filesz: 168,
+                        p_memsz: 168,
+                        ..phdr
+                    },
+                    load.clone(),
+                    ProgramHeader {
+                        p_offset: 384,
+                        p_vaddr: 0x1180,
+                        p_paddr: 0x1180,
+                        p_filesz: 180,
+                        p_memsz: 180,
+                        ..load
+                    },
+                ],
+                section_headers: vec![
+                    SectionHeader::default(),
+                    SectionHeader {
+                        sh_offset: 552,
+                        sh_addr: 0x1228,
+                        sh_size: 12,
+                        ..alloc_section(1, 176)
+                    },
+                    alloc_section(2, 184),
+                ],
+                program_headers_start: 384,
+                section_headers_start: 192,
+                preserved_bytes: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn compute_shifts_append_to_end_trims_vacated_loads() {
+        let input_header = {
+            let mut res = Header::new(Ctx::default());
+            res.e_phoff = 64;
+            res.e_phnum = 2;
+            res.e_shoff = 192;
+            res.e_shnum = 3;
+            res
+        };
+        let alloc_section = |sh_name, sh_offset, sh_addr| SectionHeader {
+            sh_flags: u64::from(elf::section_header::SHF_ALLOC),
+            sh_addr,
+            ..test_section_header(sh_name, sh_offset, 8, 8)
+        };
+        // First `PT_LOAD` holds the ELF header, the program headers table and section 1.  Second
+        // one holds only section 2.
+        let first_load = test_load_header(0, 0, 184, 0x1000);
+        let input_program_headers =
+            vec![first_load.clone(), test_load_header(184, 0x10b8, 8, 0x1000)];
+        let input_section_headers = vec![
+            SectionHeader::default(),
+            alloc_section(1, 176, 176),
+            alloc_section(2, 184, 0x10b8),
+        ];
+
+        let res = compute_shifts(
+            &[],
+            &input_header,
+            &input_program_headers,
+            &input_section_headers,
+            Ctx::default(),
+            LayoutOptions {
+                strategy: LayoutStrategy::AppendToEnd,
+                ..LayoutOptions::default()
+            },
+            &[0, 12, 12],
+        )
+        .expect("Layout is valid");
+
+        // First `PT_LOAD` is left with just the ELF header.  Second `PT_LOAD` is left empty, and is
+        // removed.
+        assert_eq!(
+            res,
+            ComputeShiftsResult {
+                program_headers: vec![
+                    ProgramHeader {
+                        p_filesz: 64,
+                        p_memsz: 64,
+                        ..first_load.clone()
+                    },
+                    ProgramHeader {
+                        p_flags: elf::program_header::PF_R,
+                        p_offset: 384,
+                        p_vaddr: 0x2180,
+                        p_paddr: 0x2180,
+                        p_filesz: 196,
+                        p_memsz: 196,
+                        ..first_load
+                    },
+                ],
+                section_headers: vec![
+                    SectionHeader::default(),
+                    SectionHeader {
+                        sh_offset: 552,
+                        sh_addr: 0x2228,
+                        sh_size: 12,
+                        ..alloc_section(1, 176, 176)
+                    },
+                    SectionHeader {
+                        sh_offset: 568,
+                        sh_addr: 0x2238,
+                        sh_size: 12,
+                        ..alloc_section(2, 184, 0x10b8)
+                    },
+                ],
+                program_headers_start: 384,
+                section_headers_start: 192,
+                preserved_bytes: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn compute_shifts_keeps_load_congruence() {
+        let input_program_headers = vec![
+            test_load_header(64, 64, 8, 0x100),
+            test_load_header(256, 0x200, 8, 0x100),
+        ];
+        let input_section_headers = vec![
+            test_section_header(1, 64, 8, 8),
+            test_section_header(2, 256, 8, 8),
+        ];
+
+        let res = compute_shifts(
+            &[],
+            &test_header(&input_section_headers),
+            &input_program_headers,
+            &input_section_headers,
+            Ctx::default(),
+            LayoutOptions::default(),
+            &adjust_single_section(&input_section_headers, 1, 4),
+        )
+        .expect("Layout is valid");
+
+        // Section 2 would fit at 80, but it starts a `PT_LOAD` with a virtual address at the start
+        // of a 0x100 block.
+        assert_eq!(
+            res,
+            ComputeShiftsResult {
+                program_headers: vec![
+                    test_load_header(64, 64, 12, 0x100),
+                    test_load_header(256, 0x200, 8, 0x100),
+                ],
+                section_headers: vec![
+                    test_section_header(1, 64, 12, 8),
+                    test_section_header(2, 256, 8, 8),
+                ],
+                program_headers_start: 0,
+                section_headers_start: 264,
+                preserved_bytes: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn compute_shifts_refuses_loads_overlapping_in_memory() {
+        let input_program_headers = vec![
+            test_load_header(64, 64, 8, 8),
+            test_load_header(256, 72, 8, 8),
+        ];
+        let input_section_headers = vec![
+            test_section_header(1, 64, 8, 8),
+            test_section_header(2, 256, 8, 8),
+        ];
+
+        let res = compute_shifts(
+            &[],
+            &test_header(&input_section_headers),
+            &input_program_headers,
+            &input_section_headers,
+            Ctx::default(),
+            LayoutOptions::default(),
+            &adjust_single_section(&input_section_headers, 1, 4),
+        );
+
+        let err = res.expect_err("First `PT_LOAD` grows into the second one");
+        assert!(
+            err.starts_with("`PT_LOAD` program sections overlap in memory."),
+            "Unexpected error: {err}",
+        );
+    }
+
+    #[test]
+    fn compute_shifts_refuses_moving_loaded_sections() {
+        let loaded_section = |sh_name, sh_offset| SectionHeader {
+            sh_flags: elf::section_header::SHF_ALLOC.into(),
+            sh_addr: sh_offset,
+            ..test_section_header(sh_name, sh_offset, 8, 8)
+        };
+        let input_program_headers = vec![test_load_header(64, 64, 16, 8)];
+        let input_section_headers = vec![loaded_section(1, 64), loaded_section(2, 72)];
+
+        // Growing the last section of a `PT_LOAD` keeps the other sections in place.
+        compute_shifts(
+            &[],
+            &test_header(&input_section_headers),
+            &input_program_headers,
+            &input_section_headers,
+            Ctx::default(),
+            LayoutOptions::default(),
+            &adjust_single_section(&input_section_headers, 2, 8),
+        )
+        .expect("Layout is valid");
+
+        // Section 2 is moved in the file, but not in memory.
+        let res = compute_shifts(
+            &[],
+            &test_header(&input_section_headers),
+            &input_program_headers,
+            &input_section_headers,
+            Ctx::default(),
+            LayoutOptions::default(),
+            &adjust_single_section(&input_section_headers, 1, 8),
+        );
+
+        let err = res.expect_err("Section 2 is moved away from its address");
+        assert!(
+            err.starts_with("Section is not placed in the file at its address"),
+            "Unexpected error: {err}",
+        );
+    }
+
+    #[test]
+    fn compute_shifts_updates_overlapping_program_sections() {
+        let input_program_headers = vec![
+            // Covers all the sections, like a `PT_LOAD`.
+            test_program_header(64, 24, 8),
+            // Nested inside the first one, like `PT_DYNAMIC`.
+            test_program_header(72, 8, 8),
+            // Spans several sections, like `PT_GNU_RELRO`.
+            test_program_header(72, 16, 8),
+            // Takes no space in the file, like `PT_GNU_STACK`.
+            test_program_header(0, 0, 16),
+            // Takes no space in the file, but follows an `SHT_NOBITS` section, like a `PT_TLS`
+            // with only `.tbss`.
+            test_program_header(88, 0, 8),
+        ];
+        let input_section_headers = vec![
+            test_section_header(1, 64, 8, 8),
+            test_section_header(2, 72, 8, 8),
+            test_section_header(3, 80, 8, 8),
+            SectionHeader {
+                sh_type: elf::section_header::SHT_NOBITS,
+                ..test_section_header(4, 88, 16, 8)
+            },
+        ];
+
+        let res = compute_shifts(
+            &[],
+            &test_header(&input_section_headers),
+            &input_program_headers,
+            &input_section_headers,
+            Ctx::default(),
+            LayoutOptions::default(),
+            &adjust_single_section(&input_section_headers, 1, 8),
+        )
+        .expect("Layout is valid");
+
+        assert_eq!(
+            res,
+            ComputeShiftsResult {
+                program_headers: vec![
+                    test_program_header(64, 32, 8),
+                    ProgramHeader {
+                        p_offset: 80,
+                        ..test_program_header(72, 8, 8)
+                    },
+                    ProgramHeader {
+                        p_offset: 80,
+                        ..test_program_header(72, 16, 8)
+                    },
+                    test_program_header(0, 0, 16),
+                    ProgramHeader {
+                        p_offset: 96,
+                        ..test_program_header(88, 0, 8)
+                    },
+                ],
+                section_headers: vec![
+                    test_section_header(1, 64, 16, 8),
+                    test_section_header(2, 80, 8, 8),
+                    test_section_header(3, 88, 8, 8),
+                    SectionHeader {
+                        sh_type: elf::section_header::SHT_NOBITS,
+                        ..test_section_header(4, 96, 16, 8)
+                    },
+                ],
+                program_headers_start: 0,
+                section_headers_start: 96,
+                preserved_bytes: vec![],
             }
         );
     }
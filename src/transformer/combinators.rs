@@ -0,0 +1,430 @@
+//! Building blocks for custom [`SectionTransformer`]s.
+//!
+//! ```no_run
+//! use std::fs::{self, File};
+//!
+//! use elf_editor::transformer::{
+//!     combinators::{map_bytes, only_section_named},
+//!     transform_elf_sections, LayoutOptions, SectionTransformer as _,
+//! };
+//! use goblin::{container::Ctx, elf::Elf};
+//!
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let input_bytes = fs::read("input.so")?;
+//! let elf = Elf::parse(&input_bytes)?;
+//! let ctx = Ctx::new(elf.header.container()?, elf.header.endianness()?);
+//!
+//! let uppercase = map_bytes(|bytes: &[u8]| bytes.to_ascii_uppercase());
+//! let clear = map_bytes(|_: &[u8]| vec![]);
+//! let transformer = only_section_named(&elf, ".comment", uppercase)
+//!     .expect("Input has a .comment section")
+//!     .chain(only_section_named(&elf, ".note", clear).expect("Input has a .note section"));
+//!
+//! let output = File::create("output.so")?;
+//! let options = LayoutOptions::default();
+//! transform_elf_sections(&input_bytes, &elf, ctx, output, options, transformer)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::io;
+
+use goblin::{
+    container::Ctx,
+    elf::{section_header::SHT_NOBITS, Elf, SectionHeader},
+};
+
+use crate::inspect::find_in_strtab;
+
+use super::{SectionPlan, SectionTransformer};
+
+/// Copies all the sections unchanged.
+pub struct KeepAsIs;
+
+impl SectionTransformer for KeepAsIs {
+    type Plan = ();
+
+    fn plan(
+        &self,
+        _input_bytes: &[u8],
+        _section_index: usize,
+        _section_header: &SectionHeader,
+        _ctx: Ctx,
+    ) -> Option<SectionPlan<Self::Plan>> {
+        None
+    }
+
+    fn write(
+        &self,
+        _input_bytes: &[u8],
+        _section_index: usize,
+        _section_header: &SectionHeader,
+        _ctx: Ctx,
+        _plan: Self::Plan,
+        _output: &mut dyn io::Write,
+    ) {
+        unreachable!("`plan()` never returns a plan")
+    }
+}
+
+/// Applies `second` to the output of `first`, for every section.  See
+/// [`SectionTransformer::chain()`].
+pub struct Chain<First, Second> {
+    first: First,
+    second: Second,
+}
+
+impl<First, Second> Chain<First, Second> {
+    pub fn new(first: First, second: Second) -> Self {
+        Self { first, second }
+    }
+}
+
+impl<First, Second> SectionTransformer for Chain<First, Second>
+where
+    First: SectionTransformer,
+    Second: SectionTransformer,
+{
+    /// Final section bytes.  `second` needs to see the bytes produced by `first`, so both are
+    /// fully applied during planning.
+    type Plan = Vec<u8>;
+
+    fn plan(
+        &self,
+        input_bytes: &[u8],
+        section_index: usize,
+        section_header: &SectionHeader,
+        ctx: Ctx,
+    ) -> Option<SectionPlan<Self::Plan>> {
+        let Some(SectionPlan { new_size, plan }) =
+            self.first
+                .plan(input_bytes, section_index, section_header, ctx)
+        else {
+            let SectionPlan { new_size, plan } =
+                self.second
+                    .plan(input_bytes, section_index, section_header, ctx)?;
+            let mut bytes = Vec::with_capacity(new_size as usize);
+            if section_header.sh_type != SHT_NOBITS {
+                self.second.write(
+                    input_bytes,
+                    section_index,
+                    section_header,
+                    ctx,
+                    plan,
+                    &mut bytes,
+                );
+            }
+            return Some(SectionPlan {
+                new_size,
+                plan: bytes,
+            });
+        };
+
+        // `SHT_NOBITS` sections only have a size.
+        if section_header.sh_type == SHT_NOBITS {
+            let intermediate_header = SectionHeader {
+                sh_size: new_size,
+                ..section_header.clone()
+            };
+            let new_size = self
+                .second
+                .plan(&[], section_index, &intermediate_header, ctx)
+                .map_or(new_size, |plan| plan.new_size);
+            return Some(SectionPlan {
+                new_size,
+                plan: vec![],
+            });
+        }
+
+        let mut intermediate = Vec::with_capacity(new_size as usize);
+        self.first.write(
+            input_bytes,
+            section_index,
+            section_header,
+            ctx,
+            plan,
+            &mut intermediate,
+        );
+
+        // `second` sees the output of `first` as the whole input file, with the section at the
+        // very start.
+        let intermediate_header = SectionHeader {
+            sh_offset: 0,
+            sh_size: intermediate.len() as u64,
+            ..section_header.clone()
+        };
+        let bytes = match self
+            .second
+            .plan(&intermediate, section_index, &intermediate_header, ctx)
+        {
+            Some(SectionPlan { new_size, plan }) => {
+                let mut bytes = Vec::with_capacity(new_size as usize);
+                self.second.write(
+                    &intermediate,
+                    section_index,
+                    &intermediate_header,
+                    ctx,
+                    plan,
+                    &mut bytes,
+                );
+                bytes
+            }
+            None => intermediate,
+        };
+
+        Some(SectionPlan {
+            new_size: bytes.len() as u64,
+            plan: bytes,
+        })
+    }
+
+    fn write(
+        &self,
+        _input_bytes: &[u8],
+        _section_index: usize,
+        _section_header: &SectionHeader,
+        _ctx: Ctx,
+        plan: Self::Plan,
+        output: &mut dyn io::Write,
+    ) {
+        output
+            .write_all(&plan)
+            .expect("Output can consume all the section data");
+    }
+}
+
+/// Applies `inner` only to the section with the specified name.  See [`only_section_named()`].
+pub struct OnlySectionNamed<Inner> {
+    /// Offset of the section name in the section headers string table.
+    sh_name: usize,
+    inner: Inner,
+}
+
+impl<Inner> OnlySectionNamed<Inner> {
+    /// `sh_name` is the offset of the section name in the section headers string table, as stored
+    /// in [`SectionHeader::sh_name`].
+    pub fn new(sh_name: usize, inner: Inner) -> Self {
+        Self { sh_name, inner }
+    }
+}
+
+/// Applies `inner` only to the section called `name`.  Returns `None` if the `elf` section headers
+/// string table does not contain `name`.
+pub fn only_section_named<Inner>(
+    elf: &Elf,
+    name: &str,
+    inner: Inner,
+) -> Option<OnlySectionNamed<Inner>> {
+    let sh_name = find_in_strtab(&elf.shdr_strtab, name)?;
+    Some(OnlySectionNamed::new(sh_name, inner))
+}
+
+impl<Inner> SectionTransformer for OnlySectionNamed<Inner>
+where
+    Inner: SectionTransformer,
+{
+    type Plan = Inner::Plan;
+
+    fn plan(
+        &self,
+        input_bytes: &[u8],
+        section_index: usize,
+        section_header: &SectionHeader,
+        ctx: Ctx,
+    ) -> Option<SectionPlan<Self::Plan>> {
+        if section_header.sh_name != self.sh_name {
+            return None;
+        }
+
+        self.inner
+            .plan(input_bytes, section_index, section_header, ctx)
+    }
+
+    fn write(
+        &self,
+        input_bytes: &[u8],
+        section_index: usize,
+        section_header: &SectionHeader,
+        ctx: Ctx,
+        plan: Self::Plan,
+        output: &mut dyn io::Write,
+    ) {
+        self.inner.write(
+            input_bytes,
+            section_index,
+            section_header,
+            ctx,
+            plan,
+            output,
+        )
+    }
+}
+
+/// Replaces section bytes with the result of a callback.  See [`map_bytes()`].
+pub struct MapBytes<Callback> {
+    callback: Callback,
+}
+
+/// Replaces the bytes of every section that occupies space in the file with the result of the
+/// `callback`.  Sections where `callback` returns the same bytes, or that point outside of the
+/// input, are not changed.  Usually combined with [`only_section_named()`].
+pub fn map_bytes<Callback>(callback: Callback) -> MapBytes<Callback>
+where
+    Callback: Fn(&[u8]) -> Vec<u8>,
+{
+    MapBytes { callback }
+}
+
+impl<Callback> SectionTransformer for MapBytes<Callback>
+where
+    Callback: Fn(&[u8]) -> Vec<u8>,
+{
+    type Plan = Vec<u8>;
+
+    fn plan(
+        &self,
+        input_bytes: &[u8],
+        _section_index: usize,
+        section_header: &SectionHeader,
+        _ctx: Ctx,
+    ) -> Option<SectionPlan<Self::Plan>> {
+        if section_header.sh_type == SHT_NOBITS {
+            return None;
+        }
+
+        let start = section_header.sh_offset as usize;
+        let end = start.saturating_add(section_header.sh_size as usize);
+        let input = input_bytes.get(start..end)?;
+
+        let bytes = (self.callback)(input);
+        if bytes == input {
+            return None;
+        }
+
+        Some(SectionPlan {
+            new_size: bytes.len() as u64,
+            plan: bytes,
+        })
+    }
+
+    fn write(
+        &self,
+        _input_bytes: &[u8],
+        _section_index: usize,
+        _section_header: &SectionHeader,
+        _ctx: Ctx,
+        plan: Self::Plan,
+        output: &mut dyn io::Write,
+    ) {
+        output
+            .write_all(&plan)
+            .expect("Output can consume all the section data");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use goblin::{container::Ctx, elf::SectionHeader};
+    use pretty_assertions::assert_eq;
+
+    use crate::transformer::{SectionPlan, SectionTransformer};
+
+    use super::{map_bytes, KeepAsIs, OnlySectionNamed};
+
+    // Runs both phases of the `transformer` for a single section, returning the new section bytes,
+    // or `None` if the section is not changed.
+    fn apply(
+        transformer: &impl SectionTransformer,
+        input_bytes: &[u8],
+        section_index: usize,
+        section_header: &SectionHeader,
+    ) -> Option<Vec<u8>> {
+        let ctx = Ctx::default();
+        let SectionPlan { new_size, plan } =
+            transformer.plan(input_bytes, section_index, section_header, ctx)?;
+
+        let mut res = vec![];
+        transformer.write(
+            input_bytes,
+            section_index,
+            section_header,
+            ctx,
+            plan,
+            &mut res,
+        );
+        assert_eq!(res.len() as u64, new_size);
+        Some(res)
+    }
+
+    fn test_section_header(sh_name: usize, sh_offset: u64, sh_size: u64) -> SectionHeader {
+        SectionHeader {
+            sh_name,
+            sh_offset,
+            sh_size,
+            ..SectionHeader::default()
+        }
+    }
+
+    #[test]
+    fn chain_applies_second_to_output_of_first() {
+        let input_bytes = b"..abc..";
+        let section = test_section_header(1, 2, 3);
+
+        let transformer = map_bytes(|bytes: &[u8]| bytes.to_ascii_uppercase())
+            .chain(map_bytes(|bytes: &[u8]| [bytes, b"!"].concat()));
+
+        assert_eq!(
+            apply(&transformer, input_bytes, 1, &section),
+            Some(b"ABC!".to_vec())
+        );
+    }
+
+    #[test]
+    fn chain_with_unchanged_first() {
+        let input_bytes = b"..abc..";
+        let section = test_section_header(1, 2, 3);
+
+        let transformer = KeepAsIs.chain(map_bytes(|bytes: &[u8]| bytes.to_ascii_uppercase()));
+
+        assert_eq!(
+            apply(&transformer, input_bytes, 1, &section),
+            Some(b"ABC".to_vec())
+        );
+    }
+
+    #[test]
+    fn only_section_named_skips_other_sections() {
+        let input_bytes = b"abcdef";
+        let target = test_section_header(1, 0, 3);
+        let other = test_section_header(7, 3, 3);
+
+        let transformer =
+            OnlySectionNamed::new(1, map_bytes(|bytes: &[u8]| bytes.to_ascii_uppercase()));
+
+        assert_eq!(
+            apply(&transformer, input_bytes, 1, &target),
+            Some(b"ABC".to_vec())
+        );
+        assert_eq!(apply(&transformer, input_bytes, 1, &other), None);
+    }
+
+    #[test]
+    fn map_bytes_same_bytes_is_no_change() {
+        let input_bytes = b"abc";
+        let section = test_section_header(1, 0, 3);
+
+        let transformer = map_bytes(|bytes: &[u8]| bytes.to_vec());
+
+        assert_eq!(apply(&transformer, input_bytes, 1, &section), None);
+    }
+
+    #[test]
+    fn map_bytes_section_outside_of_input_is_no_change() {
+        let input_bytes = b"abc";
+        let section = test_section_header(1, 2, 3);
+
+        let transformer = map_bytes(|bytes: &[u8]| bytes.to_ascii_uppercase());
+
+        assert_eq!(apply(&transformer, input_bytes, 1, &section), None);
+    }
+}
@@ -0,0 +1,89 @@
+//! Reports all the places where the input ELF structure differs from what the rest of the tool
+//! expects.
+
+use goblin::{container::Ctx, elf::Elf};
+
+use elf_editor::structure::{find_structure_violations, Violation};
+
+use crate::args::verify::VerifyArgs;
+
+/// Maximum number of bytes shown for a single violation.
+const HEXDUMP_MAX_BYTES: u64 = 64;
+
+const HEXDUMP_BYTES_PER_LINE: usize = 16;
+
+/// Returns `true` if the input structure is supported.
+pub fn run(input_bytes: &[u8], elf: &Elf, ctx: Ctx, args: VerifyArgs) -> bool {
+    let VerifyArgs { unknown_bytes } = args;
+
+    let violations = find_structure_violations(input_bytes, elf, ctx, unknown_bytes.into());
+
+    if violations.is_empty() {
+        println!("ELF structure is supported");
+        return true;
+    }
+
+    println!("Found {} structure violation(s)", violations.len());
+    for (i, violation) in violations.iter().enumerate() {
+        println!();
+        print_violation(input_bytes, i + 1, violation);
+    }
+
+    false
+}
+
+fn print_violation(input_bytes: &[u8], number: usize, violation: &Violation) {
+    let Violation {
+        kind,
+        message,
+        range,
+    } = violation;
+
+    println!("#{number}: {kind}");
+    for line in message.lines() {
+        println!("  {line}");
+    }
+    println!("  Offsets: 0x{:0>16x} - 0x{:0>16x}", range.start, range.end);
+
+    let dump_end = range
+        .end
+        .min(range.start.saturating_add(HEXDUMP_MAX_BYTES))
+        .min(input_bytes.len() as u64);
+    if range.start >= dump_end {
+        return;
+    }
+
+    for (line_i, line) in input_bytes[range.start as usize..dump_end as usize]
+        .chunks(HEXDUMP_BYTES_PER_LINE)
+        .enumerate()
+    {
+        print_hexdump_line(range.start + (line_i * HEXDUMP_BYTES_PER_LINE) as u64, line);
+    }
+
+    if range.end > dump_end {
+        println!("    ... 0x{:x} more bytes", range.end - dump_end);
+    }
+}
+
+fn print_hexdump_line(offset: u64, bytes: &[u8]) {
+    let hex = bytes
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let ascii = bytes
+        .iter()
+        .map(|&b| {
+            if b.is_ascii_graphic() || b == b' ' {
+                b as char
+            } else {
+                '.'
+            }
+        })
+        .collect::<String>();
+
+    println!(
+        "    0x{offset:0>16x}: {hex:<width$} |{ascii}|",
+        width = HEXDUMP_BYTES_PER_LINE * 3 - 1,
+    );
+}
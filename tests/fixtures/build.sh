@@ -0,0 +1,43 @@
+#!/bin/bash -e
+#
+# Rebuilds the fixture ELF files from the sources in `src/`.
+#
+# Requires `gcc`, `llvm-mc` and `rust-lld`, from the Rust toolchain.
+
+cd "$(dirname "$0")"
+
+LLD=${LLD:-$(find "$(rustc --print sysroot)" -name rust-lld -type f | head -n 1)}
+BUILD=$(mktemp -d)
+trap 'rm -rf "$BUILD"' EXIT
+
+# Most fixtures are linked with `-z norelro`, as `PT_GNU_RELRO` segments that end in the middle of
+# a section are not supported yet.
+
+gcc -shared -fPIC -O1 -Wl,-z,norelro -Wl,--hash-style=gnu -Wl,--build-id=none \
+  -o x86_64.so src/x86_64.c
+
+gcc -O1 -Wl,-z,norelro -Wl,--hash-style=gnu -Wl,--build-id=none \
+  -o x86_64-exe src/x86_64-exe.c
+
+llvm-mc -triple aarch64-linux-gnu -filetype=obj -o "$BUILD/aarch64.o" src/aarch64.s
+"$LLD" -flavor gnu -pie -z norelro --hash-style=gnu --export-dynamic \
+  -o aarch64-pie "$BUILD/aarch64.o"
+
+llvm-mc -triple i386-linux-gnu -filetype=obj -o "$BUILD/i386.o" src/i386.s
+"$LLD" -flavor gnu -m elf_i386 -shared -z norelro --hash-style=both \
+  -o i386.so "$BUILD/i386.o"
+
+llvm-mc -triple powerpc-linux-gnu -filetype=obj -o "$BUILD/powerpc.o" src/powerpc.s
+"$LLD" -flavor gnu -shared -z norelro --hash-style=sysv \
+  -o powerpc.so "$BUILD/powerpc.o"
+
+# `lld` does not link `EM_BPF` objects.  As the program has no relocations, it is linked as an
+# x86_64 object, and `e_machine` is switched back to `EM_BPF` afterwards.
+set_machine() {
+  printf "$2" | dd of="$1" bs=1 seek=18 count=2 conv=notrunc status=none
+}
+llvm-mc -triple bpfel -filetype=obj -o "$BUILD/sbpf.o" src/sbpf.s
+set_machine "$BUILD/sbpf.o" '\x3e\x00'
+"$LLD" -flavor gnu -shared -z notext --entry entrypoint --script src/sbpf.ld \
+  -o sbpf.so "$BUILD/sbpf.o"
+set_machine sbpf.so '\xf7\x00'
@@ -0,0 +1,32 @@
+// Position independent executable, with a relative relocation and a data section.
+
+    .text
+    .globl _start
+    .type _start,@function
+_start:
+    adrp x0, pointer
+    ldr x0, [x0, :lo12:pointer]
+    ldr w0, [x0]
+    mov x8, #93
+    svc #0
+    .size _start, .-_start
+
+    .data
+    .globl value
+    .type value,@object
+value:
+    .word 42
+    .size value, 4
+
+    .p2align 3
+    .type pointer,@object
+pointer:
+    .xword value
+    .size pointer, 8
+
+    .bss
+    .globl buffer
+    .type buffer,@object
+buffer:
+    .zero 64
+    .size buffer, 64
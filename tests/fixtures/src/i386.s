@@ -0,0 +1,17 @@
+// 32 bit shared object.
+
+    .text
+    .globl entrypoint
+    .type entrypoint,@function
+entrypoint:
+    movl 4(%esp), %eax
+    addl $1, %eax
+    ret
+    .size entrypoint, .-entrypoint
+
+    .data
+    .globl counter
+    .type counter,@object
+counter:
+    .long 1
+    .size counter, 4
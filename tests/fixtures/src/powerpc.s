@@ -0,0 +1,16 @@
+// Big-endian 32 bit shared object.
+
+    .text
+    .globl entrypoint
+    .type entrypoint,@function
+entrypoint:
+    addi 3, 3, 1
+    blr
+    .size entrypoint, .-entrypoint
+
+    .data
+    .globl counter
+    .type counter,@object
+counter:
+    .long 1
+    .size counter, 4
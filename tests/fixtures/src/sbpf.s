@@ -0,0 +1,21 @@
+// Solana sBPF program: an `entrypoint` function, that reads a constant from `.rodata`.
+
+    .text
+    .globl entrypoint
+    .type entrypoint,@function
+entrypoint:
+    call helper
+    exit
+    .size entrypoint, .-entrypoint
+
+    .type helper,@function
+helper:
+    r0 = 0
+    exit
+    .size helper, .-helper
+
+    .section .rodata
+    .type answer,@object
+answer:
+    .quad 42
+    .size answer, 8
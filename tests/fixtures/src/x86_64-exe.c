@@ -0,0 +1,8 @@
+// Dynamically linked executable, with a `PT_INTERP` program section.
+
+#include <stdio.h>
+
+int main(void) {
+  puts("hello");
+  return 0;
+}
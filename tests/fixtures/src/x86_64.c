@@ -0,0 +1,13 @@
+// Shared object with a few dynamic symbols, data, and relocations.
+
+int counter = 1;
+static const char message[] = "hello";
+
+const char *get_message(void) { return message; }
+
+int next(void) { return ++counter; }
+
+int entrypoint(const unsigned char *input) {
+  (void)input;
+  return next();
+}
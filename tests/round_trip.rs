@@ -0,0 +1,752 @@
+//! Tests that run over the fixture ELF files in `tests/fixtures`.  See `tests/fixtures/build.sh`
+//! for how the fixtures are produced.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+};
+
+use goblin::{
+    container::Ctx,
+    elf::{
+        program_header::PT_LOAD,
+        section_header::{SHT_DYNSYM, SHT_GNU_HASH},
+        Elf, Sym, Symtab,
+    },
+};
+use pretty_assertions::assert_eq;
+use scroll::Pread as _;
+
+use elf_editor::structure::{find_structure_violations, UnknownBytes, ViolationKind};
+use elf_editor::transformer::{
+    combinators::KeepAsIs, transform_elf_sections, LayoutOptions, LayoutStrategy,
+};
+
+const FIXTURES: &[&str] = &[
+    // x86_64 shared object, built by GCC.
+    "x86_64.so",
+    // x86_64 dynamically linked executable, with a `PT_INTERP`.
+    "x86_64-exe",
+    // aarch64 position independent executable.
+    "aarch64-pie",
+    // Solana sBPF program.
+    "sbpf.so",
+    // ELF32.
+    "i386.so",
+    // Big-endian ELF32.
+    "powerpc.so",
+];
+
+const SHOW_COMMANDS: &[&str] = &[
+    "header",
+    "layout",
+    "program-sections",
+    "file-segments",
+    "sections",
+    "dyn-sym",
+    "symtab",
+    "sh-str-tab",
+    "relocations",
+    "entrypoint",
+];
+
+fn fixture_path(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name)
+}
+
+fn read_fixture(name: &str) -> Vec<u8> {
+    fs::read(fixture_path(name)).expect("Fixture is readable")
+}
+
+fn parse(bytes: &[u8]) -> (Elf<'_>, Ctx) {
+    let elf = Elf::parse(bytes).expect("Fixture is a valid ELF");
+    let ctx = Ctx {
+        container: elf.header.container().expect("Valid ELF class"),
+        le: elf.header.endianness().expect("Valid ELF endianness"),
+    };
+    (elf, ctx)
+}
+
+fn run_editor(fixture: &str, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_elf-editor"))
+        .arg("--input")
+        .arg(fixture_path(fixture))
+        .args(args)
+        .output()
+        .expect("elf-editor runs")
+}
+
+#[test]
+fn fixtures_are_supported() {
+    for fixture in FIXTURES {
+        let output = run_editor(fixture, &["verify"]);
+        assert!(
+            output.status.success(),
+            "{fixture} structure is supported:\n{}",
+            String::from_utf8_lossy(&output.stdout),
+        );
+    }
+}
+
+#[test]
+fn no_op_transform_is_identical() {
+    for fixture in FIXTURES {
+        let input_bytes = read_fixture(fixture);
+        let (elf, ctx) = parse(&input_bytes);
+
+        for strategy in [
+            LayoutStrategy::Pack,
+            LayoutStrategy::PreserveGaps,
+            LayoutStrategy::AppendToEnd,
+        ] {
+            let options = LayoutOptions {
+                strategy,
+                ..LayoutOptions::default()
+            };
+
+            let mut output = vec![];
+            transform_elf_sections(&input_bytes, &elf, ctx, &mut output, options, KeepAsIs)
+                .expect("Fixture layout is valid");
+
+            assert!(
+                output == input_bytes,
+                "{fixture} is not changed by a no-op transformation with {strategy:?}",
+            );
+        }
+    }
+}
+
+/// `x86_64.so`, with a `.text` `sh_addralign` that is not a power of two.
+fn misaligned_fixture() -> Vec<u8> {
+    let mut input_bytes = read_fixture("x86_64.so");
+    let (elf, _) = parse(&input_bytes);
+    let index = elf
+        .section_headers
+        .iter()
+        .position(|header| elf.shdr_strtab.get_at(header.sh_name) == Some(".text"))
+        .expect("Fixture has a .text section");
+    // `sh_addralign` is at offset 48 in an `Elf64_Shdr`.
+    let field = elf.header.e_shoff as usize + index * elf.header.e_shentsize as usize + 48;
+    input_bytes[field..field + 8].copy_from_slice(&176093659137u64.to_le_bytes());
+    input_bytes
+}
+
+#[test]
+fn bad_alignment_is_rejected() {
+    // Layout code pads sections up to their alignment, so a bogus `sh_addralign` used to make the
+    // no-op transformation allocate gigabytes, instead of being reported by the verification.
+    let input_bytes = misaligned_fixture();
+
+    let (elf, ctx) = parse(&input_bytes);
+    let violations = find_structure_violations(&input_bytes, &elf, ctx, UnknownBytes::Reject);
+    assert!(
+        violations
+            .iter()
+            .any(|violation| violation.kind == ViolationKind::Misaligned),
+        "Non power of two alignment is reported: {violations:?}",
+    );
+}
+
+#[test]
+fn show_output() {
+    for fixture in FIXTURES {
+        for command in SHOW_COMMANDS {
+            let output = run_editor(fixture, &["show", command]);
+            assert!(output.status.success(), "show {command} for {fixture}");
+
+            insta::assert_snapshot!(
+                format!("{fixture}-{command}"),
+                String::from_utf8_lossy(&output.stdout)
+            );
+        }
+    }
+}
+
+#[test]
+fn show_json_output() {
+    for fixture in FIXTURES {
+        for command in SHOW_COMMANDS {
+            let output = run_editor(fixture, &["--format", "json", "show", command]);
+            assert!(output.status.success(), "show {command} for {fixture}");
+
+            let json: serde_json::Value = serde_json::from_slice(&output.stdout)
+                .unwrap_or_else(|err| panic!("show {command} for {fixture} is JSON: {err}"));
+            assert!(
+                json.is_object(),
+                "show {command} for {fixture} is an object"
+            );
+
+            // Text snapshots cover all the fixtures already, one is enough to pin the schema.
+            if *fixture == "sbpf.so" {
+                insta::assert_snapshot!(
+                    format!("{fixture}-{command}-json"),
+                    String::from_utf8_lossy(&output.stdout)
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn show_interpreter() {
+    let output = run_editor(
+        "x86_64-exe",
+        &["--format", "json", "show", "program-sections"],
+    );
+    assert!(output.status.success(), "show program-sections");
+
+    let json: serde_json::Value =
+        serde_json::from_slice(&output.stdout).expect("show program-sections is JSON");
+    assert_eq!(json["interpreter"], "/lib64/ld-linux-x86-64.so.2");
+}
+
+#[test]
+fn show_rejects_unknown_types() {
+    // Range markers are not section types.
+    for section_type in ["bogus", "loos", "SHT_HIUSER"] {
+        let output = run_editor("x86_64.so", &["show", "sections", "--type", section_type]);
+        assert!(
+            !output.status.success(),
+            "show sections --type {section_type}"
+        );
+        assert!(
+            String::from_utf8_lossy(&output.stderr).contains("Unknown section type"),
+            "Error names the problem"
+        );
+    }
+
+    for command in ["dyn-sym", "symtab"] {
+        let output = run_editor("x86_64.so", &["show", command, "--type", "bogus"]);
+        assert!(!output.status.success(), "show {command} --type bogus");
+        assert!(
+            String::from_utf8_lossy(&output.stderr).contains("Unknown symbol type"),
+            "Error names the problem"
+        );
+    }
+}
+
+#[test]
+fn show_dyn_sym_filters() {
+    for (name, args) in [
+        ("undefined", &["--undefined"][..]),
+        (
+            "defined-functions",
+            &["--defined", "--type", "stt_func"][..],
+        ),
+        ("name", &["--name", "*e?t*"][..]),
+    ] {
+        let output = run_editor("x86_64.so", &[&["show", "dyn-sym"][..], args].concat());
+        assert!(output.status.success(), "show dyn-sym {args:?}");
+
+        insta::assert_snapshot!(
+            format!("x86_64.so-dyn-sym-{name}"),
+            String::from_utf8_lossy(&output.stdout)
+        );
+    }
+}
+
+#[test]
+fn show_symtab_sorted() {
+    for order in ["address", "size"] {
+        let args = [
+            "show",
+            "symtab",
+            "--defined",
+            "--type",
+            "func",
+            "--sort",
+            order,
+        ];
+        let output = run_editor("x86_64.so", &args);
+        assert!(output.status.success(), "show symtab --sort {order}");
+
+        insta::assert_snapshot!(
+            format!("x86_64.so-symtab-{order}"),
+            String::from_utf8_lossy(&output.stdout)
+        );
+    }
+}
+
+/// Number of buckets of the `.gnu.hash` table, if there is one.
+fn gnu_hash_buckets(bytes: &[u8]) -> Option<u32> {
+    let (elf, ctx) = parse(bytes);
+    let header = elf
+        .section_headers
+        .iter()
+        .find(|header| header.sh_type == SHT_GNU_HASH)?;
+    bytes.pread_with(header.sh_offset as usize, ctx.le).ok()
+}
+
+/// Finds a symbol via the `.dynsym` section header, so that tables without a hash table are also
+/// searched.
+fn find_dynsym(bytes: &[u8], name: &str) -> Option<Sym> {
+    let (elf, ctx) = parse(bytes);
+    let header = elf
+        .section_headers
+        .iter()
+        .find(|header| header.sh_type == SHT_DYNSYM)?;
+    let count = header.sh_size as usize / Sym::size(ctx.container);
+    let symbols = Symtab::parse(bytes, header.sh_offset as usize, count, ctx).ok()?;
+
+    symbols
+        .iter()
+        .find(|symbol| elf.dynstrtab.get_at(symbol.st_name) == Some(name))
+}
+
+#[test]
+fn dyn_sym_add() {
+    for fixture in FIXTURES {
+        let output_path =
+            Path::new(env!("CARGO_TARGET_TMPDIR")).join(format!("dyn_sym_add-{fixture}"));
+        let output = run_editor(
+            fixture,
+            &[
+                "modify",
+                "--layout",
+                "append-to-end",
+                "--output",
+                output_path.to_str().expect("Temporary path is UTF-8"),
+                "dyn-sym",
+                "add",
+                "added_symbol",
+                "18",
+                "0",
+                "1",
+                "16",
+                "4",
+            ],
+        );
+        assert!(
+            output.status.success(),
+            "dyn-sym add for {fixture}:\n{}",
+            String::from_utf8_lossy(&output.stdout),
+        );
+
+        let output_bytes = fs::read(&output_path).expect("Output file is written");
+        let symbol = find_dynsym(&output_bytes, "added_symbol")
+            .unwrap_or_else(|| panic!("{fixture} output has the added symbol"));
+        assert_eq!(
+            (
+                symbol.st_info,
+                symbol.st_other,
+                symbol.st_shndx,
+                symbol.st_value,
+                symbol.st_size
+            ),
+            (18, 0, 1, 16, 4),
+            "{fixture} added symbol fields",
+        );
+
+        // Existing symbols are not affected.
+        let input_bytes = read_fixture(fixture);
+        assert_eq!(
+            find_dynsym(&output_bytes, "entrypoint").map(|symbol| symbol.st_value),
+            find_dynsym(&input_bytes, "entrypoint").map(|symbol| symbol.st_value),
+            "{fixture} entrypoint",
+        );
+        assert_eq!(
+            gnu_hash_buckets(&output_bytes),
+            gnu_hash_buckets(&input_bytes),
+            "{fixture} .gnu.hash keeps its buckets",
+        );
+
+        fs::remove_file(&output_path).expect("Output file is removed");
+    }
+}
+
+#[test]
+fn dyn_sym_remove() {
+    for fixture in ["x86_64.so", "sbpf.so", "i386.so", "powerpc.so"] {
+        let output_path =
+            Path::new(env!("CARGO_TARGET_TMPDIR")).join(format!("dyn_sym_remove-{fixture}"));
+        let output = run_editor(
+            fixture,
+            &[
+                "modify",
+                "--layout",
+                "append-to-end",
+                "--output",
+                output_path.to_str().expect("Temporary path is UTF-8"),
+                "dyn-sym",
+                "remove",
+                "entrypoint",
+            ],
+        );
+        assert!(
+            output.status.success(),
+            "dyn-sym remove for {fixture}:\n{}",
+            String::from_utf8_lossy(&output.stdout),
+        );
+
+        let output_bytes = fs::read(&output_path).expect("Output file is written");
+        assert!(
+            find_dynsym(&output_bytes, "entrypoint").is_none(),
+            "{fixture} output does not have the removed symbol",
+        );
+
+        // Symbols that follow the removed one are still found.
+        let input_bytes = read_fixture(fixture);
+        assert_eq!(
+            find_dynsym(&output_bytes, "counter"),
+            find_dynsym(&input_bytes, "counter"),
+            "{fixture} counter",
+        );
+
+        fs::remove_file(&output_path).expect("Output file is removed");
+    }
+}
+
+#[test]
+fn dyn_sym_remove_refuses_referenced_symbol() {
+    let output_path = Path::new(env!("CARGO_TARGET_TMPDIR")).join("dyn_sym_remove_referenced.so");
+    let output = run_editor(
+        "x86_64.so",
+        &[
+            "modify",
+            "--layout",
+            "append-to-end",
+            "--output",
+            output_path.to_str().expect("Temporary path is UTF-8"),
+            "dyn-sym",
+            "remove",
+            "next",
+        ],
+    );
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("refers to the removed symbol"));
+    assert!(!output_path.exists());
+}
+
+#[test]
+fn diff() {
+    for fixture in FIXTURES {
+        let output = run_editor(fixture, &["diff", fixture_path(fixture).to_str().unwrap()]);
+        assert!(
+            output.status.success(),
+            "{fixture} has no differences with itself"
+        );
+
+        let modified_path = Path::new(env!("CARGO_TARGET_TMPDIR")).join(format!("diff-{fixture}"));
+        let modified = modified_path.to_str().expect("Temporary path is UTF-8");
+        let output = run_editor(
+            fixture,
+            &[
+                "modify",
+                "--layout",
+                "append-to-end",
+                "--output",
+                modified,
+                "dyn-sym",
+                "add",
+                "added_symbol",
+                "18",
+                "0",
+                "1",
+                "16",
+                "4",
+            ],
+        );
+        assert!(output.status.success(), "dyn-sym add for {fixture}");
+
+        let output = run_editor(fixture, &["--format", "json", "diff", modified]);
+        assert!(
+            !output.status.success(),
+            "{fixture} differences are reported"
+        );
+
+        let diff: serde_json::Value =
+            serde_json::from_slice(&output.stdout).expect("diff output is JSON");
+        assert!(
+            diff["symbols"]
+                .as_array()
+                .unwrap()
+                .contains(&serde_json::json!({
+                    "table": ".dynsym",
+                    "name": "added_symbol",
+                    "change": { "kind": "added" },
+                })),
+            "{fixture} added symbol is reported: {diff:#}",
+        );
+        assert!(
+            diff["sections"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .any(|section| section["name"] == ".dynsym" && section["content"].is_object()),
+            "{fixture} .dynsym content change is reported: {diff:#}",
+        );
+
+        fs::remove_file(&modified_path).expect("Output file is removed");
+    }
+}
+
+#[test]
+fn pack_layout_refuses_to_move_loaded_sections() {
+    // With the `pack` layout, `.dynsym` grows into the sections that follow it in the same
+    // `PT_LOAD`, and they can not be moved in the file without being moved in memory.
+    let output_path = Path::new(env!("CARGO_TARGET_TMPDIR")).join("pack_moves_loaded.so");
+    let output = run_editor(
+        "x86_64.so",
+        &[
+            "modify",
+            "--layout",
+            "pack",
+            "--output",
+            output_path.to_str().expect("Temporary path is UTF-8"),
+            "dyn-sym",
+            "add",
+            "added_symbol",
+            "18",
+            "0",
+            "1",
+            "16",
+            "4",
+        ],
+    );
+
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("Failed to lay out the output file"),
+        "{stdout}"
+    );
+    assert!(
+        stdout.contains("Section is not placed in the file at its address"),
+        "{stdout}"
+    );
+    assert!(!output_path.exists());
+}
+
+#[test]
+fn verify_reports_violations() {
+    let input_bytes = misaligned_fixture();
+    let input_path = Path::new(env!("CARGO_TARGET_TMPDIR")).join("verify_misaligned.so");
+    fs::write(&input_path, &input_bytes).expect("Input file is written");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_elf-editor"))
+        .arg("--input")
+        .arg(&input_path)
+        .arg("verify")
+        .output()
+        .expect("elf-editor runs");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(!output.status.success(), "Misaligned input is rejected");
+    assert!(
+        stdout.contains(": misaligned"),
+        "Violation is reported:\n{stdout}"
+    );
+
+    // The violation range is `.text`, and its first bytes are shown.
+    let (elf, _) = parse(&input_bytes);
+    let text = elf
+        .section_headers
+        .iter()
+        .find(|header| elf.shdr_strtab.get_at(header.sh_name) == Some(".text"))
+        .expect("Fixture has a .text section");
+    let start = text.sh_offset as usize;
+    let hex = input_bytes[start..start + 16]
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect::<Vec<_>>()
+        .join(" ");
+    assert!(
+        stdout.contains(&format!("    0x{start:0>16x}: {hex} |")),
+        "Violation bytes are dumped:\n{stdout}"
+    );
+}
+
+#[test]
+fn modify_rejects_unsupported_input() {
+    let tmp = Path::new(env!("CARGO_TARGET_TMPDIR"));
+    let input_path = tmp.join("modify_unsupported.so");
+    let output_path = tmp.join("modify_unsupported.out.so");
+    fs::write(&input_path, misaligned_fixture()).expect("Input file is written");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_elf-editor"))
+        .arg("--input")
+        .arg(&input_path)
+        .args(["modify", "--output"])
+        .arg(&output_path)
+        .args(["dyn-sym", "remove", "entrypoint"])
+        .output()
+        .expect("elf-editor runs");
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Unsupported ELF structure"));
+    assert!(!output_path.exists());
+
+    fs::remove_file(&input_path).expect("Input file is removed");
+}
+
+#[test]
+fn output_must_differ_from_input() {
+    let input = fixture_path("sbpf.so");
+    let input = input.to_str().expect("Fixture path is UTF-8");
+    let input_bytes = read_fixture("sbpf.so");
+
+    for args in [
+        &["extract", "section", ".text", "-o", input][..],
+        &[
+            "modify",
+            "--output",
+            input,
+            "dyn-sym",
+            "remove",
+            "entrypoint",
+        ][..],
+        &[
+            "create",
+            "--output",
+            input,
+            "from-binary",
+            "--machine",
+            "bpf",
+        ][..],
+    ] {
+        let output = run_editor("sbpf.so", args);
+        assert!(!output.status.success(), "{args:?} fails");
+        assert!(
+            String::from_utf8_lossy(&output.stdout)
+                .contains("Output file must be different from the input file"),
+            "{args:?} explains the failure",
+        );
+    }
+
+    assert!(
+        read_fixture("sbpf.so") == input_bytes,
+        "Input is not changed"
+    );
+}
+
+#[test]
+fn create_from_binary() {
+    let tmp = Path::new(env!("CARGO_TARGET_TMPDIR"));
+    let input_path = tmp.join("create_from_binary.bin");
+    let output_path = tmp.join("create_from_binary.so");
+    // sBPF `mov64 r0, 0` and `exit`.
+    let content = [
+        0xb7, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, //
+        0x95, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, //
+    ];
+    fs::write(&input_path, content).expect("Input file is written");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_elf-editor"))
+        .arg("--input")
+        .arg(&input_path)
+        .args(["create", "--output"])
+        .arg(&output_path)
+        .args([
+            "from-binary",
+            "--machine",
+            "bpf",
+            "--base",
+            "0x100000000",
+            "--entry-symbol",
+            "entrypoint",
+        ])
+        .output()
+        .expect("elf-editor runs");
+    assert!(
+        output.status.success(),
+        "create from-binary:\n{}",
+        String::from_utf8_lossy(&output.stdout),
+    );
+
+    let output_bytes = fs::read(&output_path).expect("Output file is written");
+    let (elf, _) = parse(&output_bytes);
+    assert_eq!(elf.header.e_entry, 0x1_0000_0000);
+
+    let text = elf
+        .section_headers
+        .iter()
+        .find(|header| elf.shdr_strtab.get_at(header.sh_name) == Some(".text"))
+        .expect("Output has a .text section");
+    assert_eq!(text.sh_addr, 0x1_0000_0000);
+    assert_eq!(
+        &output_bytes[text.file_range().expect(".text is in the file")],
+        &content,
+    );
+
+    // Symbol is visible to the loaders, that look it up via the dynamic section.
+    let entrypoint = elf
+        .dynsyms
+        .iter()
+        .find(|symbol| elf.dynstrtab.get_at(symbol.st_name) == Some("entrypoint"))
+        .expect("Output has an entrypoint symbol");
+    assert_eq!(entrypoint.st_value, 0x1_0000_0000);
+
+    fs::remove_file(&input_path).expect("Input file is removed");
+    fs::remove_file(&output_path).expect("Output file is removed");
+}
+
+#[test]
+fn extract() {
+    let tmp = Path::new(env!("CARGO_TARGET_TMPDIR"));
+
+    for fixture in FIXTURES {
+        let input_bytes = read_fixture(fixture);
+        let (elf, _) = parse(&input_bytes);
+
+        let output_path = tmp.join(format!("extract-{fixture}.text"));
+        let output = run_editor(
+            fixture,
+            &[
+                "extract",
+                "section",
+                ".text",
+                "-o",
+                output_path.to_str().expect("Temporary path is UTF-8"),
+            ],
+        );
+        assert!(output.status.success(), "extract section for {fixture}");
+
+        let text = elf
+            .section_headers
+            .iter()
+            .find(|header| elf.shdr_strtab.get_at(header.sh_name) == Some(".text"))
+            .expect("Fixture has a .text section");
+        assert!(
+            fs::read(&output_path).expect("Output file is written")
+                == input_bytes[text.file_range().expect(".text is in the file")],
+            "{fixture} .text content",
+        );
+        fs::remove_file(&output_path).expect("Output file is removed");
+
+        // Every loaded section is found in the image at its physical address.
+        let output_path = tmp.join(format!("extract-{fixture}.bin"));
+        let output = run_editor(
+            fixture,
+            &[
+                "extract",
+                "image",
+                "-o",
+                output_path.to_str().expect("Temporary path is UTF-8"),
+            ],
+        );
+        assert!(output.status.success(), "extract image for {fixture}");
+
+        let image = fs::read(&output_path).expect("Output file is written");
+        let loads = elf
+            .program_headers
+            .iter()
+            .filter(|header| header.p_type == PT_LOAD && header.p_filesz != 0);
+        let image_start = loads
+            .clone()
+            .map(|header| header.p_paddr)
+            .min()
+            .expect("Fixture has PT_LOAD segments");
+        for header in loads {
+            let start = (header.p_paddr - image_start) as usize;
+            assert!(
+                image[start..start + header.p_filesz as usize] == input_bytes[header.file_range()],
+                "{fixture} PT_LOAD at 0x{:x}",
+                header.p_paddr,
+            );
+        }
+        fs::remove_file(&output_path).expect("Output file is removed");
+    }
+}